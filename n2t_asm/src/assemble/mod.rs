@@ -2,9 +2,27 @@ mod convert;
 mod predefined;
 mod symbol_table;
 
-use crate::parse::{Ident, Instruction, Program};
+use crate::parse::{Ident, Instruction, Item, Program};
 pub use symbol_table::{Address, SymbolTable};
 
+/// Splits a stream of items into a program and a symbol table containing the ROM address of every
+/// label in it
+pub fn resolve_labels(items: impl IntoIterator<Item = Item>) -> (Program, SymbolTable) {
+    let mut sym_table = SymbolTable::new();
+    let mut instructions = Vec::new();
+
+    for item in items {
+        match item {
+            Item::Label(lb) => {
+                sym_table.insert(lb, Address::Rom(instructions.len() as u16));
+            }
+            Item::Instruction(x) => instructions.push(x),
+        }
+    }
+
+    (Program(instructions), sym_table)
+}
+
 pub fn to_string(sym_table: &mut SymbolTable, program: &Program) -> String {
     to_raw(sym_table, program)
         .into_iter()
@@ -42,8 +60,26 @@ fn to_raw<'a>(
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::n2tasm;
     use crate::parse::program;
 
+    #[test]
+    fn labels_from_items() {
+        let (program, mut symbols) = resolve_labels(n2tasm! {
+            {@END}
+            {(0);JMP}
+            {(END)}
+            {@END}
+            {(0);JMP}
+        });
+
+        assert_eq!(symbols.get("END"), Some(&Address::Rom(2)));
+        assert_eq!(
+            to_vec(&mut symbols, &program),
+            [2, 0b1110_1010_1000_0111, 2, 0b1110_1010_1000_0111]
+        );
+    }
+
     #[test]
     fn mult() {
         let (mult, mut mult_symbols) = program(
@@ -16,6 +16,7 @@ impl Address {
     }
 }
 
+#[derive(Clone, Debug)]
 pub struct SymbolTable {
     value_set: HashSet<Address>,
     map: HashMap<String, Address>,
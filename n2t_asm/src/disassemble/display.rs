@@ -0,0 +1,106 @@
+use crate::parse::{CExpr, Dst, Ident, Instruction, Item, JumpCondition, Source};
+use std::fmt::{Display, Formatter};
+
+impl Display for Item {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Item::Label(lb) => write!(f, "({lb})"),
+            Item::Instruction(instr) => write!(f, "{instr}"),
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Instruction::A(Ident::Addr(x)) => write!(f, "@{x}"),
+            Instruction::A(Ident::Name(s)) => write!(f, "@{s}"),
+            Instruction::C { expr, dst, jump } => {
+                if !dst.is_empty() {
+                    write!(f, "{dst}=")?;
+                }
+                write!(f, "{expr}")?;
+                if !matches!(jump, JumpCondition::Never) {
+                    write!(f, ";{jump}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Display for Dst {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        [(Dst::A, 'A'), (Dst::M, 'M'), (Dst::D, 'D')]
+            .into_iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .try_for_each(|(_, c)| write!(f, "{c}"))
+    }
+}
+
+impl Display for CExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let x = |source: &Source| match source {
+            Source::Register => 'A',
+            Source::Memory => 'M',
+        };
+
+        match self {
+            CExpr::Zero => write!(f, "0"),
+            CExpr::One => write!(f, "1"),
+            CExpr::NegOne => write!(f, "-1"),
+            CExpr::D => write!(f, "D"),
+            CExpr::X(s) => write!(f, "{}", x(s)),
+            CExpr::NotD => write!(f, "!D"),
+            CExpr::NotX(s) => write!(f, "!{}", x(s)),
+            CExpr::NegD => write!(f, "-D"),
+            CExpr::NegX(s) => write!(f, "-{}", x(s)),
+            CExpr::DPlusOne => write!(f, "D+1"),
+            CExpr::DMinusOne => write!(f, "D-1"),
+            CExpr::XPlusOne(s) => write!(f, "{}+1", x(s)),
+            CExpr::XMinusOne(s) => write!(f, "{}-1", x(s)),
+            CExpr::DPlusX(s) => write!(f, "D+{}", x(s)),
+            CExpr::DMinusX(s) => write!(f, "D-{}", x(s)),
+            CExpr::XMinusD(s) => write!(f, "{}-D", x(s)),
+            CExpr::DAndX(s) => write!(f, "D&{}", x(s)),
+            CExpr::DOrX(s) => write!(f, "D|{}", x(s)),
+        }
+    }
+}
+
+impl Display for JumpCondition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mnemonic = match self {
+            JumpCondition::Never => "",
+            JumpCondition::Always => "JMP",
+            JumpCondition::GreaterThan => "JGT",
+            JumpCondition::LessThan => "JLT",
+            JumpCondition::GreaterEqual => "JGE",
+            JumpCondition::LessEqual => "JLE",
+            JumpCondition::Equal => "JEQ",
+            JumpCondition::NEqual => "JNE",
+        };
+        write!(f, "{mnemonic}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::assemble;
+    use crate::parse::program;
+
+    #[test]
+    fn round_trip() {
+        let source = "@R2\nM=0\n@MULT_LOOP\nAMD=M-1;JNE\nD=D|A\n0;JMP\nD;JGE\nM=!M\n";
+        let (parsed, mut symbols) = program(source).unwrap();
+        let printed = parsed.0.iter().map(|instr| format!("{instr}\n")).collect::<String>();
+
+        assert_eq!(printed, source);
+
+        let (reparsed, mut resymbols) = program(&printed).unwrap();
+        assert_eq!(
+            assemble::to_vec(&mut symbols, &parsed),
+            assemble::to_vec(&mut resymbols, &reparsed)
+        );
+    }
+}
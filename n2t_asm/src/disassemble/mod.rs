@@ -1,3 +1,4 @@
+mod display;
 mod from_struct;
 
 pub use from_struct::from_struct;
@@ -0,0 +1,149 @@
+/// The number of words of data memory the CPU can address, which covers RAM, the screen, and the
+/// keyboard
+pub const RAM_SIZE: usize = 0x8000;
+
+/// A model of the Hack CPU, along with its instruction and data memory
+pub struct Cpu {
+    pub rom: Vec<u16>,
+    pub ram: Vec<i16>,
+    pub a: i16,
+    pub d: i16,
+    pub pc: u16,
+    /// The number of instructions which have been executed
+    pub time: u64,
+}
+
+impl Cpu {
+    pub fn new(rom: Vec<u16>) -> Self {
+        Self {
+            rom,
+            ram: vec![0; RAM_SIZE],
+            a: 0,
+            d: 0,
+            pc: 0,
+            time: 0,
+        }
+    }
+
+    /// Whether the program counter points past the end of the program
+    pub fn halted(&self) -> bool {
+        self.pc as usize >= self.rom.len()
+    }
+
+    fn alu(bits: u16, x: i16, y: i16) -> i16 {
+        let flag = |n: u16| bits & (1 << n) != 0;
+        let x = if flag(5) { 0 } else { x };
+        let x = if flag(4) { !x } else { x };
+        let y = if flag(3) { 0 } else { y };
+        let y = if flag(2) { !y } else { y };
+        let out = if flag(1) { x.wrapping_add(y) } else { x & y };
+        if flag(0) {
+            !out
+        } else {
+            out
+        }
+    }
+
+    /// Executes the instruction at the program counter. Does nothing if the CPU has halted.
+    pub fn step(&mut self) {
+        let Some(&instruction) = self.rom.get(self.pc as usize) else {
+            return;
+        };
+        self.time += 1;
+
+        if instruction & 0x8000 == 0 {
+            self.a = instruction as i16;
+            self.pc += 1;
+            return;
+        }
+
+        let address = self.a as u16 as usize % RAM_SIZE;
+        let y = if instruction & 0x1000 != 0 {
+            self.ram[address]
+        } else {
+            self.a
+        };
+        let out = Self::alu(instruction >> 6, self.d, y);
+
+        if instruction & 0b001_000 != 0 {
+            self.ram[address] = out;
+        }
+        if instruction & 0b010_000 != 0 {
+            self.d = out;
+        }
+        if instruction & 0b100_000 != 0 {
+            self.a = out;
+        }
+
+        let jump = (instruction & 0b100 != 0 && out < 0)
+            || (instruction & 0b010 != 0 && out == 0)
+            || (instruction & 0b001 != 0 && out > 0);
+        if jump {
+            self.pc = self.a as u16;
+        } else {
+            self.pc += 1;
+        }
+    }
+
+    /// Runs until the CPU halts or `max_steps` instructions have been executed, returning the
+    /// number of instructions executed
+    pub fn run(&mut self, max_steps: u64) -> u64 {
+        let start = self.time;
+        while !self.halted() && self.time - start < max_steps {
+            self.step();
+        }
+        self.time - start
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assemble::to_vec;
+    use crate::parse::program;
+
+    #[test]
+    fn mult() {
+        let (mult, mut symbols) = program(
+            r#"
+@R2
+M=0
+(MULT_LOOP)
+@R0
+D=M
+@EXIT
+D;JEQ
+@R1
+D=M
+@R2
+M=M+D
+@R0
+M=M-1
+@MULT_LOOP
+0;JMP
+(EXIT)
+            "#,
+        )
+        .unwrap();
+
+        let mut cpu = Cpu::new(to_vec(&mut symbols, &mult));
+        cpu.ram[0] = 6;
+        cpu.ram[1] = -7;
+        cpu.run(1000);
+
+        assert!(cpu.halted());
+        assert_eq!(cpu.ram[2], -42);
+    }
+
+    #[test]
+    fn alu() {
+        let (code, mut symbols) = program("@5\nD=-A\n@3\nD=D|A\nAM=D+1\nD=!A\nMD=A-D;JGE").unwrap();
+
+        let mut cpu = Cpu::new(to_vec(&mut symbols, &code));
+        cpu.run(100);
+
+        // -5 | 3 = -5, so A ends up pointing to the top of memory after wrapping
+        assert_eq!(cpu.ram[RAM_SIZE - 4], -7);
+        assert_eq!((cpu.a, cpu.d, cpu.pc), (-4, -7, 7));
+    }
+}
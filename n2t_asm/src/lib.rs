@@ -3,6 +3,7 @@
 
 pub mod assemble;
 pub mod disassemble;
+pub mod emulate;
 pub mod err;
 mod macro_rule;
 pub mod parse;
@@ -18,6 +18,12 @@ macro_rules! _n2tasm_one {
     };
 
     // A-instruction
+    ({@s:$name:ident}) => {
+        Item::Instruction(Instruction::A(Ident::Name($name.to_string())))
+    };
+    ({@$name:ident}) => {
+        Item::Instruction(Instruction::A(Ident::Name(stringify!($name).to_string())))
+    };
     ({@n:$ident:path}) => {
         Item::Instruction(Instruction::A(Ident::Addr($ident)))
     };
@@ -267,6 +273,8 @@ mod test {
             {(abcdef)}
             {@0}
             {@n:location}
+            {@s:label_name}
+            {@abcdef}
             {M=(M+1);JEQ}
             {ADM=(M);}
         };
@@ -1,4 +1,4 @@
-use crate::assemble::{Address, SymbolTable};
+use crate::assemble::{resolve_labels, SymbolTable};
 use crate::err::AssemblyError;
 use nom::IResult;
 use nom_locate::LocatedSpan;
@@ -15,27 +15,15 @@ pub type Span<'a> = LocatedSpan<&'a str>;
 type PResult<'a, I> = IResult<Span<'a>, I, AssemblyError>;
 
 pub fn program(program: &str) -> Result<(Program, SymbolTable), AssemblyError> {
-    let mut sym_table = SymbolTable::new();
-
-    let mut line = 0;
-
-    let program = parsing::program(program.into())
-        .map(|res| res.map_err(nom::Err::into).map(|(_, p)| p))
-        .filter_map(|item| match item {
-            Ok(Item::Label(lb)) => {
-                sym_table.insert(lb, Address::Rom(line));
-                None
-            }
-            Ok(Item::Instruction(x)) => {
-                line += 1;
-                Some(Ok(x))
-            }
-            Err(x) => Some(Err(x)),
-        })
-        .try_collect::<Vec<_>>()
-        .map(Program);
-
-    program.map(|p| (p, sym_table))
+    items(program).map(resolve_labels).map_err(|(_, e)| e)
+}
+
+/// Parses a program into its labels and instructions, keeping the labels in place. An error is
+/// given with the line it was found on, starting from 1.
+pub fn items(program: &str) -> Result<Vec<Item>, (u32, AssemblyError)> {
+    parsing::program(program.into())
+        .map(|(line, res)| res.map(|(_, item)| item).map_err(|e| (line, e.into())))
+        .try_collect()
 }
 
 #[cfg(test)]
@@ -11,14 +11,16 @@ use nom::sequence::{delimited, preceded};
 use nom::Parser;
 use std::str::FromStr;
 
-pub fn program(program: Span) -> impl Iterator<Item = PResult<Item>> {
-    super::util::many0_spliterate(alt_line_spaced(instruction), program, '\n').filter_map(|res| {
-        match res {
-            Ok((a, Some(b))) => Some(Ok((a, b))),
+/// Parses each line of a program, along with its line number, starting from 1. Lines without an
+/// item are skipped.
+pub fn program(program: Span) -> impl Iterator<Item = (u32, PResult<Item>)> {
+    super::util::many0_spliterate(alt_line_spaced(instruction), program, '\n')
+        .zip(1..)
+        .filter_map(|(res, line)| match res {
+            Ok((a, Some(b))) => Some((line, Ok((a, b)))),
             Ok((_, None)) => None,
-            Err(x) => Some(Err(x)),
-        }
-    })
+            Err(x) => Some((line, Err(x))),
+        })
 }
 
 /// instruction line must begin on the first character of the instruction
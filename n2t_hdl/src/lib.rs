@@ -5,5 +5,6 @@ pub mod channel_range;
 mod clock_behavior;
 pub mod model;
 pub mod prelude;
+pub mod script;
 
 pub type Span<'a> = nom_locate::LocatedSpan<&'a str>;
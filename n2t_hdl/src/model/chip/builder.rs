@@ -8,7 +8,6 @@ use bitvec::prelude::*;
 use itertools::Itertools;
 use std::collections::hash_map::Entry;
 use std::collections::{HashMap, VecDeque};
-use tap::Tap;
 
 pub struct ChipBuilder {
     registered: HashMap<String, ChipInfo>,
@@ -106,7 +105,7 @@ impl IncompleteBarrier {
             in_buffer: self.default.clone(),
             intermediate: self.default.clone(),
             clock_mask: self.clock_mask,
-            out_buffer: self.default.clone().tap_mut(|v| v.fill(false)),
+            out_buffer: BitVec::repeat(false, self.interface.size_out()),
             chip: self.chip,
             router: self.router,
         }
@@ -129,8 +128,10 @@ impl ChipBuilder {
     }
 
     pub fn with_builtins(&mut self) {
-        self.registered
-            .extend([("Nand".to_string(), builtin::nand())]);
+        self.registered.extend([
+            ("Nand".to_string(), builtin::nand()),
+            ("DFF".to_string(), builtin::dff()),
+        ]);
     }
 
     pub fn register_hdl(&mut self, chip: ChipRepr) -> Result<(), ModelConstructionError> {
@@ -225,13 +226,17 @@ impl ChipBuilder {
             .map(|(id, (barrier, _))| (id, barrier.complete()))
             .collect::<HashMap<_, _>>();
         let out_buffer = BitVec::repeat(false, top_interface.size_out());
+        let clocked = registry.values().any(|barrier| barrier.chip.clocked());
 
         let chip = Box::new(NativeChip {
             registry,
             in_router,
+            in_buffer: BitVec::repeat(false, top_interface.size_in()),
             out_chip: out_id,
             out_buffer,
             request_queue: VecDeque::new(),
+            clocked,
+            initialized: false,
         });
 
         Ok(ChipInfo {
@@ -41,6 +41,41 @@ pub fn nand() -> ChipInfo {
     }
 }
 
+#[derive(Clone)]
+struct Dff {
+    bit: bool,
+}
+
+impl Chip for Dff {
+    fn eval(&mut self, _: &BitSlice) -> BitVec {
+        BitVec::repeat(self.bit, 1)
+    }
+    fn boxed_clone(&self) -> Box<dyn Chip> {
+        Box::new(self.clone())
+    }
+    fn clock(&mut self, args: &BitSlice) -> BitVec {
+        self.bit = args[0];
+        BitVec::repeat(self.bit, 1)
+    }
+    fn clocked(&self) -> bool {
+        true
+    }
+}
+
+pub fn dff() -> ChipInfo {
+    ChipInfo {
+        interface: Interface {
+            name: "DFF".to_string(),
+            map: [
+                ("in".to_string(), ChannelPin::SeqIn((0..=0).into())),
+                ("out".to_string(), ChannelPin::ComOut((0..=0).into())),
+            ]
+            .into(),
+        },
+        chip: Box::new(Dff { bit: false }),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -52,4 +87,13 @@ mod test {
         assert_eq!(nand.eval(bits!(1, 0)), bits!(1));
         assert_eq!(nand.eval(bits!(1, 1)), bits!(0));
     }
+
+    #[test]
+    fn dff() {
+        let mut dff = Dff { bit: false };
+        assert_eq!(dff.eval(bits!(1)), bits!(0));
+        assert_eq!(dff.clock(bits!(1)), bits!(1));
+        assert_eq!(dff.eval(bits!(0)), bits!(1));
+        assert_eq!(dff.clock(bits!(0)), bits!(0));
+    }
 }
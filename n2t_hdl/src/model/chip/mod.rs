@@ -23,4 +23,8 @@ pub trait Chip {
     fn clock(&mut self, args: &BitSlice) -> BitVec;
     fn eval(&mut self, args: &BitSlice) -> BitVec;
     fn boxed_clone(&self) -> Box<dyn Chip>;
+    /// Whether clocking this chip can change its state
+    fn clocked(&self) -> bool {
+        false
+    }
 }
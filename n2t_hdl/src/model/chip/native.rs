@@ -63,9 +63,13 @@ impl Debug for Barrier {
 pub struct NativeChip {
     pub(super) registry: HashMap<Id, Barrier>,
     pub(super) in_router: Router,
+    /// The arguments the chip was last evaluated with
+    pub(super) in_buffer: BitVec,
     pub(super) out_chip: Id,
     pub(super) out_buffer: BitVec,
     pub(super) request_queue: VecDeque<Request>,
+    pub(super) clocked: bool,
+    pub(super) initialized: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -122,61 +126,95 @@ impl Barrier {
         self.intermediate
             .copy_from_bitslice(self.in_buffer.as_bitslice());
     }
-    fn accept(&mut self, req: &Request) {
-        self.in_buffer[req.range.as_range()].copy_from_bitslice(req.data.as_bitslice())
+    /// Writes the request into the input buffer, returning whether any bit
+    /// changed
+    fn accept(&mut self, req: &Request) -> bool {
+        let target = &mut self.in_buffer[req.range.as_range()];
+        let changed = *target != *req.data;
+        target.copy_from_bitslice(req.data.as_bitslice());
+        changed
     }
-    fn eval(&mut self) -> impl Iterator<Item = Request> + '_ {
+    fn eval(&mut self) -> Option<impl Iterator<Item = Request> + '_> {
         self.switch_buffers_eval();
-        self.out_buffer = self.chip.eval(self.intermediate.as_bitslice());
-        self.router.gen_requests(self.out_buffer.as_bitslice())
+        let out = self.chip.eval(self.intermediate.as_bitslice());
+        self.update(out)
     }
     fn clock(&mut self) -> Option<impl Iterator<Item = Request> + '_> {
-        (!self.clock_mask.all()).then(|| {
-            self.switch_buffers_clock();
-            self.out_buffer = self.chip.clock(self.intermediate.as_bitslice());
-            self.router.gen_requests(self.out_buffer.as_bitslice())
-        })
+        if !self.chip.clocked() {
+            return None;
+        }
+        self.switch_buffers_clock();
+        let out = self.chip.clock(self.intermediate.as_bitslice());
+        self.update(out)
+    }
+    /// Stores the new output, only generating requests when it changed
+    fn update(&mut self, out: BitVec) -> Option<impl Iterator<Item = Request> + '_> {
+        let changed = out != self.out_buffer;
+        self.out_buffer = out;
+        changed.then(|| self.router.gen_requests(self.out_buffer.as_bitslice()))
     }
 }
 
-impl Chip for NativeChip {
-    fn clock(&mut self, args: &BitSlice) -> BitVec {
-        self.request_queue.extend(self.in_router.gen_requests(args));
+impl NativeChip {
+    /// Evaluates every part once, so that parts whose inputs never change
+    /// still drive their outputs
+    fn initialize(&mut self) {
+        if self.initialized {
+            return;
+        }
+        self.initialized = true;
+        for barrier in self.registry.values_mut() {
+            if let Some(requests) = barrier.eval() {
+                self.request_queue.extend(requests);
+            }
+        }
+    }
+
+    /// Propagates queued requests until no part's output changes
+    fn settle(&mut self) {
         while let Some(req) = self.request_queue.pop_front() {
-            println!(
-                "With request queue: {:?} and request: {req:?}",
-                self.request_queue
-            );
             if req.id == self.out_chip {
                 self.out_buffer[req.range.as_range()].copy_from_bitslice(req.data.as_bitslice());
             } else {
-                let chip = self.registry.get_mut(&req.id).unwrap();
-                chip.accept(&req);
-                chip.clock()
-                    .map(|requests| self.request_queue.extend(requests));
+                let barrier = self.registry.get_mut(&req.id).unwrap();
+                if barrier.accept(&req) {
+                    if let Some(requests) = barrier.eval() {
+                        self.request_queue.extend(requests);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Chip for NativeChip {
+    /// Settles the chip on `args`, then clocks every sequential part at once
+    /// before letting their new outputs propagate
+    fn clock(&mut self, args: &BitSlice) -> BitVec {
+        if !self.initialized || *args != *self.in_buffer {
+            self.eval(args);
+        }
+        for barrier in self.registry.values_mut() {
+            if let Some(requests) = barrier.clock() {
+                self.request_queue.extend(requests);
             }
         }
+        self.settle();
         self.out_buffer.clone()
     }
 
     fn eval(&mut self, args: &BitSlice) -> BitVec {
+        self.initialize();
+        self.in_buffer = args.to_bitvec();
         self.request_queue.extend(self.in_router.gen_requests(args));
-        while let Some(req) = self.request_queue.pop_front() {
-            println!(
-                "With request queue: {:?} and request: {req:?}",
-                self.request_queue
-            );
-            if req.id == self.out_chip {
-                self.out_buffer[req.range.as_range()].copy_from_bitslice(req.data.as_bitslice());
-            } else {
-                let chip = self.registry.get_mut(&req.id).unwrap();
-                chip.accept(&req);
-                self.request_queue.extend(chip.eval())
-            }
-        }
+        self.settle();
         self.out_buffer.clone()
     }
 
+    fn clocked(&self) -> bool {
+        self.clocked
+    }
+
     fn boxed_clone(&self) -> Box<dyn Chip> {
         Box::new(self.clone())
     }
@@ -194,7 +232,7 @@ mod test {
                     in_buffer: bitvec!(0, 0),
                     intermediate: bitvec!(0, 0),
                     clock_mask: bitvec!(1, 1),
-                    out_buffer: bitvec!(0, 0),
+                    out_buffer: bitvec!(0),
                     chip: crate::model::chip::builtin::nand().chip,
                     router: Router {
                         map: vec![(
@@ -227,9 +265,12 @@ mod test {
                     ),
                 ],
             },
+            in_buffer: bitvec![0],
             out_chip: Id(0),
             out_buffer: bitvec![0],
             request_queue: VecDeque::new(),
+            clocked: false,
+            initialized: false,
         }
     }
 
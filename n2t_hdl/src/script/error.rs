@@ -0,0 +1,44 @@
+use crate::model::chip::ModelConstructionError;
+use n2t_script::ParseError;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ScriptError {
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+
+    #[error("Could not access {path:?}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Could not parse {path:?}: {message}")]
+    Hdl { path: PathBuf, message: String },
+
+    #[error("Could not find {0}.hdl")]
+    MissingChip(String),
+
+    #[error("Chip {0} depends on itself")]
+    Recursive(String),
+
+    #[error(transparent)]
+    Model(#[from] ModelConstructionError),
+
+    #[error("No chip has been loaded")]
+    NoChip,
+
+    #[error("`{0}` is not supported")]
+    Unsupported(String),
+
+    #[error("`{0}` is not a pin of the loaded chip")]
+    UnknownVariable(String),
+
+    #[error("Comparison failure at line {line}\nexpected: {expected}\nactual:   {actual}")]
+    Comparison {
+        line: usize,
+        expected: String,
+        actual: String,
+    },
+}
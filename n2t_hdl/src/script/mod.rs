@@ -0,0 +1,473 @@
+//! Test scripts in the hardware simulator's `.tst` dialect, which load a chip, drive its inputs,
+//! and record its pins in an output file which is checked against a comparison file
+
+mod error;
+
+pub use error::ScriptError;
+pub use n2t_script::{Column, Comparison, Condition, Radix, Variable};
+
+use crate::channel_range::ChannelRange;
+use crate::model::chip::{ChipBuilder, ChipInfo, ModelConstructionError};
+use crate::model::parser::{create_chip, Form};
+use bitvec::prelude::*;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Something that advances the simulation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// Recomputes the outputs from the current inputs
+    Eval,
+    /// The first half of a clock cycle, which evaluates the chip
+    Tick,
+    /// The second half of a clock cycle, which commits the state of every clocked part
+    Tock,
+}
+
+impl n2t_script::Step for Step {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "eval" => Some(Step::Eval),
+            "tick" => Some(Step::Tick),
+            "tock" => Some(Step::Tock),
+            _ => None,
+        }
+    }
+}
+
+pub type Command = n2t_script::Command<Step>;
+pub type Statement = n2t_script::Statement<Step>;
+
+/// Parses a script for the hardware simulator
+pub fn parse(script: &str) -> Result<Vec<Statement>, ScriptError> {
+    Ok(n2t_script::parse(script)?)
+}
+
+/// Runs scripts against chips built from HDL files, keeping track of the output and comparison
+/// files
+pub struct Runner {
+    builder: ChipBuilder,
+    /// Files named by the script are relative to this directory
+    dir: PathBuf,
+    /// Other directories searched for chips the loaded chip depends on
+    libraries: Vec<PathBuf>,
+    chip: Option<ChipInfo>,
+    inputs: BitVec,
+    /// The inputs when the clock last ticked, which the next tock clocks in
+    latched: BitVec,
+    outputs: BitVec,
+    /// Completed clock cycles, and whether the current one has ticked
+    time: (u32, bool),
+    output_list: Vec<Column>,
+    output_file: Option<File>,
+    compare: Option<Vec<String>>,
+    /// Every line that has been output so far
+    output: Vec<String>,
+    echoes: Vec<String>,
+}
+
+fn io_error(path: &Path) -> impl FnOnce(std::io::Error) -> ScriptError + '_ {
+    |source| ScriptError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+/// Registers the chip `name` with the builder, reading it and every chip it uses from the first
+/// directory containing them. Chips which are already registered, like `Nand`, are not read.
+pub fn load_chip(
+    builder: &mut ChipBuilder,
+    dirs: &[PathBuf],
+    name: &str,
+) -> Result<(), ScriptError> {
+    load_chip_within(builder, dirs, name, &mut Vec::new())
+}
+
+fn load_chip_within(
+    builder: &mut ChipBuilder,
+    dirs: &[PathBuf],
+    name: &str,
+    loading: &mut Vec<String>,
+) -> Result<(), ScriptError> {
+    if builder.get_registry().contains_key(name) {
+        return Ok(());
+    }
+    if loading.iter().any(|chip| chip == name) {
+        return Err(ScriptError::Recursive(name.to_string()));
+    }
+
+    let path = dirs
+        .iter()
+        .map(|dir| dir.join(format!("{name}.hdl")))
+        .find(|path| path.is_file())
+        .ok_or_else(|| ScriptError::MissingChip(name.to_string()))?;
+    let code = fs::read_to_string(&path).map_err(io_error(&path))?;
+    let parse = || {
+        create_chip(code.as_str().into()).map_err(|e| ScriptError::Hdl {
+            path: path.clone(),
+            message: e.to_string(),
+        })
+    };
+    if let Form::Builtin(_) = parse()?.logic {
+        return Err(ScriptError::Unsupported(format!("BUILTIN {name}")));
+    }
+
+    loading.push(name.to_string());
+    loop {
+        match builder.register_hdl(parse()?) {
+            Err(ModelConstructionError::Needs(needed)) => {
+                for dependency in needed {
+                    load_chip_within(builder, dirs, &dependency, loading)?;
+                }
+            }
+            result => break result?,
+        }
+    }
+    loading.pop();
+    Ok(())
+}
+
+impl Runner {
+    pub fn new(builder: ChipBuilder, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            builder,
+            dir: dir.into(),
+            libraries: Vec::new(),
+            chip: None,
+            inputs: BitVec::new(),
+            latched: BitVec::new(),
+            outputs: BitVec::new(),
+            time: (0, false),
+            output_list: Vec::new(),
+            output_file: None,
+            compare: None,
+            output: Vec::new(),
+            echoes: Vec::new(),
+        }
+    }
+
+    /// Searches `dir` for chips which are not in the script's directory
+    pub fn add_library(&mut self, dir: impl Into<PathBuf>) {
+        self.libraries.push(dir.into());
+    }
+
+    pub fn output(&self) -> &[String] {
+        &self.output
+    }
+
+    /// Whether the output has been checked against a comparison file
+    pub fn compared(&self) -> bool {
+        self.compare.is_some()
+    }
+
+    /// Messages given to `echo`, in order
+    pub fn echoes(&self) -> &[String] {
+        &self.echoes
+    }
+
+    fn load(&mut self, file: &str) -> Result<(), ScriptError> {
+        let name = file.strip_suffix(".hdl").unwrap_or(file);
+        let dirs = [self.dir.clone()]
+            .into_iter()
+            .chain(self.libraries.iter().cloned())
+            .collect::<Vec<_>>();
+        load_chip(&mut self.builder, &dirs, name)?;
+
+        let chip = self.builder.get_chip_info(name).unwrap();
+        self.inputs = BitVec::repeat(false, chip.interface.size_in());
+        self.latched = self.inputs.clone();
+        self.outputs = BitVec::repeat(false, chip.interface.size_out());
+        self.time = (0, false);
+        self.chip = Some(chip);
+        self.step(Step::Eval)
+    }
+
+    /// Finds the bits of a pin, and whether it is an input
+    fn pin(&self, variable: &Variable) -> Result<(bool, ChannelRange), ScriptError> {
+        let unknown = || ScriptError::UnknownVariable(variable.to_string());
+        let interface = &self.chip.as_ref().ok_or(ScriptError::NoChip)?.interface;
+        let (input, range) = interface
+            .iter_inputs()
+            .map(|pin| (true, pin))
+            .chain(interface.iter_outputs().map(|pin| (false, pin)))
+            .find(|(_, (name, _))| **name == variable.name)
+            .map(|(input, (_, range))| (input, *range))
+            .ok_or_else(unknown)?;
+
+        let range = match variable.index {
+            Some(index) if usize::from(index) < range.size() => {
+                ChannelRange::new(range.start() + index, range.start() + index)
+            }
+            Some(_) => return Err(unknown()),
+            None if range.size() > 16 => {
+                return Err(ScriptError::Unsupported(format!(
+                    "{variable} wider than 16 bits"
+                )))
+            }
+            None => range,
+        };
+        Ok((input, range))
+    }
+
+    /// Reads a pin, which is signed only if it is 16 bits wide
+    fn get(&self, variable: &Variable) -> Result<i16, ScriptError> {
+        let (input, range) = self.pin(variable)?;
+        let pins = if input { &self.inputs } else { &self.outputs };
+        Ok(pins[range.as_range()].load_le::<u16>() as i16)
+    }
+
+    fn set(&mut self, variable: &Variable, value: i16) -> Result<(), ScriptError> {
+        let (input, range) = self.pin(variable)?;
+        if !input {
+            return Err(ScriptError::Unsupported(format!("set {variable}")));
+        }
+        let bits = &mut self.inputs[range.as_range()];
+        let mask = u16::MAX >> (16 - bits.len());
+        bits.store_le(value as u16 & mask);
+        Ok(())
+    }
+
+    fn step(&mut self, step: Step) -> Result<(), ScriptError> {
+        let chip = &mut self.chip.as_mut().ok_or(ScriptError::NoChip)?.chip;
+        match step {
+            Step::Eval => self.outputs = chip.eval(&self.inputs),
+            Step::Tick => {
+                self.outputs = chip.eval(&self.inputs);
+                self.latched = self.inputs.clone();
+                self.time.1 = true;
+            }
+            Step::Tock => {
+                // inputs set since the tick are not clocked in, but do change the outputs
+                if !self.time.1 {
+                    self.latched = self.inputs.clone();
+                }
+                self.outputs = chip.clock(&self.latched);
+                if self.latched != self.inputs {
+                    self.outputs = chip.eval(&self.inputs);
+                }
+                self.time = (self.time.0 + 1, false);
+            }
+        }
+        Ok(())
+    }
+
+    /// The clock's time, written with a `+` between a tick and a tock
+    fn time(&self) -> String {
+        match self.time {
+            (cycles, true) => format!("{cycles}+"),
+            (cycles, false) => cycles.to_string(),
+        }
+    }
+
+    /// Records a line of output, writing it to the output file and checking it against the
+    /// comparison file
+    fn output_line(&mut self, line: String) -> Result<(), ScriptError> {
+        if let Some(file) = &mut self.output_file {
+            writeln!(file, "{line}").map_err(io_error(&self.dir))?;
+        }
+        self.output.push(line);
+
+        let Some(compare) = &self.compare else {
+            return Ok(());
+        };
+        let number = self.output.len();
+        let actual = &self.output[number - 1];
+        let expected = compare.get(number - 1).map_or("", |line| line.trim_end());
+        if actual.trim_end() != expected {
+            return Err(ScriptError::Comparison {
+                line: number,
+                expected: expected.to_string(),
+                actual: actual.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    fn row(
+        &self,
+        cell: impl Fn(&Column) -> Result<String, ScriptError>,
+    ) -> Result<String, ScriptError> {
+        let cells = self
+            .output_list
+            .iter()
+            .map(cell)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(format!("|{}|", cells.join("|")))
+    }
+
+    fn cell(&self, column: &Column) -> Result<String, ScriptError> {
+        match (column.variable.name.as_str(), column.variable.index) {
+            ("time", None) => Ok(column.text(&self.time())),
+            _ => Ok(column.format(self.get(&column.variable)?)),
+        }
+    }
+
+    fn command(&mut self, command: &Command) -> Result<(), ScriptError> {
+        match command {
+            Command::Load(Some(file)) => self.load(file)?,
+            Command::Load(None) => {
+                return Err(ScriptError::Unsupported("load without a chip".to_string()))
+            }
+            Command::OutputFile(file) => {
+                let path = self.dir.join(file);
+                self.output_file = Some(File::create(&path).map_err(io_error(&path))?);
+            }
+            Command::CompareTo(file) => {
+                let path = self.dir.join(file);
+                let compare = fs::read_to_string(&path).map_err(io_error(&path))?;
+                self.compare = Some(compare.lines().map(str::to_string).collect());
+            }
+            Command::OutputList(columns) => {
+                self.output_list = columns.clone();
+                let header = self.row(|column| Ok(column.header()))?;
+                self.output_line(header)?;
+            }
+            Command::Set(variable, value) => self.set(variable, *value)?,
+            Command::Output => {
+                let row = self.row(|column| self.cell(column))?;
+                self.output_line(row)?;
+            }
+            Command::Step(step) => self.step(*step)?,
+            Command::Echo(text) => self.echoes.push(text.clone()),
+            Command::Ignored => (),
+        }
+        Ok(())
+    }
+
+    pub fn run(&mut self, statements: &[Statement]) -> Result<(), ScriptError> {
+        for statement in statements {
+            match statement {
+                Statement::Command { command, .. } => self.command(command)?,
+                Statement::Repeat {
+                    count: Some(count),
+                    body,
+                } => (0..*count).try_for_each(|_| self.run(body))?,
+                // a chip never halts, so this would run forever
+                Statement::Repeat { count: None, .. } => {
+                    return Err(ScriptError::Unsupported(
+                        "repeat without a count".to_string(),
+                    ))
+                }
+                Statement::While { condition, body } => {
+                    while condition
+                        .comparison
+                        .test(self.get(&condition.variable)?, condition.value)
+                    {
+                        self.run(body)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs the script at `path`, with files it names relative to the script and chips it uses
+/// taken from `builder`, the script's directory, or `libraries`
+pub fn run_file(
+    path: &Path,
+    builder: ChipBuilder,
+    libraries: &[PathBuf],
+) -> Result<Runner, ScriptError> {
+    let script = fs::read_to_string(path).map_err(io_error(path))?;
+    let statements = parse(&script)?;
+    let mut runner = Runner::new(builder, n2t_script::script_dir(path));
+    libraries
+        .iter()
+        .for_each(|library| runner.add_library(library));
+    runner.run(&statements)?;
+    Ok(runner)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const XOR: &str = "
+CHIP Xor {
+    IN a, b;
+    OUT out;
+
+    PARTS:
+    Nand(a=a, b=b, out=nand);
+    Nand(a=a, b=nand, out=x);
+    Nand(a=nand, b=b, out=y);
+    Nand(a=x, b=y, out=out);
+}
+";
+
+    fn runner() -> Runner {
+        let mut builder = ChipBuilder::new();
+        builder.with_builtins();
+        builder
+            .register_hdl(create_chip(XOR.into()).unwrap())
+            .unwrap();
+        let mut runner = Runner::new(builder, ".");
+        runner.load("Xor.hdl").unwrap();
+        runner
+    }
+
+    #[test]
+    fn eval() {
+        let mut runner = runner();
+        let script = parse(
+            "output-list a%B1.1.1 b%B1.1.1 out%B1.1.1;
+            set a 0, set b 0, eval, output;
+            set a 1, eval, output;
+            set b 1, eval, output;",
+        )
+        .unwrap();
+        runner.run(&script).unwrap();
+        assert_eq!(
+            runner.output,
+            [
+                "| a | b |out|",
+                "| 0 | 0 | 0 |",
+                "| 1 | 0 | 1 |",
+                "| 1 | 1 | 0 |"
+            ]
+        );
+    }
+
+    #[test]
+    fn comparison() {
+        let mut runner = runner();
+        runner.compare = Some(vec!["| a |out|".to_string(), "| 1 | 0 |".to_string()]);
+
+        let script = parse("output-list a%B1.1.1 out%B1.1.1; set a 1, eval, output;").unwrap();
+        let Err(ScriptError::Comparison { line, actual, .. }) = runner.run(&script) else {
+            panic!("expected the comparison to fail")
+        };
+        assert_eq!((line, actual.as_str()), (2, "| 1 | 1 |"));
+    }
+
+    #[test]
+    fn steps() {
+        assert!(parse("eval, tick, tock;").is_ok());
+        assert!(parse("vmstep;").is_err());
+
+        let mut runner = runner();
+        let forever = parse("repeat { tick, tock; }").unwrap();
+        assert!(matches!(
+            runner.run(&forever),
+            Err(ScriptError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn inputs_latch_on_tick() {
+        let mut builder = ChipBuilder::new();
+        builder.with_builtins();
+        let mut runner = Runner::new(builder, ".");
+        runner.load("DFF").unwrap();
+        let script = parse(
+            "output-list in%B1.1.1 out%B1.1.1;
+            set in 1, tick, set in 0, tock, output;
+            tick, tock, output;",
+        )
+        .unwrap();
+        runner.run(&script).unwrap();
+        assert_eq!(runner.output, ["|in |out|", "| 0 | 1 |", "| 0 | 0 |"]);
+    }
+}
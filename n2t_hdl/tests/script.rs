@@ -0,0 +1,67 @@
+use n2t_hdl::prelude::*;
+use n2t_hdl::script::{self, ScriptError};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn test_files(dir: &str) -> PathBuf {
+    std::env::current_dir()
+        .unwrap()
+        .join("../test_files")
+        .join(dir)
+}
+
+/// Runs a script from `dir` in a scratch directory, so the output file it writes can be checked
+/// against the one shipped with it
+fn run(dir: &str, name: &str) -> Result<(), ScriptError> {
+    let source = test_files(dir);
+    let scratch = std::env::temp_dir().join(format!("n2t_hdl_script_{name}"));
+    fs::create_dir_all(&scratch).unwrap();
+    for ext in ["tst", "cmp"] {
+        let file = format!("{name}.{ext}");
+        fs::copy(source.join(&file), scratch.join(&file)).unwrap();
+    }
+
+    let mut builder = ChipBuilder::new();
+    builder.with_builtins();
+    let libraries = [
+        source.clone(),
+        test_files("03/a"),
+        test_files("01"),
+        test_files("02"),
+    ];
+    script::run_file(&scratch.join(format!("{name}.tst")), builder, &libraries)?;
+
+    let out = |dir: &Path| fs::read(dir.join(format!("{name}.out"))).unwrap();
+    assert_eq!(out(&scratch), out(&source));
+    Ok(())
+}
+
+#[test]
+fn bit() {
+    run("03/a", "Bit").unwrap();
+}
+
+#[test]
+fn register() {
+    run("03/a", "Register").unwrap();
+}
+
+#[test]
+fn pc() {
+    run("03/a", "PC").unwrap();
+}
+
+#[test]
+fn ram8() {
+    run("03/a", "RAM8").unwrap();
+}
+
+#[test]
+fn ram64() {
+    run("03/a", "RAM64").unwrap();
+}
+
+#[test]
+fn ram512() {
+    run("03/b", "RAM512").unwrap();
+}
@@ -0,0 +1,635 @@
+//! Debugging Jack programs at the level of their source. Each VM command the compiler produces is
+//! traced back to the Jack statement it came from, and the translator's source maps carry that on
+//! to the Hack code, so the same debugger can drive either emulator.
+
+use crate::jack::ast::{Class, Statement, SubroutineKind, Type};
+use crate::jack::symbols::{SymbolTable, VarKind};
+use crate::pipeline::Artifacts;
+use crate::span::{Span, Spanned};
+use crate::translate::stack::Segment;
+use crate::translate::{translate_blocks, SourceMap, TranslateOptions};
+use crate::vm::{link, VmCommand, VmConfig, VmMachine, VmRuntimeError};
+use n2t_asm::assemble::{resolve_labels, to_vec};
+use n2t_asm::emulate::Cpu;
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+
+/// The most instructions the CPU may run for a single VM command before the debugger gives up
+const MAX_COMMAND_STEPS: u64 = 100_000;
+
+/// The number of words of ROM the Hack platform has
+const ROM_SIZE: usize = 0x8000;
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum DebugError {
+    #[error(transparent)]
+    Runtime(#[from] VmRuntimeError),
+
+    #[error("The CPU ran {0} instructions without reaching the next VM command")]
+    Runaway(u64),
+
+    #[error("The program needs {0} words of ROM, but there are only 32768")]
+    TooLarge(usize),
+}
+
+/// The Jack statement a VM command was compiled from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Origin {
+    /// The index of the class in the program
+    pub class: usize,
+    /// The index of the subroutine in its class
+    pub subroutine: usize,
+    /// The innermost statement containing the code the command was compiled from
+    pub statement: Span,
+}
+
+/// A Jack program compiled for debugging, linked with the classes of the bundled OS which it does
+/// not define itself
+pub struct Program {
+    pub classes: Vec<Class>,
+    pub commands: Vec<Spanned<VmCommand>>,
+    /// Where each command came from, or `None` for commands no statement produced, such as labels,
+    /// function headers and the OS
+    pub origins: Vec<Option<Origin>>,
+    /// Where the static variables of each class start in the static segment
+    static_bases: Vec<u16>,
+}
+
+impl Program {
+    /// Links a program which a [`Pipeline`](crate::pipeline::Pipeline) compiled from Jack with the
+    /// OS and without optimizations, so that each VM command has the span of the code it came from
+    pub fn from_artifacts(artifacts: Artifacts) -> Self {
+        let Artifacts { classes, vm, .. } = artifacts;
+        let mut files = Vec::new();
+        let mut origins = Vec::new();
+        let mut static_bases = Vec::new();
+        let mut base = 0;
+        for (i, (_, commands)) in vm.into_iter().enumerate() {
+            match classes.get(i) {
+                Some(class) => {
+                    origins.extend(origins_of(i, class, &commands));
+                    static_bases.push(base);
+                    base += statics_used(&commands);
+                }
+                // the OS classes come after the program's
+                None => origins.extend(commands.iter().map(|_| None)),
+            }
+            files.push(commands);
+        }
+
+        Self {
+            classes,
+            commands: link(files),
+            origins,
+            static_bases,
+        }
+    }
+
+    /// Loads the program into the VM emulator, bootstrapped and about to run `Sys.init`
+    pub fn vm_machine(&self) -> Result<VmMachine, DebugError> {
+        let mut machine = VmMachine::new(self.commands.clone());
+        machine.bootstrap()?;
+        Ok(machine)
+    }
+
+    /// Translates the program to Hack and loads it into the CPU emulator
+    pub fn hack_machine(&self) -> Result<HackMachine, DebugError> {
+        HackMachine::new(&self.commands)
+    }
+}
+
+/// The number of static variables a compiled class uses, which is how far [`link`] moves the
+/// statics of the classes after it
+fn statics_used(commands: &[Spanned<VmCommand>]) -> u16 {
+    commands
+        .iter()
+        .filter_map(|command| match command.item {
+            VmCommand::Push(Segment::Static, index) | VmCommand::Pop(Segment::Static, index) => {
+                Some(index + 1)
+            }
+            _ => None,
+        })
+        .max()
+        .unwrap_or_default()
+}
+
+/// Finds the statement each command of a compiled class came from. The compiler gives every command
+/// the span of the code it was compiled from, and emits the subroutines in order.
+fn origins_of(
+    class_index: usize,
+    class: &Class,
+    commands: &[Spanned<VmCommand>],
+) -> Vec<Option<Origin>> {
+    let mut subroutine = None;
+    commands
+        .iter()
+        .map(|command| {
+            if let VmCommand::Function { .. } = command.item {
+                subroutine = Some(subroutine.map_or(0, |i| i + 1));
+                return None;
+            }
+            let subroutine = subroutine?;
+            let statements = &class.subroutines[subroutine].item.statements;
+            let statement = innermost(statements, command.span)?;
+            match (&command.item, &statement.item) {
+                // jumping back to the top of a loop or past an else branch is not part of a
+                // statement, but `break` and `continue` are nothing else
+                (VmCommand::Goto(_), Statement::Break | Statement::Continue) => (),
+                (VmCommand::Label(_) | VmCommand::Goto(_), _) => return None,
+                _ => (),
+            }
+            Some(Origin {
+                class: class_index,
+                subroutine,
+                statement: statement.span,
+            })
+        })
+        .collect()
+}
+
+/// The innermost statement in `statements` which contains `span`
+fn innermost(statements: &[Spanned<Statement>], span: Span) -> Option<&Spanned<Statement>> {
+    let end = |span: Span| span.offset + span.len;
+    let statement = statements.iter().find(|statement| {
+        statement.span.offset <= span.offset && end(span) <= end(statement.span)
+    })?;
+    let inner = match &statement.item {
+        Statement::If {
+            then, otherwise, ..
+        } => innermost(then, span)
+            .or_else(|| innermost(otherwise.as_deref().unwrap_or_default(), span)),
+        Statement::While { body, .. } => innermost(body, span),
+        Statement::For {
+            init, update, body, ..
+        } => [init, update]
+            .into_iter()
+            .flatten()
+            .find_map(|part| innermost(std::slice::from_ref(part), span))
+            .or_else(|| innermost(body, span)),
+        _ => None,
+    };
+    Some(inner.unwrap_or(statement))
+}
+
+/// An emulator which the debugger can run a program on one VM command at a time
+pub trait Machine {
+    /// The index of the VM command which runs next, or `None` once the program has run off its end
+    fn command(&self) -> Option<usize>;
+    /// Runs the next VM command
+    fn step(&mut self) -> Result<(), DebugError>;
+    fn ram(&self) -> &[i16];
+}
+
+impl Machine for VmMachine {
+    fn command(&self) -> Option<usize> {
+        self.current_command().map(|_| self.pc())
+    }
+
+    fn step(&mut self) -> Result<(), DebugError> {
+        Ok(VmMachine::step(self)?)
+    }
+
+    fn ram(&self) -> &[i16] {
+        VmMachine::ram(self)
+    }
+}
+
+/// Runs translated code on the CPU emulator, using the translator's source map to find the VM
+/// command each address belongs to
+pub struct HackMachine {
+    cpu: Cpu,
+    /// The VM command whose code starts at each address
+    commands: HashMap<u16, usize>,
+}
+
+impl HackMachine {
+    /// Translates `commands` with the bootstrap and runs it up to the first command of `Sys.init`
+    pub fn new(commands: &[Spanned<VmCommand>]) -> Result<Self, DebugError> {
+        // one command per line, so that the source map's lines are the commands' indices
+        let source = commands
+            .iter()
+            .map(|command| format!("{}\n", command.item))
+            .collect::<String>();
+        let options = TranslateOptions {
+            shared_routines: true,
+            ..Default::default()
+        };
+        let blocks = translate_blocks(&source, options)
+            .collect::<Result<Vec<_>, _>>()
+            .expect("compiled code translates");
+
+        let map = SourceMap::new("", &blocks);
+        let commands = map
+            .entries
+            .iter()
+            .map(|entry| (entry.start, entry.line as usize - 1))
+            .collect();
+        let (program, mut symbols) =
+            resolve_labels(blocks.into_iter().flat_map(|block| block.items));
+        let rom = to_vec(&mut symbols, &program);
+        if rom.len() > ROM_SIZE {
+            return Err(DebugError::TooLarge(rom.len()));
+        }
+
+        let mut machine = Self {
+            cpu: Cpu::new(rom),
+            commands,
+        };
+        machine.run_to_command()?;
+        Ok(machine)
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    /// Runs until the CPU reaches the start of a VM command's code, or halts
+    fn run_to_command(&mut self) -> Result<(), DebugError> {
+        for _ in 0..MAX_COMMAND_STEPS {
+            if self.cpu.halted() || self.commands.contains_key(&self.cpu.pc) {
+                return Ok(());
+            }
+            self.cpu.step();
+        }
+        Err(DebugError::Runaway(MAX_COMMAND_STEPS))
+    }
+}
+
+impl Machine for HackMachine {
+    fn command(&self) -> Option<usize> {
+        self.commands.get(&self.cpu.pc).copied()
+    }
+
+    fn step(&mut self) -> Result<(), DebugError> {
+        if self.cpu.halted() {
+            return Err(VmRuntimeError::Halted.into());
+        }
+        self.cpu.step();
+        self.run_to_command()
+    }
+
+    fn ram(&self) -> &[i16] {
+        &self.cpu.ram
+    }
+}
+
+/// Why the debugger stopped running the program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// A step reached the statement it was looking for
+    Statement,
+    /// The program reached a statement with a breakpoint
+    Breakpoint,
+    /// The program finished, by running off its end or calling `Sys.halt`
+    Halted,
+    /// The program ran as many VM commands as it was allowed to without stopping
+    StepLimit,
+}
+
+/// A position in the Jack source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location<'a> {
+    pub class: &'a str,
+    pub subroutine: &'a str,
+    pub line: u32,
+}
+
+/// A Jack variable and the value it currently holds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    pub name: String,
+    pub kind: VarKind,
+    pub ty: Type,
+    /// Where the variable lives in RAM
+    pub address: usize,
+    pub value: i16,
+}
+
+/// Runs a Jack program on a [`Machine`], stopping at the statements and breakpoints it is asked to
+pub struct Debugger<M> {
+    program: Program,
+    machine: M,
+    /// The statement which each call that has not returned is running, innermost last. A call
+    /// which has not reached a statement of the program yet, such as one into the OS, has `None`.
+    frames: Vec<Option<Origin>>,
+    /// The lines of each class which have breakpoints
+    breakpoints: HashSet<(usize, u32)>,
+}
+
+impl<M: Machine> Debugger<M> {
+    /// Debugs `program` on `machine`, which should have it loaded and be about to run `Sys.init`
+    pub fn new(program: Program, machine: M) -> Self {
+        Self {
+            program,
+            machine,
+            frames: vec![None],
+            breakpoints: HashSet::new(),
+        }
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn machine(&self) -> &M {
+        &self.machine
+    }
+
+    /// Stops the program at the statement starting on `line` of `class`. Returns `false` if no
+    /// statement starts there.
+    pub fn set_breakpoint(&mut self, class: &str, line: u32) -> bool {
+        let Some(class) = self.class_index(class) else {
+            return false;
+        };
+        let exists = self
+            .program
+            .origins
+            .iter()
+            .flatten()
+            .any(|origin| origin.class == class && origin.statement.line == line);
+        if exists {
+            self.breakpoints.insert((class, line));
+        }
+        exists
+    }
+
+    /// Removes a breakpoint, returning whether there was one
+    pub fn clear_breakpoint(&mut self, class: &str, line: u32) -> bool {
+        self.class_index(class)
+            .is_some_and(|class| self.breakpoints.remove(&(class, line)))
+    }
+
+    fn class_index(&self, name: &str) -> Option<usize> {
+        self.program
+            .classes
+            .iter()
+            .position(|class| class.name.item == name)
+    }
+
+    /// The statement the program is stopped at, if it is stopped in its own code
+    pub fn location(&self) -> Option<Location<'_>> {
+        let origin = self.current()?;
+        let class = &self.program.classes[origin.class];
+        Some(Location {
+            class: &class.name.item,
+            subroutine: &class.subroutines[origin.subroutine].item.name.item,
+            line: origin.statement.line,
+        })
+    }
+
+    fn current(&self) -> Option<Origin> {
+        self.frames.last().copied().flatten()
+    }
+
+    /// Runs until the program starts another statement, stepping into the subroutines it calls
+    pub fn step_into(&mut self, max_steps: usize) -> Result<Stop, DebugError> {
+        self.run_until(max_steps, |_| true)
+    }
+
+    /// Runs until the program starts another statement in the same call, or returns from it
+    pub fn step_over(&mut self, max_steps: usize) -> Result<Stop, DebugError> {
+        let depth = self.frames.len();
+        self.run_until(max_steps, |debugger| debugger.frames.len() <= depth)
+    }
+
+    /// Runs until the current call returns and its caller starts another statement
+    pub fn step_out(&mut self, max_steps: usize) -> Result<Stop, DebugError> {
+        let depth = self.frames.len();
+        self.run_until(max_steps, |debugger| debugger.frames.len() < depth)
+    }
+
+    /// Runs until a breakpoint is reached or the program finishes
+    pub fn resume(&mut self, max_steps: usize) -> Result<Stop, DebugError> {
+        self.run_until(max_steps, |_| false)
+    }
+
+    /// Runs VM commands until the program starts a statement which `done` accepts or which has a
+    /// breakpoint
+    fn run_until(
+        &mut self,
+        max_steps: usize,
+        done: impl Fn(&Self) -> bool,
+    ) -> Result<Stop, DebugError> {
+        for _ in 0..max_steps {
+            if self.finished() {
+                return Ok(Stop::Halted);
+            }
+            if self.advance()? {
+                let origin = self.current().unwrap();
+                if self
+                    .breakpoints
+                    .contains(&(origin.class, origin.statement.line))
+                {
+                    return Ok(Stop::Breakpoint);
+                }
+                if done(self) {
+                    return Ok(Stop::Statement);
+                }
+            }
+        }
+        Ok(Stop::StepLimit)
+    }
+
+    /// Whether the program has run off its end or is about to call `Sys.halt`, which never returns
+    fn finished(&self) -> bool {
+        match self.machine.command() {
+            Some(index) => matches!(
+                &self.program.commands[index].item,
+                VmCommand::Call { name, .. } if name == "Sys.halt"
+            ),
+            None => true,
+        }
+    }
+
+    /// Runs one VM command, returning whether the next one starts a statement
+    fn advance(&mut self) -> Result<bool, DebugError> {
+        let index = self.machine.command().ok_or(VmRuntimeError::Halted)?;
+        match self.program.commands[index].item {
+            VmCommand::Call { .. } => self.frames.push(None),
+            VmCommand::Return if self.frames.len() > 1 => {
+                self.frames.pop();
+            }
+            _ => (),
+        }
+        self.machine.step()?;
+
+        let next = self
+            .machine
+            .command()
+            .and_then(|index| self.program.origins[index]);
+        match next {
+            Some(origin) if Some(origin) != self.current() => {
+                *self.frames.last_mut().unwrap() = Some(origin);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// The variables visible to the statement the program is stopped at, with their values: its
+    /// locals and arguments, then its class's fields and statics. Functions have no fields, and
+    /// constants have no place in memory.
+    pub fn variables(&self) -> Vec<Variable> {
+        let Some(origin) = self.current() else {
+            return Vec::new();
+        };
+        let class = &self.program.classes[origin.class];
+        let subroutine = &class.subroutines[origin.subroutine].item;
+        let mut table = SymbolTable::new(class);
+        table.enter(subroutine);
+
+        let ram = self.machine.ram();
+        let config = VmConfig::default();
+        let pointer = |register: u16| ram[register as usize] as u16 as usize;
+        let mut variables = table
+            .iter()
+            .filter_map(|(name, symbol)| {
+                let base = match symbol.kind {
+                    VarKind::Local => pointer(config.pointers.local),
+                    VarKind::Argument => pointer(config.pointers.argument),
+                    VarKind::Field if subroutine.kind == SubroutineKind::Function => return None,
+                    VarKind::Field => pointer(config.pointers.this),
+                    VarKind::Static => {
+                        (config.statics.start + self.program.static_bases[origin.class]) as usize
+                    }
+                    VarKind::Constant => return None,
+                };
+                let address = base + symbol.index as usize;
+                Some(Variable {
+                    name: name.to_string(),
+                    kind: symbol.kind,
+                    ty: symbol.ty.clone(),
+                    address,
+                    value: ram.get(address).copied().unwrap_or_default(),
+                })
+            })
+            .collect::<Vec<_>>();
+        let order = |kind| match kind {
+            VarKind::Local => 0,
+            VarKind::Argument => 1,
+            VarKind::Field => 2,
+            VarKind::Static | VarKind::Constant => 3,
+        };
+        variables.sort_by_key(|variable| (order(variable.kind), variable.address));
+        variables
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pipeline::{Language, Pipeline, SourceFile, Stage};
+
+    const MAIN: &str = "class Main {
+    static int total;
+
+    function void main() {
+        var int i;
+        let i = 0;
+        while (i < 3) {
+            let total = Main.add(total, i);
+            let i = i + 1;
+        }
+        do Memory.poke(8000, total);
+        return;
+    }
+
+    function int add(int a, int b) {
+        return a + b;
+    }
+}";
+
+    const STEPS: usize = 1_000_000;
+
+    fn program() -> Program {
+        let pipeline = Pipeline {
+            os: true,
+            ..Default::default()
+        };
+        let main = SourceFile {
+            name: "Main.jack".to_string(),
+            text: MAIN.to_string(),
+        };
+        let (artifacts, errors) = pipeline.run_until(Language::Jack, &[main], Stage::Compile);
+        assert_eq!(errors, []);
+        Program::from_artifacts(artifacts)
+    }
+
+    fn line(debugger: &Debugger<impl Machine>) -> (&str, u32) {
+        let location = debugger.location().unwrap();
+        (location.subroutine, location.line)
+    }
+
+    fn values(debugger: &Debugger<impl Machine>) -> Vec<(String, i16)> {
+        let variables = debugger.variables().into_iter();
+        variables.map(|v| (v.name, v.value)).collect()
+    }
+
+    fn debug(mut debugger: Debugger<impl Machine>) {
+        assert_eq!(debugger.step_into(STEPS), Ok(Stop::Statement));
+        assert_eq!(line(&debugger), ("main", 6));
+        assert_eq!(debugger.step_over(STEPS), Ok(Stop::Statement));
+        assert_eq!(line(&debugger), ("main", 7));
+        debugger.step_over(STEPS).unwrap();
+        assert_eq!(line(&debugger), ("main", 8));
+
+        assert_eq!(debugger.step_into(STEPS), Ok(Stop::Statement));
+        assert_eq!(line(&debugger), ("add", 16));
+        let expected = [("a", 0), ("b", 0), ("total", 0)];
+        assert_eq!(values(&debugger), expected.map(|(n, v)| (n.to_string(), v)));
+        debugger.step_over(STEPS).unwrap();
+        assert_eq!(line(&debugger), ("main", 9));
+        debugger.step_over(STEPS).unwrap();
+        assert_eq!(line(&debugger), ("main", 7));
+
+        assert!(debugger.set_breakpoint("Main", 11));
+        assert!(!debugger.set_breakpoint("Main", 10));
+        assert!(!debugger.set_breakpoint("Other", 11));
+        assert_eq!(debugger.resume(STEPS), Ok(Stop::Breakpoint));
+        assert_eq!(line(&debugger), ("main", 11));
+        let expected = [("i", 3), ("total", 3)];
+        assert_eq!(values(&debugger), expected.map(|(n, v)| (n.to_string(), v)));
+
+        // the OS has no statements to stop at
+        debugger.step_into(STEPS).unwrap();
+        assert_eq!(line(&debugger), ("main", 12));
+        assert_eq!(debugger.machine().ram()[8000], 3);
+        assert_eq!(debugger.step_out(STEPS), Ok(Stop::Halted));
+    }
+
+    #[test]
+    fn vm() {
+        let program = program();
+        let machine = program.vm_machine().unwrap();
+        debug(Debugger::new(program, machine));
+    }
+
+    #[test]
+    fn hack() {
+        let program = program();
+        let machine = program.hack_machine().unwrap();
+        debug(Debugger::new(program, machine));
+    }
+
+    #[test]
+    fn origins() {
+        let program = program();
+        let lines = program
+            .commands
+            .iter()
+            .zip(&program.origins)
+            .take_while(|(command, _)| command.item.to_string() != "function Main.add 0")
+            .map(|(command, origin)| (command.item.to_string(), origin.map(|o| o.statement.line)))
+            .collect::<Vec<_>>();
+        assert_eq!(lines[0], ("function Main.main 1".to_string(), None));
+        assert_eq!(lines[1], ("push constant 0".to_string(), Some(6)));
+        assert!(lines
+            .iter()
+            .filter(|(command, _)| command.starts_with("call Main.add"))
+            .all(|(_, line)| *line == Some(8)));
+        assert!(lines
+            .iter()
+            .filter(|(command, _)| command.starts_with("label") || command.starts_with("goto"))
+            .all(|(_, line)| line.is_none()));
+    }
+}
@@ -0,0 +1,232 @@
+use crate::span::Spanned;
+use std::fmt::{Display, Formatter};
+use strum_macros::{Display, EnumString};
+
+/// A name written in the program, such as a class, subroutine, or variable
+pub type Name = Spanned<String>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Type {
+    Int,
+    Char,
+    Boolean,
+    Class(String),
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Char => write!(f, "char"),
+            Type::Boolean => write!(f, "boolean"),
+            Type::Class(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+/// A file of Jack code, which always holds exactly one class
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Class {
+    pub name: Name,
+    pub vars: Vec<Spanned<ClassVarDec>>,
+    pub subroutines: Vec<Spanned<Subroutine>>,
+}
+
+#[derive(EnumString, Display, Debug, Clone, Copy, PartialEq, Eq)]
+#[strum(serialize_all = "lowercase")]
+pub enum ClassVarKind {
+    Static,
+    Field,
+    /// A named constant, which only the extended dialect has
+    Const,
+}
+
+/// A declaration of one or more class variables of the same kind and type, or of a constant
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassVarDec {
+    pub kind: ClassVarKind,
+    pub ty: Spanned<Type>,
+    pub names: Vec<Name>,
+    /// The value of a constant, which has exactly one name
+    pub value: Option<Spanned<Expression>>,
+}
+
+#[derive(EnumString, Display, Debug, Clone, Copy, PartialEq, Eq)]
+#[strum(serialize_all = "lowercase")]
+pub enum SubroutineKind {
+    Constructor,
+    Function,
+    Method,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parameter {
+    pub ty: Spanned<Type>,
+    pub name: Name,
+}
+
+/// A declaration of one or more local variables of the same type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VarDec {
+    pub ty: Spanned<Type>,
+    pub names: Vec<Name>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subroutine {
+    pub kind: SubroutineKind,
+    /// The type of the returned value, or `None` for `void`
+    pub return_type: Spanned<Option<Type>>,
+    pub name: Name,
+    pub parameters: Vec<Parameter>,
+    pub vars: Vec<Spanned<VarDec>>,
+    pub statements: Vec<Spanned<Statement>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Statement {
+    Let {
+        name: Name,
+        index: Option<Spanned<Expression>>,
+        value: Spanned<Expression>,
+    },
+    If {
+        condition: Spanned<Expression>,
+        then: Vec<Spanned<Statement>>,
+        otherwise: Option<Vec<Spanned<Statement>>>,
+    },
+    While {
+        condition: Spanned<Expression>,
+        body: Vec<Spanned<Statement>>,
+    },
+    Do(SubroutineCall),
+    Return(Option<Spanned<Expression>>),
+    /// A loop of the extended dialect. The initializer and update are `let` or `do` statements, and
+    /// a missing condition is always true.
+    For {
+        init: Option<Box<Spanned<Statement>>>,
+        condition: Option<Spanned<Expression>>,
+        update: Option<Box<Spanned<Statement>>>,
+        body: Vec<Spanned<Statement>>,
+    },
+    /// Leaves the innermost loop
+    Break,
+    /// Skips to the next iteration of the innermost loop, running a `for` loop's update first
+    Continue,
+}
+
+/// A call such as `f(x)`, `Class.f(x)`, or `object.f(x)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubroutineCall {
+    /// The class or variable before the `.`, if there is one
+    pub receiver: Option<Name>,
+    pub name: Name,
+    pub args: Vec<Spanned<Expression>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    And,
+    Or,
+    Lt,
+    Gt,
+    Eq,
+}
+
+impl BinaryOp {
+    pub fn from_symbol(symbol: char) -> Option<Self> {
+        Some(match symbol {
+            '+' => BinaryOp::Add,
+            '-' => BinaryOp::Sub,
+            '*' => BinaryOp::Mul,
+            '/' => BinaryOp::Div,
+            '&' => BinaryOp::And,
+            '|' => BinaryOp::Or,
+            '<' => BinaryOp::Lt,
+            '>' => BinaryOp::Gt,
+            '=' => BinaryOp::Eq,
+            _ => return None,
+        })
+    }
+
+    pub fn symbol(self) -> char {
+        match self {
+            BinaryOp::Add => '+',
+            BinaryOp::Sub => '-',
+            BinaryOp::Mul => '*',
+            BinaryOp::Div => '/',
+            BinaryOp::And => '&',
+            BinaryOp::Or => '|',
+            BinaryOp::Lt => '<',
+            BinaryOp::Gt => '>',
+            BinaryOp::Eq => '=',
+        }
+    }
+
+    /// How tightly the operator binds in the extended dialect, where `*` and `/` come before `+`
+    /// and `-`, then comparisons, then `&`, then `|`
+    pub fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Eq => 3,
+            BinaryOp::Add | BinaryOp::Sub => 4,
+            BinaryOp::Mul | BinaryOp::Div => 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+impl UnaryOp {
+    pub fn symbol(self) -> char {
+        match self {
+            UnaryOp::Neg => '-',
+            UnaryOp::Not => '~',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeywordConstant {
+    True,
+    False,
+    Null,
+    This,
+}
+
+/// An expression. Standard Jack has no operator precedence, so `a + b * c` is `(a + b) * c`, and
+/// binary operations only nest on the right when the program has parentheses. The extended dialect
+/// has precedence, so there `b * c` is the right operand of `+`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expression {
+    Integer(u16),
+    String(String),
+    /// A character literal, which is the character's code
+    Char(char),
+    Keyword(KeywordConstant),
+    Variable(String),
+    Index {
+        name: Name,
+        index: Box<Spanned<Expression>>,
+    },
+    Call(SubroutineCall),
+    Parenthesized(Box<Spanned<Expression>>),
+    Unary {
+        op: UnaryOp,
+        operand: Box<Spanned<Expression>>,
+    },
+    Binary {
+        op: BinaryOp,
+        left: Box<Spanned<Expression>>,
+        right: Box<Spanned<Expression>>,
+    },
+}
@@ -0,0 +1,559 @@
+use super::ast::*;
+use super::os::os_api;
+use super::symbols::{SymbolTable, VarKind};
+use super::SemanticError;
+use crate::span::{Span, Spanned};
+use std::collections::HashMap;
+
+/// The subroutines of every class, by class name and then subroutine name
+type Subroutines<'a> = HashMap<&'a str, HashMap<&'a str, &'a Subroutine>>;
+
+/// Checks the classes of a program for mistakes which the course's compiler accepts and turns into
+/// broken VM code, returning the errors found in each class in the order they were given. The
+/// official API of the Jack OS is assumed to exist, unless the program brings its own classes.
+pub fn check(classes: &[Class]) -> Vec<Vec<Spanned<SemanticError>>> {
+    let os = os_api();
+    let os = os
+        .iter()
+        .filter(|os| !classes.iter().any(|class| class.name.item == os.name.item));
+    let mut subroutines = Subroutines::new();
+    for class in os.chain(classes) {
+        subroutines.entry(&class.name.item).or_default().extend(
+            class
+                .subroutines
+                .iter()
+                .map(|subroutine| (subroutine.item.name.item.as_str(), &subroutine.item)),
+        );
+    }
+
+    classes
+        .iter()
+        .map(|class| {
+            let mut checker = Checker {
+                subroutines: &subroutines,
+                class,
+                kind: SubroutineKind::Function,
+                symbols: SymbolTable::new(class),
+                errors: Vec::new(),
+            };
+            checker.class();
+            checker.errors.sort_by_key(|e| e.span.offset);
+            checker.errors
+        })
+        .collect()
+}
+
+/// Whether every path through some statements ends in a `return`, or never ends at all
+pub(super) fn always_returns(statements: &[Spanned<Statement>]) -> bool {
+    statements.iter().any(|statement| match &statement.item {
+        Statement::Return(_) => true,
+        Statement::If {
+            then,
+            otherwise: Some(otherwise),
+            ..
+        } => always_returns(then) && always_returns(otherwise),
+        Statement::While { condition, body } => {
+            condition.item == Expression::Keyword(KeywordConstant::True) && !breaks(body)
+        }
+        Statement::For {
+            condition, body, ..
+        } => {
+            let forever = condition.as_ref().is_none_or(|condition| {
+                condition.item == Expression::Keyword(KeywordConstant::True)
+            });
+            forever && !breaks(body)
+        }
+        _ => false,
+    })
+}
+
+/// Whether the body of a loop can `break` out of it
+fn breaks(statements: &[Spanned<Statement>]) -> bool {
+    statements.iter().any(|statement| match &statement.item {
+        Statement::Break => true,
+        Statement::If {
+            then, otherwise, ..
+        } => breaks(then) || breaks(otherwise.as_deref().unwrap_or_default()),
+        _ => false,
+    })
+}
+
+/// Whether a value obviously cannot be stored in a variable. Jack lets `int`, `char` and
+/// `boolean` mix freely, and `Array` is often used as a raw pointer, so only other class types
+/// are checked.
+fn incompatible(value: &Type, target: &Type) -> bool {
+    match (value, target) {
+        (Type::Class(class), _) | (_, Type::Class(class)) if class == "Array" => false,
+        (Type::Class(value), Type::Class(target)) => value != target,
+        (Type::Class(_), _) | (_, Type::Class(_)) => true,
+        _ => false,
+    }
+}
+
+struct Checker<'a> {
+    subroutines: &'a Subroutines<'a>,
+    class: &'a Class,
+    /// The kind of the subroutine being checked
+    kind: SubroutineKind,
+    symbols: SymbolTable,
+    errors: Vec<Spanned<SemanticError>>,
+}
+
+impl<'a> Checker<'a> {
+    fn report(&mut self, span: Span, error: SemanticError) {
+        self.errors.push(Spanned { item: error, span });
+    }
+
+    fn known_type(&mut self, ty: &Spanned<Type>) {
+        if let Type::Class(class) = &ty.item {
+            if !self.subroutines.contains_key(class.as_str()) {
+                self.report(ty.span, SemanticError::UndefinedClass(class.clone()));
+            }
+        }
+    }
+
+    fn class(&mut self) {
+        for var in &self.class.vars {
+            self.known_type(&var.item.ty);
+            if var.item.kind != ClassVarKind::Const {
+                continue;
+            }
+            let ty = &var.item.ty;
+            if let Type::Class(_) = ty.item {
+                self.report(ty.span, SemanticError::ConstantType(ty.item.clone()));
+            }
+            for name in &var.item.names {
+                if self.symbols.value(&name.item).is_none() {
+                    let span = var
+                        .item
+                        .value
+                        .as_ref()
+                        .map_or(name.span, |value| value.span);
+                    self.report(span, SemanticError::NotConstant(name.item.clone()));
+                }
+            }
+        }
+        for subroutine in &self.class.subroutines {
+            self.subroutine(&subroutine.item);
+        }
+    }
+
+    fn subroutine(&mut self, subroutine: &Subroutine) {
+        self.symbols.enter(subroutine);
+        self.kind = subroutine.kind;
+
+        if let Spanned {
+            item: Some(ty),
+            span,
+        } = &subroutine.return_type
+        {
+            self.known_type(&Spanned {
+                item: ty.clone(),
+                span: *span,
+            });
+        }
+        for parameter in &subroutine.parameters {
+            self.known_type(&parameter.ty);
+        }
+        for var in &subroutine.vars {
+            self.known_type(&var.item.ty);
+        }
+
+        self.statements(&subroutine.statements, subroutine);
+        if !always_returns(&subroutine.statements) {
+            let name = &subroutine.name;
+            self.report(name.span, SemanticError::MissingReturn(name.item.clone()));
+        }
+    }
+
+    fn statements(&mut self, statements: &[Spanned<Statement>], subroutine: &Subroutine) {
+        for statement in statements {
+            self.statement(statement, subroutine);
+        }
+    }
+
+    fn statement(&mut self, statement: &Spanned<Statement>, subroutine: &Subroutine) {
+        match &statement.item {
+            Statement::Let { name, index, value } => {
+                let target = self.variable(name);
+                let symbol = self.symbols.get(&name.item);
+                if index.is_none() && symbol.is_some_and(|s| s.kind == VarKind::Constant) {
+                    let error = SemanticError::AssignToConstant(name.item.clone());
+                    self.report(name.span, error);
+                }
+                if let Some(index) = index {
+                    self.expression(index);
+                }
+                let value_type = self.expression(value);
+                if let (Some(target), Some(value_type), None) = (target, value_type, index) {
+                    if incompatible(&value_type, &target) {
+                        let error = SemanticError::IncompatibleTypes {
+                            value: value_type,
+                            target,
+                        };
+                        self.report(value.span, error);
+                    }
+                }
+            }
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.expression(condition);
+                self.statements(then, subroutine);
+                if let Some(otherwise) = otherwise {
+                    self.statements(otherwise, subroutine);
+                }
+            }
+            Statement::While { condition, body } => {
+                self.expression(condition);
+                self.statements(body, subroutine);
+            }
+            Statement::For {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.statement(init, subroutine);
+                }
+                if let Some(condition) = condition {
+                    self.expression(condition);
+                }
+                if let Some(update) = update {
+                    self.statement(update, subroutine);
+                }
+                self.statements(body, subroutine);
+            }
+            Statement::Break | Statement::Continue => (),
+            Statement::Do(call) => {
+                self.call(call);
+            }
+            Statement::Return(value) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+                let name = subroutine.name.item.clone();
+                match (&subroutine.return_type.item, value) {
+                    (None, Some(value)) => {
+                        self.report(value.span, SemanticError::UnexpectedReturnValue(name))
+                    }
+                    (Some(_), None) => {
+                        self.report(statement.span, SemanticError::MissingReturnValue(name))
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    /// Finds the type of a variable, reporting it if it is not declared
+    fn variable(&mut self, name: &Name) -> Option<Type> {
+        match self.symbols.get(&name.item) {
+            Some(symbol) => Some(symbol.ty.clone()),
+            None => {
+                let error = SemanticError::UndeclaredVariable(name.item.clone());
+                self.report(name.span, error);
+                None
+            }
+        }
+    }
+
+    /// Checks a call, returning the subroutine it calls if that is known
+    fn call(&mut self, call: &SubroutineCall) -> Option<&'a Subroutine> {
+        for arg in &call.args {
+            self.expression(arg);
+        }
+
+        // the class to look in, and whether the call is on an object
+        let (class, on_object) = match &call.receiver {
+            None => (self.class.name.item.clone(), None),
+            Some(receiver) => match self.symbols.get(&receiver.item).map(|s| s.ty.clone()) {
+                Some(Type::Class(class)) => (class, Some(true)),
+                Some(ty) => {
+                    let name = receiver.item.clone();
+                    self.report(receiver.span, SemanticError::NotAnObject { name, ty });
+                    return None;
+                }
+                None => (receiver.item.clone(), Some(false)),
+            },
+        };
+
+        let Some(subroutines) = self.subroutines.get(class.as_str()) else {
+            // class names are capitalized, so a lowercase name is probably a mistyped variable
+            let receiver = call.receiver.as_ref()?;
+            let error = match class.starts_with(char::is_lowercase) {
+                true => SemanticError::UndeclaredVariable(class),
+                false => SemanticError::UndefinedClass(class),
+            };
+            self.report(receiver.span, error);
+            return None;
+        };
+        let name = &call.name;
+        let Some(&target) = subroutines.get(name.item.as_str()) else {
+            let error = SemanticError::UndefinedSubroutine {
+                class,
+                name: name.item.clone(),
+            };
+            self.report(name.span, error);
+            return None;
+        };
+
+        let full_name = format!("{class}.{}", name.item);
+        let is_method = target.kind == SubroutineKind::Method;
+        match on_object {
+            None if is_method && self.kind == SubroutineKind::Function => {
+                self.report(name.span, SemanticError::NeedsObject(full_name.clone()))
+            }
+            Some(true) if !is_method => {
+                self.report(name.span, SemanticError::NotAMethod(full_name.clone()))
+            }
+            Some(false) if is_method => {
+                self.report(name.span, SemanticError::NeedsObject(full_name.clone()))
+            }
+            _ => (),
+        }
+        if call.args.len() != target.parameters.len() {
+            let error = SemanticError::ArgumentCount {
+                name: full_name,
+                expected: target.parameters.len(),
+                given: call.args.len(),
+            };
+            self.report(name.span, error);
+        }
+        Some(target)
+    }
+
+    /// Checks an expression, returning its type if it is known
+    fn expression(&mut self, expression: &Spanned<Expression>) -> Option<Type> {
+        match &expression.item {
+            Expression::Integer(_) => Some(Type::Int),
+            Expression::Char(_) => Some(Type::Char),
+            Expression::String(_) => Some(Type::Class("String".to_string())),
+            Expression::Keyword(KeywordConstant::True | KeywordConstant::False) => {
+                Some(Type::Boolean)
+            }
+            Expression::Keyword(KeywordConstant::Null) => None,
+            Expression::Keyword(KeywordConstant::This) => {
+                Some(Type::Class(self.class.name.item.clone()))
+            }
+            Expression::Variable(name) => self.variable(&Spanned {
+                item: name.clone(),
+                span: expression.span,
+            }),
+            Expression::Index { name, index } => {
+                self.variable(name);
+                self.expression(index);
+                None
+            }
+            Expression::Call(call) => {
+                let target = self.call(call)?;
+                if target.return_type.item.is_none() {
+                    let name = call.name.item.clone();
+                    self.report(expression.span, SemanticError::VoidValue(name));
+                }
+                target.return_type.item.clone()
+            }
+            Expression::Parenthesized(inner) => self.expression(inner),
+            Expression::Unary { op, operand } => {
+                let operand = self.expression(operand);
+                match op {
+                    UnaryOp::Neg => Some(Type::Int),
+                    UnaryOp::Not => operand.filter(|ty| !matches!(ty, Type::Class(_))),
+                }
+            }
+            Expression::Binary { op, left, right } => {
+                let left = self.expression(left);
+                self.expression(right);
+                match op {
+                    BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Eq => Some(Type::Boolean),
+                    BinaryOp::And | BinaryOp::Or => left.filter(|ty| !matches!(ty, Type::Class(_))),
+                    _ => Some(Type::Int),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jack::{parse, parse_with, Dialect};
+
+    fn errors(sources: &[&str]) -> Vec<Vec<(u32, SemanticError)>> {
+        let classes = sources
+            .iter()
+            .map(|source| parse(source).0.unwrap())
+            .collect::<Vec<_>>();
+        check(&classes)
+            .into_iter()
+            .map(|errors| errors.into_iter().map(|e| (e.span.line, e.item)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn valid_program() {
+        let main = "class Main {
+    function void main() {
+        var Point p; var Array a; var int x;
+        let p = Point.new(1);
+        let a = Array.new(3);
+        let a = 8000;
+        let x = p.getX() + a[1];
+        do Output.printInt(Math.max(x, 2));
+        while (true) { }
+    }
+}";
+        let point = "class Point {
+    field int x;
+    constructor Point new(int ax) { let x = ax; return this; }
+    method int getX() { if (x < 0) { return -x; } else { return x; } }
+    method Point copy() { return Point.new(getX()); }
+}";
+        assert_eq!(errors(&[main, point]), [vec![], vec![]]);
+    }
+
+    #[test]
+    fn mistakes() {
+        let main = "class Main {
+    field int f;
+    function int main(Missing m) {
+        var Point p; var int n;
+        let q = 1;
+        do Nope.run();
+        do p.nothing();
+        do p.move();
+        do Point.move(1);
+        do Point.new();
+        do move(1);
+        let n = Output.println();
+        let p = 5;
+        do n.run();
+        return;
+    }
+    method void done() { return 1; }
+    method void unfinished() { if (f) { return; } }
+}";
+        let point = "class Point {
+    constructor Point new(int x) { return this; }
+    method void move(int dx) { return; }
+}";
+        assert_eq!(
+            errors(&[main, point]),
+            [
+                vec![
+                    (3, SemanticError::UndefinedClass("Missing".to_string())),
+                    (5, SemanticError::UndeclaredVariable("q".to_string())),
+                    (6, SemanticError::UndefinedClass("Nope".to_string())),
+                    (
+                        7,
+                        SemanticError::UndefinedSubroutine {
+                            class: "Point".to_string(),
+                            name: "nothing".to_string()
+                        }
+                    ),
+                    (
+                        8,
+                        SemanticError::ArgumentCount {
+                            name: "Point.move".to_string(),
+                            expected: 1,
+                            given: 0
+                        }
+                    ),
+                    (9, SemanticError::NeedsObject("Point.move".to_string())),
+                    (
+                        10,
+                        SemanticError::ArgumentCount {
+                            name: "Point.new".to_string(),
+                            expected: 1,
+                            given: 0
+                        }
+                    ),
+                    (
+                        11,
+                        SemanticError::UndefinedSubroutine {
+                            class: "Main".to_string(),
+                            name: "move".to_string()
+                        }
+                    ),
+                    (12, SemanticError::VoidValue("println".to_string())),
+                    (
+                        13,
+                        SemanticError::IncompatibleTypes {
+                            value: Type::Int,
+                            target: Type::Class("Point".to_string())
+                        }
+                    ),
+                    (
+                        14,
+                        SemanticError::NotAnObject {
+                            name: "n".to_string(),
+                            ty: Type::Int
+                        }
+                    ),
+                    (15, SemanticError::MissingReturnValue("main".to_string())),
+                    (17, SemanticError::UnexpectedReturnValue("done".to_string())),
+                    (18, SemanticError::MissingReturn("unfinished".to_string())),
+                ],
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn os_helpers() {
+        // the bundled OS defines `Math.divideAbs`, but it is not part of the OS's API
+        let main = "class Main {
+    function void main() {
+        do Math.divideAbs(7, 2);
+        return;
+    }
+}";
+        assert_eq!(
+            errors(&[main]),
+            [vec![(
+                3,
+                SemanticError::UndefinedSubroutine {
+                    class: "Math".to_string(),
+                    name: "divideAbs".to_string()
+                }
+            )]]
+        );
+    }
+
+    #[test]
+    fn extended() {
+        let main = "class Main {
+    const int N = 'a' * 2;
+    const Point P = null;
+    const int M = Main.f();
+    function int f() {
+        for (;;) { if (N > 0) { break; } }
+    }
+    function int g() {
+        for (let N = 0; true; ) { }
+    }
+}";
+        let main = parse_with(main, Dialect::Extended).0.unwrap();
+        let point = parse("class Point { }").0.unwrap();
+        let errors = check(&[main, point])
+            .swap_remove(0)
+            .into_iter()
+            .map(|e| (e.span.line, e.item))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                (
+                    3,
+                    SemanticError::ConstantType(Type::Class("Point".to_string()))
+                ),
+                (4, SemanticError::NotConstant("M".to_string())),
+                (5, SemanticError::MissingReturn("f".to_string())),
+                (9, SemanticError::AssignToConstant("N".to_string())),
+            ]
+        );
+    }
+}
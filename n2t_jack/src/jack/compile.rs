@@ -0,0 +1,612 @@
+use super::ast::*;
+use super::symbols::{SymbolTable, VarKind};
+use super::CompileError;
+use crate::span::{Span, Spanned};
+use crate::translate::arithmetic::Arithmetic;
+use crate::translate::stack::Segment;
+use crate::vm::VmCommand;
+
+/// Compiles a class to VM code. Each command is spanned with the Jack code it was generated from,
+/// rather than a position in a VM file.
+///
+/// Labels are named like those of the course's compiler, so that its output can be compared with
+/// ours.
+pub fn compile(class: &Class) -> Result<Vec<Spanned<VmCommand>>, Vec<Spanned<CompileError>>> {
+    let mut compiler = Compiler {
+        class,
+        symbols: SymbolTable::new(class),
+        commands: Vec::new(),
+        errors: Vec::new(),
+        ifs: 0,
+        whiles: 0,
+        fors: 0,
+        loops: Vec::new(),
+    };
+    for subroutine in &class.subroutines {
+        compiler.subroutine(subroutine);
+    }
+
+    match compiler.errors.is_empty() {
+        true => Ok(compiler.commands),
+        false => Err(compiler.errors),
+    }
+}
+
+struct Compiler<'a> {
+    class: &'a Class,
+    symbols: SymbolTable,
+    commands: Vec<Spanned<VmCommand>>,
+    errors: Vec<Spanned<CompileError>>,
+    /// How many `if` statements of the current subroutine have been compiled
+    ifs: usize,
+    whiles: usize,
+    fors: usize,
+    /// The labels that `continue` and `break` jump to in each loop being compiled, innermost last
+    loops: Vec<(String, String)>,
+}
+
+impl<'a> Compiler<'a> {
+    fn emit(&mut self, span: Span, command: VmCommand) {
+        self.commands.push(Spanned {
+            item: command,
+            span,
+        });
+    }
+
+    fn push(&mut self, span: Span, segment: Segment, index: u16) {
+        self.emit(span, VmCommand::Push(segment, index));
+    }
+
+    fn pop(&mut self, span: Span, segment: Segment, index: u16) {
+        self.emit(span, VmCommand::Pop(segment, index));
+    }
+
+    fn arithmetic(&mut self, span: Span, op: Arithmetic) {
+        self.emit(span, VmCommand::Arithmetic(op));
+    }
+
+    fn call(&mut self, span: Span, name: String, args: u16) {
+        self.emit(span, VmCommand::Call { name, args });
+    }
+
+    fn label(&mut self, span: Span, label: &str) {
+        self.emit(span, VmCommand::Label(label.to_string()));
+    }
+
+    fn subroutine(&mut self, subroutine: &Spanned<Subroutine>) {
+        let subroutine = &subroutine.item;
+        let span = subroutine.name.span;
+        self.symbols.enter(subroutine);
+        self.ifs = 0;
+        self.whiles = 0;
+        self.fors = 0;
+
+        let name = format!("{}.{}", self.class.name.item, subroutine.name.item);
+        let locals = self.symbols.count(VarKind::Local);
+        self.emit(span, VmCommand::Function { name, locals });
+        match subroutine.kind {
+            SubroutineKind::Constructor => {
+                let fields = self.symbols.count(VarKind::Field);
+                self.push(span, Segment::Constant, fields);
+                self.call(span, "Memory.alloc".to_string(), 1);
+                self.pop(span, Segment::Pointer, 0);
+            }
+            SubroutineKind::Method => {
+                self.push(span, Segment::Argument, 0);
+                self.pop(span, Segment::Pointer, 0);
+            }
+            SubroutineKind::Function => (),
+        }
+
+        self.statements(&subroutine.statements);
+    }
+
+    fn statements(&mut self, statements: &[Spanned<Statement>]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    /// Finds the segment and index of a variable, reporting it if it is not declared. Constants
+    /// have no place in memory, so they are reported too.
+    fn variable(&mut self, name: &str, span: Span) -> Option<(Segment, u16)> {
+        let error = match self.symbols.get(name) {
+            Some(symbol) if symbol.kind == VarKind::Constant => {
+                CompileError::AssignToConstant(name.to_string())
+            }
+            Some(symbol) => return Some((symbol.kind.segment(), symbol.index)),
+            None => CompileError::UndeclaredVariable(name.to_string()),
+        };
+        self.errors.push(Spanned { item: error, span });
+        None
+    }
+
+    /// Pushes the value of a variable or constant
+    fn push_variable(&mut self, name: &str, span: Span) {
+        match self.symbols.get(name).map(|symbol| symbol.kind) {
+            Some(VarKind::Constant) => match self.symbols.value(name) {
+                Some(value) => self.constant(span, value),
+                None => self.errors.push(Spanned {
+                    item: CompileError::NotConstant(name.to_string()),
+                    span,
+                }),
+            },
+            _ => {
+                if let Some((segment, index)) = self.variable(name, span) {
+                    self.push(span, segment, index);
+                }
+            }
+        }
+    }
+
+    /// Pushes a number. Constants can only be pushed up to 32767, so negative numbers are pushed as
+    /// their complement.
+    fn constant(&mut self, span: Span, value: i16) {
+        match value >= 0 {
+            true => self.push(span, Segment::Constant, value as u16),
+            false => {
+                self.push(span, Segment::Constant, !value as u16);
+                self.arithmetic(span, Arithmetic::Not);
+            }
+        }
+    }
+
+    /// Compiles the body of a loop, which `continue` leaves for `next` and `break` for `end`
+    fn loop_body(&mut self, body: &[Spanned<Statement>], next: &str, end: &str) {
+        self.loops.push((next.to_string(), end.to_string()));
+        self.statements(body);
+        self.loops.pop();
+    }
+
+    fn statement(&mut self, statement: &Spanned<Statement>) {
+        let span = statement.span;
+        match &statement.item {
+            Statement::Let {
+                name,
+                index: None,
+                value,
+            } => {
+                self.expression(value);
+                if let Some((segment, index)) = self.variable(&name.item, name.span) {
+                    self.pop(span, segment, index);
+                }
+            }
+            Statement::Let {
+                name,
+                index: Some(index),
+                value,
+            } => {
+                self.element_address(name, index);
+                self.expression(value);
+                self.pop(span, Segment::Temp, 0);
+                self.pop(span, Segment::Pointer, 1);
+                self.push(span, Segment::Temp, 0);
+                self.pop(span, Segment::That, 0);
+            }
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                let n = self.ifs;
+                self.ifs += 1;
+                let (true_label, false_label, end_label) = (
+                    format!("IF_TRUE{n}"),
+                    format!("IF_FALSE{n}"),
+                    format!("IF_END{n}"),
+                );
+                self.expression(condition);
+                self.emit(span, VmCommand::IfGoto(true_label.clone()));
+                self.emit(span, VmCommand::Goto(false_label.clone()));
+                self.label(span, &true_label);
+                self.statements(then);
+                match otherwise {
+                    Some(otherwise) => {
+                        self.emit(span, VmCommand::Goto(end_label.clone()));
+                        self.label(span, &false_label);
+                        self.statements(otherwise);
+                        self.label(span, &end_label);
+                    }
+                    None => self.label(span, &false_label),
+                }
+            }
+            Statement::While { condition, body } => {
+                let n = self.whiles;
+                self.whiles += 1;
+                let (start_label, end_label) = (format!("WHILE_EXP{n}"), format!("WHILE_END{n}"));
+                self.label(span, &start_label);
+                self.expression(condition);
+                self.arithmetic(span, Arithmetic::Not);
+                self.emit(span, VmCommand::IfGoto(end_label.clone()));
+                self.loop_body(body, &start_label, &end_label);
+                self.emit(span, VmCommand::Goto(start_label));
+                self.label(span, &end_label);
+            }
+            Statement::For {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                let n = self.fors;
+                self.fors += 1;
+                let (start_label, next_label, end_label) = (
+                    format!("FOR_EXP{n}"),
+                    format!("FOR_NEXT{n}"),
+                    format!("FOR_END{n}"),
+                );
+                if let Some(init) = init {
+                    self.statement(init);
+                }
+                self.label(span, &start_label);
+                if let Some(condition) = condition {
+                    self.expression(condition);
+                    self.arithmetic(span, Arithmetic::Not);
+                    self.emit(span, VmCommand::IfGoto(end_label.clone()));
+                }
+                self.loop_body(body, &next_label, &end_label);
+                self.label(span, &next_label);
+                if let Some(update) = update {
+                    self.statement(update);
+                }
+                self.emit(span, VmCommand::Goto(start_label));
+                self.label(span, &end_label);
+            }
+            Statement::Break | Statement::Continue => {
+                // the parser only allows these inside loops
+                if let Some((next, end)) = self.loops.last() {
+                    let label = match statement.item {
+                        Statement::Break => end.clone(),
+                        _ => next.clone(),
+                    };
+                    self.emit(span, VmCommand::Goto(label));
+                }
+            }
+            Statement::Do(call) => {
+                self.subroutine_call(call, span);
+                self.pop(span, Segment::Temp, 0);
+            }
+            Statement::Return(value) => {
+                match value {
+                    Some(value) => self.expression(value),
+                    None => self.push(span, Segment::Constant, 0),
+                }
+                self.emit(span, VmCommand::Return);
+            }
+        }
+    }
+
+    /// Pushes the address of an array element
+    fn element_address(&mut self, name: &Name, index: &Spanned<Expression>) {
+        self.push_variable(&name.item, name.span);
+        self.expression(index);
+        self.arithmetic(index.span, Arithmetic::Add);
+    }
+
+    fn subroutine_call(&mut self, call: &SubroutineCall, span: Span) {
+        let mut args = call.args.len() as u16;
+        let class = match &call.receiver {
+            // a method of this object, unless the class has a function of that name
+            None => {
+                let function = self.class.subroutines.iter().any(|subroutine| {
+                    subroutine.item.name.item == call.name.item
+                        && subroutine.item.kind != SubroutineKind::Method
+                });
+                if !function {
+                    self.push(span, Segment::Pointer, 0);
+                    args += 1;
+                }
+                self.class.name.item.clone()
+            }
+            Some(receiver) => match self.symbols.get(&receiver.item) {
+                // a method of another object
+                Some(symbol) => {
+                    let (ty, segment, index) =
+                        (symbol.ty.clone(), symbol.kind.segment(), symbol.index);
+                    self.push(receiver.span, segment, index);
+                    args += 1;
+                    match ty {
+                        Type::Class(class) => class,
+                        ty => {
+                            self.errors.push(Spanned {
+                                item: CompileError::NotAnObject {
+                                    name: receiver.item.clone(),
+                                    ty,
+                                },
+                                span: receiver.span,
+                            });
+                            return;
+                        }
+                    }
+                }
+                // a function or constructor of a class
+                None => receiver.item.clone(),
+            },
+        };
+
+        for arg in &call.args {
+            self.expression(arg);
+        }
+        self.call(span, format!("{class}.{}", call.name.item), args);
+    }
+
+    fn expression(&mut self, expression: &Spanned<Expression>) {
+        let span = expression.span;
+        match &expression.item {
+            Expression::Integer(value) => self.constant(span, *value as i16),
+            Expression::Char(c) => self.push(span, Segment::Constant, *c as u16),
+            Expression::String(text) => {
+                self.push(span, Segment::Constant, text.len() as u16);
+                self.call(span, "String.new".to_string(), 1);
+                for c in text.chars() {
+                    self.push(span, Segment::Constant, c as u16);
+                    self.call(span, "String.appendChar".to_string(), 2);
+                }
+            }
+            Expression::Keyword(KeywordConstant::True) => {
+                self.push(span, Segment::Constant, 0);
+                self.arithmetic(span, Arithmetic::Not);
+            }
+            Expression::Keyword(KeywordConstant::False | KeywordConstant::Null) => {
+                self.push(span, Segment::Constant, 0)
+            }
+            Expression::Keyword(KeywordConstant::This) => self.push(span, Segment::Pointer, 0),
+            Expression::Variable(name) => self.push_variable(name, span),
+            Expression::Index { name, index } => {
+                self.element_address(name, index);
+                self.pop(span, Segment::Pointer, 1);
+                self.push(span, Segment::That, 0);
+            }
+            Expression::Call(call) => self.subroutine_call(call, span),
+            Expression::Parenthesized(inner) => self.expression(inner),
+            Expression::Unary { op, operand } => {
+                self.expression(operand);
+                let op = match op {
+                    UnaryOp::Neg => Arithmetic::Neg,
+                    UnaryOp::Not => Arithmetic::Not,
+                };
+                self.arithmetic(span, op);
+            }
+            Expression::Binary { op, left, right } => {
+                self.expression(left);
+                self.expression(right);
+                match op {
+                    BinaryOp::Mul => self.call(span, "Math.multiply".to_string(), 2),
+                    BinaryOp::Div => self.call(span, "Math.divide".to_string(), 2),
+                    op => {
+                        let op = match op {
+                            BinaryOp::Add => Arithmetic::Add,
+                            BinaryOp::Sub => Arithmetic::Sub,
+                            BinaryOp::And => Arithmetic::And,
+                            BinaryOp::Or => Arithmetic::Or,
+                            BinaryOp::Lt => Arithmetic::Lt,
+                            BinaryOp::Gt => Arithmetic::Gt,
+                            _ => Arithmetic::Eq,
+                        };
+                        self.arithmetic(span, op);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jack::{parse, parse_with, Dialect};
+    use crate::vm::VmMachine;
+
+    fn compile_source(source: &str) -> Result<Vec<String>, Vec<CompileError>> {
+        let (class, errors) = parse(source);
+        assert_eq!(errors, []);
+        compile(&class.unwrap())
+            .map(|commands| commands.iter().map(|c| c.item.to_string()).collect())
+            .map_err(|errors| errors.into_iter().map(|e| e.item).collect())
+    }
+
+    #[test]
+    fn objects() {
+        let source = "class Point {
+    field int x, y;
+    static Point origin;
+    constructor Point new(int ax) { let x = ax; return this; }
+    method void set(Array a, int i) { let a[i] = x; let y = a[0]; return; }
+    function void main() { var Point p; let p = Point.new(\"hi\"); do p.set(null, 40000); return; }
+}";
+        let commands = compile_source(source).unwrap();
+        assert_eq!(
+            commands,
+            [
+                "function Point.new 0",
+                "push constant 2",
+                "call Memory.alloc 1",
+                "pop pointer 0",
+                "push argument 0",
+                "pop this 0",
+                "push pointer 0",
+                "return",
+                "function Point.set 0",
+                "push argument 0",
+                "pop pointer 0",
+                "push argument 1",
+                "push argument 2",
+                "add",
+                "push this 0",
+                "pop temp 0",
+                "pop pointer 1",
+                "push temp 0",
+                "pop that 0",
+                "push argument 1",
+                "push constant 0",
+                "add",
+                "pop pointer 1",
+                "push that 0",
+                "pop this 1",
+                "push constant 0",
+                "return",
+                "function Point.main 1",
+                "push constant 2",
+                "call String.new 1",
+                "push constant 104",
+                "call String.appendChar 2",
+                "push constant 105",
+                "call String.appendChar 2",
+                "call Point.new 1",
+                "pop local 0",
+                "push local 0",
+                "push constant 0",
+                "push constant 25535",
+                "not",
+                "call Point.set 3",
+                "pop temp 0",
+                "push constant 0",
+                "return",
+            ]
+        );
+    }
+
+    #[test]
+    fn control_flow() {
+        let source = "class Main {
+    function int sum(int n) {
+        var int total;
+        while (n > 0) {
+            if (n = 3) { let total = total + (n * 2); } else { let total = total + n; }
+            if (true) { }
+            let n = n - 1;
+        }
+        return total;
+    }
+}";
+        let commands = compile_source(source).unwrap();
+        let labels = commands
+            .iter()
+            .filter(|c| c.starts_with("label") || c.contains("goto"))
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            labels,
+            [
+                "label WHILE_EXP0",
+                "if-goto WHILE_END0",
+                "if-goto IF_TRUE0",
+                "goto IF_FALSE0",
+                "label IF_TRUE0",
+                "goto IF_END0",
+                "label IF_FALSE0",
+                "label IF_END0",
+                "if-goto IF_TRUE1",
+                "goto IF_FALSE1",
+                "label IF_TRUE1",
+                "label IF_FALSE1",
+                "goto WHILE_EXP0",
+                "label WHILE_END0",
+            ]
+        );
+
+        // running it gives 5 + 4 + 6 + 2 + 1
+        let mut commands = parse(source)
+            .0
+            .map(|class| compile(&class).unwrap())
+            .unwrap();
+        let program =
+            "function Sys.init 0\npush constant 5\ncall Main.sum 1\nlabel HALT\ngoto HALT\n
+function Math.multiply 0\npush argument 0\npush argument 0\nadd\nreturn";
+        commands.extend(crate::vm::parse(program).map(Result::unwrap));
+        let mut vm = VmMachine::new(commands);
+        vm.bootstrap().unwrap();
+        vm.run(10_000).unwrap();
+        assert_eq!(vm.stack().last(), Some(&18));
+    }
+
+    #[test]
+    fn extended() {
+        let source = "class Main {
+    const int LIMIT = 10;
+    const char A = 'A';
+    function int sum() {
+        var int total, i;
+        for (let i = 0; i < LIMIT; let i = i + 1) {
+            if (i = 2) { continue; } else if (i = 6) { break; }
+            let total = total + i * 2;
+        }
+        while (true) {
+            let total = total + 1;
+            if (total > 30) { break; }
+        }
+        return total + A - 'A';
+    }
+}";
+        let class = parse_with(source, Dialect::Extended).0.unwrap();
+        let mut commands = compile(&class).unwrap();
+        let labels = commands
+            .iter()
+            .map(|c| c.item.to_string())
+            .filter(|c| c.starts_with("label FOR") || c.starts_with("goto FOR"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            labels,
+            [
+                "label FOR_EXP0",
+                "goto FOR_NEXT0",
+                "goto FOR_END0",
+                "label FOR_NEXT0",
+                "goto FOR_EXP0",
+                "label FOR_END0",
+            ]
+        );
+
+        // the loop adds 2 * (0 + 1 + 3 + 4 + 5), then the while loop counts up to 31
+        let program = "function Sys.init 0\ncall Main.sum 0\nlabel HALT\ngoto HALT";
+        commands.extend(crate::vm::parse(program).map(Result::unwrap));
+        let mut vm = VmMachine::new(commands);
+        vm.set_native_os(true);
+        vm.bootstrap().unwrap();
+        vm.run(10_000).unwrap();
+        assert_eq!(vm.stack().last(), Some(&31));
+
+        let source = "class Main { const int N = 1; function void f() { let N = 2; return; } }";
+        let errors = compile(&parse_with(source, Dialect::Extended).0.unwrap()).unwrap_err();
+        assert_eq!(
+            errors[0].item,
+            CompileError::AssignToConstant("N".to_string())
+        );
+    }
+
+    #[test]
+    fn unqualified_calls() {
+        let source = "class Main {
+    function int twice(int x) { return x + x; }
+    method int get() { return 1; }
+    method int both() { return twice(get()); }
+}";
+        let commands = compile_source(source).unwrap();
+        assert_eq!(
+            commands[commands.len() - 4..],
+            [
+                "push pointer 0",
+                "call Main.get 1",
+                "call Main.twice 1",
+                "return",
+            ][..]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let source = "class Main {
+    function void main() { var int n; let m = 1; do n.run(); return; }
+}";
+        assert_eq!(
+            compile_source(source),
+            Err(vec![
+                CompileError::UndeclaredVariable("m".to_string()),
+                CompileError::NotAnObject {
+                    name: "n".to_string(),
+                    ty: Type::Int
+                },
+            ])
+        );
+    }
+}
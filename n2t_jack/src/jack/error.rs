@@ -0,0 +1,103 @@
+use super::ast::Type;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum SyntaxError {
+    #[error("`{0}` cannot appear in a Jack program")]
+    UnexpectedCharacter(char),
+
+    #[error("`{0}` is not a number between 0 and 65535")]
+    BadNumber(String),
+
+    #[error("This string is missing its closing `\"`")]
+    UnterminatedString,
+
+    #[error("This comment is missing its closing `*/`")]
+    UnterminatedComment,
+
+    #[error("This character is missing its closing `'`")]
+    UnterminatedChar,
+
+    #[error("`'{0}'` is not a single printable character")]
+    BadChar(String),
+
+    #[error("Expected {expected}, but found {found}")]
+    Expected {
+        expected: &'static str,
+        /// The token which was found, or `the end of the file`
+        found: String,
+    },
+
+    #[error("Unexpected `{0}` after the end of the class")]
+    Trailing(String),
+
+    #[error("`{0}` can only be used inside a loop")]
+    OutsideLoop(&'static str),
+}
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum CompileError {
+    #[error("`{0}` is not declared")]
+    UndeclaredVariable(String),
+
+    #[error("`{name}` is of type {ty}, which has no subroutines to call")]
+    NotAnObject { name: String, ty: Type },
+
+    #[error("`{0}` is a constant, so it cannot be assigned to")]
+    AssignToConstant(String),
+
+    #[error("The value of `{0}` cannot be worked out while compiling")]
+    NotConstant(String),
+}
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum SemanticError {
+    #[error("`{0}` is not declared")]
+    UndeclaredVariable(String),
+
+    #[error("There is no class named `{0}`")]
+    UndefinedClass(String),
+
+    #[error("`{class}` has no subroutine named `{name}`")]
+    UndefinedSubroutine { class: String, name: String },
+
+    #[error("`{name}` takes {expected} arguments, but is given {given}")]
+    ArgumentCount {
+        name: String,
+        expected: usize,
+        given: usize,
+    },
+
+    #[error("`{0}` is a method, so it must be called on an object")]
+    NeedsObject(String),
+
+    #[error("`{0}` is not a method, so it cannot be called on an object")]
+    NotAMethod(String),
+
+    #[error("`{name}` is of type {ty}, which has no subroutines to call")]
+    NotAnObject { name: String, ty: Type },
+
+    #[error("`{0}` returns nothing, so it cannot be used as a value")]
+    VoidValue(String),
+
+    #[error("`{0}` can reach its end without returning")]
+    MissingReturn(String),
+
+    #[error("`{0}` is void, so it cannot return a value")]
+    UnexpectedReturnValue(String),
+
+    #[error("`{0}` must return a value")]
+    MissingReturnValue(String),
+
+    #[error("A value of type {value} cannot be assigned to a variable of type {target}")]
+    IncompatibleTypes { value: Type, target: Type },
+
+    #[error("`{0}` is a constant, so it cannot be assigned to")]
+    AssignToConstant(String),
+
+    #[error("The value of `{0}` cannot be worked out while compiling")]
+    NotConstant(String),
+
+    #[error("Constants must be of type int, char or boolean, not {0}")]
+    ConstantType(Type),
+}
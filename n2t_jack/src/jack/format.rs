@@ -0,0 +1,648 @@
+use super::ast::*;
+use super::error::SyntaxError;
+use super::token::{Dialect, Token};
+use super::{lex_with, parse_with};
+use crate::span::{Span, Spanned};
+
+/// How long a line can be before the expression on it is wrapped
+const MAX_WIDTH: usize = 100;
+const INDENT: &str = "    ";
+
+/// Formats a Jack class in the canonical style: four spaces of indentation, opening braces at the
+/// end of lines, one blank line between subroutines, and expressions which are too long for one
+/// line split over several. Comments are kept, but those in the middle of a statement are moved
+/// above it, and those between a block and its `else` are moved to the end of the block.
+pub fn format(source: &str) -> Result<String, Vec<Spanned<SyntaxError>>> {
+    format_with(source, Dialect::Standard)
+}
+
+/// Formats a Jack class written in a dialect of Jack
+pub fn format_with(source: &str, dialect: Dialect) -> Result<String, Vec<Spanned<SyntaxError>>> {
+    let (class, errors) = parse_with(source, dialect);
+    let class = match class {
+        Some(class) if errors.is_empty() => class,
+        _ => return Err(errors),
+    };
+
+    let tokens = lex_with(source, dialect).flatten().collect::<Vec<_>>();
+    let (comments, tokens) = tokens
+        .into_iter()
+        .partition(|token| token.item.is_comment());
+    let mut formatter = Formatter {
+        source,
+        tokens,
+        comments,
+        next_comment: 0,
+        lines: Vec::new(),
+        indent: 0,
+        last_end: 0,
+        separate: false,
+    };
+    formatter.class(&class);
+    formatter.comments_before(source.len());
+
+    let mut out = formatter.lines.join("\n");
+    out.push('\n');
+    Ok(out)
+}
+
+fn end(span: Span) -> usize {
+    span.offset + span.len
+}
+
+fn keyword(keyword: KeywordConstant) -> &'static str {
+    match keyword {
+        KeywordConstant::True => "true",
+        KeywordConstant::False => "false",
+        KeywordConstant::Null => "null",
+        KeywordConstant::This => "this",
+    }
+}
+
+fn call(call: &SubroutineCall) -> String {
+    let args = call.args.iter().map(|arg| expression(&arg.item));
+    format!("{}({})", callee(call), args.collect::<Vec<_>>().join(", "))
+}
+
+fn callee(call: &SubroutineCall) -> String {
+    match &call.receiver {
+        Some(receiver) => format!("{}.{}", receiver.item, call.name.item),
+        None => call.name.item.clone(),
+    }
+}
+
+/// Prints an expression on a single line
+fn expression(expression: &Expression) -> String {
+    match expression {
+        Expression::Integer(value) => value.to_string(),
+        Expression::Char(c) => format!("'{c}'"),
+        Expression::String(text) => format!("\"{text}\""),
+        Expression::Keyword(constant) => keyword(*constant).to_string(),
+        Expression::Variable(name) => name.clone(),
+        Expression::Index { name, index } => {
+            format!("{}[{}]", name.item, self::expression(&index.item))
+        }
+        Expression::Call(subroutine_call) => call(subroutine_call),
+        Expression::Parenthesized(inner) => format!("({})", self::expression(&inner.item)),
+        Expression::Unary { op, operand } => {
+            format!("{}{}", op.symbol(), self::expression(&operand.item))
+        }
+        Expression::Binary { op, left, right } => format!(
+            "{} {} {}",
+            self::expression(&left.item),
+            op.symbol(),
+            self::expression(&right.item)
+        ),
+    }
+}
+
+/// Prints a `let` or `do` statement without its `;`, as in the header of a `for` loop
+fn simple_statement(statement: &Statement) -> String {
+    match statement {
+        Statement::Let { name, index, value } => match index {
+            Some(index) => format!(
+                "let {}[{}] = {}",
+                name.item,
+                expression(&index.item),
+                expression(&value.item)
+            ),
+            None => format!("let {} = {}", name.item, expression(&value.item)),
+        },
+        Statement::Do(subroutine_call) => format!("do {}", call(subroutine_call)),
+        _ => unreachable!("only `let` and `do` statements are found in `for` headers"),
+    }
+}
+
+/// The operands of a chain of binary operations like `a + b - c`, which Jack nests on the left
+fn chain(expression: &Expression) -> Vec<(Option<BinaryOp>, &Expression)> {
+    match expression {
+        Expression::Binary { op, left, right } => {
+            let mut operands = chain(&left.item);
+            operands.push((Some(*op), &right.item));
+            operands
+        }
+        _ => vec![(None, expression)],
+    }
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    /// The tokens of the class, leaving out comments
+    tokens: Vec<Spanned<Token>>,
+    comments: Vec<Spanned<Token>>,
+    /// The first comment which has not been written yet
+    next_comment: usize,
+    lines: Vec<String>,
+    indent: usize,
+    /// Where the source of what was last written ends
+    last_end: usize,
+    /// Whether the next line should be separated from the last by a blank line
+    separate: bool,
+}
+
+impl Formatter<'_> {
+    /// Where the first `symbol` at or after `offset` is
+    fn symbol_after(&self, offset: usize, symbol: char) -> usize {
+        self.tokens
+            .iter()
+            .find(|token| token.span.offset >= offset && token.item == Token::Symbol(symbol))
+            .map_or(offset, |token| token.span.offset)
+    }
+
+    /// Starts a new line, after a blank one if the source had one since the last thing written
+    fn start_line(&mut self, offset: usize) {
+        let gap = &self.source[self.last_end.min(offset)..offset];
+        let blank = self.separate || gap.matches('\n').count() >= 2;
+        let after_brace = self
+            .lines
+            .last()
+            .is_none_or(|line| line.ends_with('{') || line.is_empty());
+        if blank && !after_brace {
+            self.lines.push(String::new());
+        }
+        self.separate = false;
+    }
+
+    fn push(&mut self, text: &str) {
+        self.lines
+            .push(format!("{}{text}", INDENT.repeat(self.indent)));
+    }
+
+    /// Writes the comments which come before `offset`. Those on the same line as the end of the
+    /// last thing written stay at the end of its line.
+    fn comments_before(&mut self, offset: usize) {
+        self.write_comments(offset, true);
+    }
+
+    /// Writes the comments which come before `offset` on lines of their own, for comments in the
+    /// middle of a statement which are moved above it
+    fn comments_above(&mut self, offset: usize) {
+        self.write_comments(offset, false);
+    }
+
+    fn write_comments(&mut self, offset: usize, allow_trailing: bool) {
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            let span = comment.span;
+            if span.offset >= offset {
+                break;
+            }
+            self.next_comment += 1;
+
+            let text = span.of(self.source).trim_end();
+            let gap = &self.source[self.last_end.min(span.offset)..span.offset];
+            let trailing = allow_trailing
+                && !gap.contains('\n')
+                && self.lines.last().is_some_and(|l| !l.is_empty());
+            let mut lines = text.lines();
+            let first = lines.next().unwrap_or_default();
+            if trailing {
+                let line = self.lines.last_mut().unwrap();
+                line.push(' ');
+                line.push_str(first);
+            } else {
+                self.start_line(span.offset);
+                self.push(first.trim());
+            }
+            // the rest of a block comment goes on lines of its own
+            lines.for_each(|rest| self.push_comment_line(rest));
+            self.last_end = end(span);
+        }
+    }
+
+    /// Writes a line of a block comment after its first, lining up the `*`s of doc comments
+    fn push_comment_line(&mut self, line: &str) {
+        let line = line.trim();
+        match line {
+            "" => self.lines.push(String::new()),
+            _ if line.starts_with('*') => self.push(&format!(" {line}")),
+            _ => self.push(line),
+        }
+    }
+
+    /// Writes the first line of something which starts at `offset`, after any comments before it
+    fn line(&mut self, offset: usize, text: &str) {
+        self.comments_before(offset);
+        self.start_line(offset);
+        self.push(text);
+        self.last_end = offset;
+    }
+
+    /// Writes what starts at `offset` as `prefix`, an expression, then `suffix`, splitting the
+    /// expression over several lines if it is too long
+    fn wrapped(&mut self, offset: usize, prefix: &str, value: &Expression, suffix: &str) {
+        let flat = format!("{prefix}{}{suffix}", expression(value));
+        if self.indent * INDENT.len() + flat.len() <= MAX_WIDTH {
+            return self.line(offset, &flat);
+        }
+
+        match value {
+            Expression::Binary { .. } => {
+                // each operator starts a line, indented twice so that it stands out from a block
+                let operands = chain(value);
+                let count = operands.len();
+                for (i, (op, operand)) in operands.into_iter().enumerate() {
+                    let suffix = if i + 1 == count { suffix } else { "" };
+                    match op {
+                        None => self.line(offset, &format!("{prefix}{}", expression(operand))),
+                        Some(op) => self.push(&format!(
+                            "{INDENT}{INDENT}{} {}{suffix}",
+                            op.symbol(),
+                            expression(operand)
+                        )),
+                    }
+                }
+            }
+            Expression::Call(subroutine_call) if !subroutine_call.args.is_empty() => {
+                self.line(offset, &format!("{prefix}{}(", callee(subroutine_call)));
+                let count = subroutine_call.args.len();
+                for (i, arg) in subroutine_call.args.iter().enumerate() {
+                    let comma = if i + 1 == count { "" } else { "," };
+                    self.push(&format!("{INDENT}{}{comma}", expression(&arg.item)));
+                }
+                self.push(&format!("){suffix}"));
+            }
+            _ => self.line(offset, &flat),
+        }
+    }
+
+    /// Writes the `}` at `offset`, after any comments at the end of the block it closes
+    fn close(&mut self, offset: usize) {
+        self.comments_before(offset);
+        self.indent -= 1;
+        self.push("}");
+        self.last_end = offset + 1;
+    }
+
+    fn class(&mut self, class: &Class) {
+        let start = self.tokens.first().map_or(0, |token| token.span.offset);
+        self.line(start, &format!("class {} {{", class.name.item));
+        self.last_end = self.symbol_after(end(class.name.span), '{') + 1;
+        self.indent += 1;
+
+        for dec in &class.vars {
+            let names = dec.item.names.iter().map(|name| name.item.as_str());
+            let names = names.collect::<Vec<_>>().join(", ");
+            let text = match &dec.item.value {
+                Some(value) => format!(
+                    "{} {} {names} = {};",
+                    dec.item.kind,
+                    dec.item.ty.item,
+                    expression(&value.item)
+                ),
+                None => format!("{} {} {names};", dec.item.kind, dec.item.ty.item),
+            };
+            self.line(dec.span.offset, &text);
+            self.last_end = end(dec.span);
+        }
+        for subroutine in &class.subroutines {
+            self.separate = true;
+            self.subroutine(subroutine);
+        }
+
+        let close = self.tokens.last().map_or(0, |token| token.span.offset);
+        self.close(close);
+    }
+
+    fn subroutine(&mut self, subroutine: &Spanned<Subroutine>) {
+        let span = subroutine.span;
+        let subroutine = &subroutine.item;
+        let return_type = match &subroutine.return_type.item {
+            Some(ty) => ty.to_string(),
+            None => "void".to_string(),
+        };
+        let parameters = subroutine
+            .parameters
+            .iter()
+            .map(|parameter| format!("{} {}", parameter.ty.item, parameter.name.item));
+        let header = format!(
+            "{} {return_type} {}({}) {{",
+            subroutine.kind,
+            subroutine.name.item,
+            parameters.collect::<Vec<_>>().join(", ")
+        );
+        self.line(span.offset, &header);
+        self.last_end = self.symbol_after(end(subroutine.name.span), '{') + 1;
+        self.indent += 1;
+
+        for dec in &subroutine.vars {
+            let names = dec.item.names.iter().map(|name| name.item.as_str());
+            let names = names.collect::<Vec<_>>().join(", ");
+            self.line(
+                dec.span.offset,
+                &format!("var {} {names};", dec.item.ty.item),
+            );
+            self.last_end = end(dec.span);
+        }
+        self.statements(&subroutine.statements);
+        self.close(end(span) - 1);
+    }
+
+    fn statements(&mut self, statements: &[Spanned<Statement>]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    /// Writes the statements of a block whose `{` is at `open`, returning where its `}` is
+    fn block(&mut self, open: usize, statements: &[Spanned<Statement>]) -> usize {
+        self.last_end = open + 1;
+        self.indent += 1;
+        self.statements(statements);
+        let last = statements.last().map_or(open + 1, |last| end(last.span));
+        self.symbol_after(last, '}')
+    }
+
+    fn statement(&mut self, statement: &Spanned<Statement>) {
+        let offset = statement.span.offset;
+        // `if` statements move the comments in their conditions themselves
+        let head = match &statement.item {
+            Statement::If { .. } => offset,
+            Statement::While { condition, .. } => self.symbol_after(end(condition.span), '{'),
+            Statement::For { .. } => self.symbol_after(offset, '{'),
+            _ => end(statement.span),
+        };
+        self.comments_before(offset);
+        self.comments_above(head);
+        match &statement.item {
+            Statement::Let { name, index, value } => {
+                let target = match index {
+                    Some(index) => format!("{}[{}]", name.item, expression(&index.item)),
+                    None => name.item.clone(),
+                };
+                self.wrapped(offset, &format!("let {target} = "), &value.item, ";");
+            }
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.if_statement(offset, "if (", condition, then, otherwise);
+                self.close(end(statement.span) - 1);
+            }
+            Statement::While { condition, body } => {
+                self.wrapped(offset, "while (", &condition.item, ") {");
+                let open = self.symbol_after(end(condition.span), '{');
+                self.block(open, body);
+                self.close(end(statement.span) - 1);
+            }
+            Statement::For {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                // each part after the first is preceded by a space, if it is there
+                let part = |statement: &Option<Box<Spanned<Statement>>>| {
+                    statement
+                        .as_ref()
+                        .map_or(String::new(), |s| simple_statement(&s.item))
+                };
+                let spaced = |text: String| match text.is_empty() {
+                    true => text,
+                    false => format!(" {text}"),
+                };
+                let condition = condition
+                    .as_ref()
+                    .map_or(String::new(), |c| expression(&c.item));
+                let header = format!(
+                    "for ({};{};{}) {{",
+                    part(init),
+                    spaced(condition),
+                    spaced(part(update))
+                );
+                self.line(offset, &header);
+                // the header has no braces, so the first is the body's
+                let open = self.symbol_after(offset, '{');
+                self.block(open, body);
+                self.close(end(statement.span) - 1);
+            }
+            Statement::Break => self.line(offset, "break;"),
+            Statement::Continue => self.line(offset, "continue;"),
+            Statement::Do(subroutine_call) => {
+                let value = Expression::Call(subroutine_call.clone());
+                self.wrapped(offset, "do ", &value, ";");
+            }
+            Statement::Return(None) => self.line(offset, "return;"),
+            Statement::Return(Some(value)) => self.wrapped(offset, "return ", &value.item, ";"),
+        }
+        if !matches!(
+            statement.item,
+            Statement::If { .. } | Statement::While { .. } | Statement::For { .. }
+        ) {
+            self.last_end = end(statement.span);
+        }
+    }
+
+    /// Writes an `if` statement starting with `prefix` up to its last `}`. An `else` whose only
+    /// statement is another `if`, written without braces, continues on the same line as
+    /// `} else if (`.
+    fn if_statement(
+        &mut self,
+        offset: usize,
+        prefix: &str,
+        condition: &Spanned<Expression>,
+        then: &[Spanned<Statement>],
+        otherwise: &Option<Vec<Spanned<Statement>>>,
+    ) {
+        let open = self.symbol_after(end(condition.span), '{');
+        self.comments_above(open);
+        self.wrapped(offset, prefix, &condition.item, ") {");
+        let close = self.block(open, then);
+        let Some(otherwise) = otherwise else {
+            return;
+        };
+        self.comments_before(close);
+        let open = self.symbol_after(close + 1, '{');
+        // comments around the `else`, and in the condition of an `else if`, go at the end of the
+        // block before it
+        self.comments_above(open);
+        self.indent -= 1;
+        match otherwise.as_slice() {
+            [Spanned {
+                item:
+                    Statement::If {
+                        condition,
+                        then,
+                        otherwise,
+                    },
+                span,
+            }] if open > span.offset => {
+                self.last_end = span.offset;
+                self.if_statement(span.offset, "} else if (", condition, then, otherwise);
+            }
+            _ => {
+                self.push("} else {");
+                self.block(open, otherwise);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jack::{compile, parse, OS_SOURCES};
+
+    #[test]
+    fn formatting() {
+        let source = "// Draws things
+class Main{ field int x,y ; static boolean on;
+/** Starts
+      * the program */
+function void main( ) { var int i;
+
+  let i=-1+ ( 2*3 ); // a comment
+  if(i>0){do Output.printInt(i);}else{ while (~on) { let on = true; }
+  // the end of the block
+  }
+
+
+  Output.println();
+    do Screen.drawRectangle(Main.left(i + 100), Main.top(i + 200), Main.right(i + 300), Main.bottom(i + 400));
+  return; } }";
+
+        assert_eq!(
+            format(source).unwrap(),
+            "// Draws things
+class Main {
+    field int x, y;
+    static boolean on;
+
+    /** Starts
+     * the program */
+    function void main() {
+        var int i;
+
+        let i = -1 + (2 * 3); // a comment
+        if (i > 0) {
+            do Output.printInt(i);
+        } else {
+            while (~on) {
+                let on = true;
+            }
+            // the end of the block
+        }
+
+        do Output.println();
+        do Screen.drawRectangle(
+            Main.left(i + 100),
+            Main.top(i + 200),
+            Main.right(i + 300),
+            Main.bottom(i + 400)
+        );
+        return;
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn os() {
+        // the OS keeps its own layout, like the aligned comments of the font, but formatting it
+        // must not change what it compiles to
+        let compiled = |source: &str| {
+            let commands = compile(&parse(source).0.unwrap()).unwrap();
+            commands.into_iter().map(|c| c.item).collect::<Vec<_>>()
+        };
+        for (name, source) in OS_SOURCES {
+            let formatted = format(source).unwrap();
+            assert_eq!(format(&formatted).unwrap(), formatted, "{name}");
+            assert_eq!(compiled(&formatted), compiled(source), "{name}");
+        }
+    }
+
+    #[test]
+    fn stable() {
+        let source = "class Main { function int f(int a) {
+    /* one
+        two */ if (a) { return a * 1000 + a * 2000 + a * 3000 + a * 4000 + a * 5000 + a * 6000 + a * 7000; }
+    return 0; // none
+} }";
+        let formatted = format(source).unwrap();
+        assert_eq!(format(&formatted).unwrap(), formatted);
+
+        // formatting only changes the layout, so the code compiles the same
+        let compiled = |source: &str| {
+            let commands = compile(&parse(source).0.unwrap()).unwrap();
+            commands.into_iter().map(|c| c.item).collect::<Vec<_>>()
+        };
+        assert_eq!(compiled(&formatted), compiled(source));
+    }
+
+    #[test]
+    fn extended() {
+        let source = "class Main {
+const int N=10;
+function void main() { var int i;
+for(let i=0;i<N;let i=i+1){ if (i=3){continue;} else if(i>5) {break;} else {do Output.printChar('x');} }
+for(;;){return;}
+} }";
+        assert_eq!(
+            format_with(source, Dialect::Extended).unwrap(),
+            "class Main {
+    const int N = 10;
+
+    function void main() {
+        var int i;
+        for (let i = 0; i < N; let i = i + 1) {
+            if (i = 3) {
+                continue;
+            } else if (i > 5) {
+                break;
+            } else {
+                do Output.printChar('x');
+            }
+        }
+        for (;;) {
+            return;
+        }
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn comments_in_statements() {
+        let source = "class Main { function void f(int a) {
+    let a = a /* mid */ + 1;
+    while (a /* check */ < 3) { let a = a + 1; }
+    if (a) {
+        return;
+    } // done
+    else if (a /* again */ > 1) {
+        return;
+    }
+} }";
+        let format = |source| format_with(source, Dialect::Extended).unwrap();
+        let formatted = format(source);
+        assert_eq!(format(&formatted), formatted);
+        assert_eq!(
+            formatted,
+            "class Main {
+    function void f(int a) {
+        /* mid */
+        let a = a + 1;
+        /* check */
+        while (a < 3) {
+            let a = a + 1;
+        }
+        if (a) {
+            return;
+            // done
+            /* again */
+        } else if (a > 1) {
+            return;
+        }
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert!(format("class Main { function void f() { let x = ; } }").is_err());
+    }
+}
@@ -0,0 +1,441 @@
+//! What editors need to know about Jack code: what each name refers to, and what could be typed
+//! next
+
+use super::ast::*;
+use super::os::os_api;
+use super::symbols::{Symbol, SymbolTable, VarKind};
+use crate::span::{Span, Spanned};
+
+/// Something a name in a program refers to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Class(String),
+    Subroutine { class: String, name: String },
+    Variable { name: String, symbol: Symbol },
+}
+
+/// A name in a class, including the names given in declarations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub span: Span,
+    pub target: Target,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Class,
+    Subroutine(SubroutineKind),
+    Variable,
+}
+
+/// A name which could be typed at some point in a class
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+    /// The declaration of what the name refers to
+    pub detail: String,
+}
+
+/// The classes of a program, followed by the API of the Jack OS classes which it does not define
+/// itself
+pub fn with_os(classes: &[Class]) -> Vec<Class> {
+    let os = os_api()
+        .into_iter()
+        .filter(|os| !classes.iter().any(|class| class.name.item == os.name.item));
+    classes.iter().cloned().chain(os).collect()
+}
+
+/// Finds every name in a class and what it refers to, in the order they appear
+pub fn references(class: &Class) -> Vec<Reference> {
+    let mut resolver = Resolver {
+        class,
+        symbols: SymbolTable::new(class),
+        references: Vec::new(),
+    };
+    resolver.class();
+    resolver.references
+}
+
+/// The name at a byte offset, which may be just after its last character
+pub fn reference_at(references: &[Reference], offset: usize) -> Option<&Reference> {
+    references.iter().find(|reference| {
+        (reference.span.offset..=reference.span.offset + reference.span.len).contains(&offset)
+    })
+}
+
+/// Where something is declared, as the name of the class it is in and the span of its name
+pub fn definition(target: &Target, class: &Class, classes: &[Class]) -> Option<(String, Span)> {
+    let find = |name: &str| classes.iter().find(|class| class.name.item == name);
+    match target {
+        Target::Class(name) => Some((name.clone(), find(name)?.name.span)),
+        Target::Subroutine { class, name } => {
+            let subroutine = find(class)?
+                .subroutines
+                .iter()
+                .find(|subroutine| subroutine.item.name.item == *name)?;
+            Some((class.clone(), subroutine.item.name.span))
+        }
+        Target::Variable { symbol, .. } => Some((class.name.item.clone(), symbol.span)),
+    }
+}
+
+/// A line of Jack describing something, such as `field int size` or `method int List.length()`
+pub fn describe(target: &Target, classes: &[Class]) -> Option<String> {
+    match target {
+        Target::Class(name) => Some(format!("class {name}")),
+        Target::Subroutine { class, name } => {
+            let subroutine = classes
+                .iter()
+                .find(|c| c.name.item == *class)?
+                .subroutines
+                .iter()
+                .find(|subroutine| subroutine.item.name.item == *name)?;
+            Some(signature(class, &subroutine.item))
+        }
+        Target::Variable { name, symbol } => Some(format!("{} {} {name}", symbol.kind, symbol.ty)),
+    }
+}
+
+fn signature(class: &str, subroutine: &Subroutine) -> String {
+    let return_type = match &subroutine.return_type.item {
+        Some(ty) => ty.to_string(),
+        None => "void".to_string(),
+    };
+    let parameters = subroutine
+        .parameters
+        .iter()
+        .map(|parameter| format!("{} {}", parameter.ty.item, parameter.name.item));
+    format!(
+        "{} {return_type} {class}.{}({})",
+        subroutine.kind,
+        subroutine.name.item,
+        parameters.collect::<Vec<_>>().join(", ")
+    )
+}
+
+/// The spans to change to rename a local variable or argument, or `None` if the target is
+/// something else, which other files might refer to
+pub fn rename(references: &[Reference], target: &Target) -> Option<Vec<Span>> {
+    let Target::Variable { symbol, .. } = target else {
+        return None;
+    };
+    if !matches!(symbol.kind, VarKind::Local | VarKind::Argument) {
+        return None;
+    }
+    let spans = references
+        .iter()
+        .filter(|reference| reference.target == *target)
+        .map(|reference| reference.span);
+    Some(spans.collect())
+}
+
+/// What could be typed at a byte offset in `source`. After `name.`, these are the subroutines
+/// which can be called on the class or variable `name`, and otherwise they are the variables,
+/// subroutines and classes in scope.
+pub fn completions(
+    classes: &[Class],
+    class: &Class,
+    source: &str,
+    offset: usize,
+) -> Vec<Completion> {
+    let before = &source[..offset.min(source.len())];
+    let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let start = before.trim_end_matches(is_name);
+    let receiver = start
+        .strip_suffix('.')
+        .map(|rest| &rest[rest.trim_end_matches(is_name).len()..]);
+
+    let mut symbols = SymbolTable::new(class);
+    let subroutine = class.subroutines.iter().find(|subroutine| {
+        (subroutine.span.offset..=subroutine.span.offset + subroutine.span.len).contains(&offset)
+    });
+    if let Some(subroutine) = subroutine {
+        symbols.enter(&subroutine.item);
+    }
+    let find = |name: &str| classes.iter().find(|class| class.name.item == name);
+    let members = |class: &Class, methods: bool| {
+        class
+            .subroutines
+            .iter()
+            .filter(move |subroutine| (subroutine.item.kind == SubroutineKind::Method) == methods)
+            .map(|subroutine| Completion {
+                label: subroutine.item.name.item.clone(),
+                kind: CompletionKind::Subroutine(subroutine.item.kind),
+                detail: signature(&class.name.item, &subroutine.item),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    match receiver {
+        Some(receiver) => match symbols.get(receiver) {
+            Some(Symbol {
+                ty: Type::Class(ty),
+                ..
+            }) => find(ty).map_or_else(Vec::new, |class| members(class, true)),
+            Some(_) => Vec::new(),
+            None => find(receiver).map_or_else(Vec::new, |class| members(class, false)),
+        },
+        None => {
+            let mut completions = symbols
+                .iter()
+                .map(|(name, symbol)| Completion {
+                    label: name.to_string(),
+                    kind: CompletionKind::Variable,
+                    detail: format!("{} {} {name}", symbol.kind, symbol.ty),
+                })
+                .collect::<Vec<_>>();
+            completions.sort_by(|a, b| a.label.cmp(&b.label));
+            let own = class.subroutines.iter().map(|subroutine| Completion {
+                label: subroutine.item.name.item.clone(),
+                kind: CompletionKind::Subroutine(subroutine.item.kind),
+                detail: signature(&class.name.item, &subroutine.item),
+            });
+            completions.extend(own);
+            completions.extend(classes.iter().map(|class| Completion {
+                label: class.name.item.clone(),
+                kind: CompletionKind::Class,
+                detail: format!("class {}", class.name.item),
+            }));
+            completions
+        }
+    }
+}
+
+struct Resolver<'a> {
+    class: &'a Class,
+    symbols: SymbolTable,
+    references: Vec<Reference>,
+}
+
+impl Resolver<'_> {
+    fn add(&mut self, span: Span, target: Target) {
+        self.references.push(Reference { span, target });
+    }
+
+    fn variable(&mut self, name: &str, span: Span) {
+        if let Some(symbol) = self.symbols.get(name) {
+            let target = Target::Variable {
+                name: name.to_string(),
+                symbol: symbol.clone(),
+            };
+            self.add(span, target);
+        }
+    }
+
+    fn ty(&mut self, span: Span, ty: &Type) {
+        if let Type::Class(name) = ty {
+            self.add(span, Target::Class(name.clone()));
+        }
+    }
+
+    fn class(&mut self) {
+        let class = self.class;
+        self.add(class.name.span, Target::Class(class.name.item.clone()));
+        for dec in &class.vars {
+            self.ty(dec.item.ty.span, &dec.item.ty.item);
+            for name in &dec.item.names {
+                self.variable(&name.item, name.span);
+            }
+            if let Some(value) = &dec.item.value {
+                self.expression(value);
+            }
+        }
+
+        for subroutine in &class.subroutines {
+            let subroutine = &subroutine.item;
+            self.symbols.enter(subroutine);
+            if let Some(ty) = &subroutine.return_type.item {
+                self.ty(subroutine.return_type.span, ty);
+            }
+            let target = Target::Subroutine {
+                class: class.name.item.clone(),
+                name: subroutine.name.item.clone(),
+            };
+            self.add(subroutine.name.span, target);
+            for parameter in &subroutine.parameters {
+                self.ty(parameter.ty.span, &parameter.ty.item);
+                self.variable(&parameter.name.item, parameter.name.span);
+            }
+            for dec in &subroutine.vars {
+                self.ty(dec.item.ty.span, &dec.item.ty.item);
+                for name in &dec.item.names {
+                    self.variable(&name.item, name.span);
+                }
+            }
+            self.statements(&subroutine.statements);
+        }
+    }
+
+    fn statements(&mut self, statements: &[Spanned<Statement>]) {
+        for statement in statements {
+            match &statement.item {
+                Statement::Let { name, index, value } => {
+                    self.variable(&name.item, name.span);
+                    if let Some(index) = index {
+                        self.expression(index);
+                    }
+                    self.expression(value);
+                }
+                Statement::If {
+                    condition,
+                    then,
+                    otherwise,
+                } => {
+                    self.expression(condition);
+                    self.statements(then);
+                    self.statements(otherwise.as_deref().unwrap_or_default());
+                }
+                Statement::While { condition, body } => {
+                    self.expression(condition);
+                    self.statements(body);
+                }
+                Statement::For {
+                    init,
+                    condition,
+                    update,
+                    body,
+                } => {
+                    for statement in init.iter().chain(update) {
+                        self.statements(std::slice::from_ref(statement));
+                    }
+                    if let Some(condition) = condition {
+                        self.expression(condition);
+                    }
+                    self.statements(body);
+                }
+                Statement::Break | Statement::Continue => (),
+                Statement::Do(call) => self.call(call),
+                Statement::Return(value) => {
+                    if let Some(value) = value {
+                        self.expression(value);
+                    }
+                }
+            }
+        }
+    }
+
+    fn call(&mut self, call: &SubroutineCall) {
+        let class = match &call.receiver {
+            Some(receiver) => match self.symbols.get(&receiver.item).cloned() {
+                Some(symbol) => {
+                    self.variable(&receiver.item, receiver.span);
+                    match symbol.ty {
+                        Type::Class(class) => Some(class),
+                        _ => None,
+                    }
+                }
+                None => {
+                    self.add(receiver.span, Target::Class(receiver.item.clone()));
+                    Some(receiver.item.clone())
+                }
+            },
+            None => Some(self.class.name.item.clone()),
+        };
+        if let Some(class) = class {
+            let name = call.name.item.clone();
+            self.add(call.name.span, Target::Subroutine { class, name });
+        }
+        for arg in &call.args {
+            self.expression(arg);
+        }
+    }
+
+    fn expression(&mut self, expression: &Spanned<Expression>) {
+        match &expression.item {
+            Expression::Variable(name) => self.variable(name, expression.span),
+            Expression::Index { name, index } => {
+                self.variable(&name.item, name.span);
+                self.expression(index);
+            }
+            Expression::Call(call) => self.call(call),
+            Expression::Parenthesized(inner) | Expression::Unary { operand: inner, .. } => {
+                self.expression(inner)
+            }
+            Expression::Binary { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expression::Integer(_)
+            | Expression::Char(_)
+            | Expression::String(_)
+            | Expression::Keyword(_) => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jack::parse;
+
+    const LIST: &str = "class List {
+    field int data;
+    field List next;
+    constructor List new(int value, List rest) {
+        let data = value;
+        let next = rest;
+        return this;
+    }
+    method int sum() {
+        var int total;
+        let total = data;
+        if (~(next = null)) { let total = total + next.sum(); }
+        return total;
+    }
+}";
+
+    fn at<'a>(references: &'a [Reference], text: &str, nth: usize) -> &'a Target {
+        let offset = LIST.match_indices(text).nth(nth).unwrap().0;
+        &reference_at(references, offset).unwrap().target
+    }
+
+    #[test]
+    fn resolving() {
+        let class = parse(LIST).0.unwrap();
+        let references = references(&class);
+        let classes = with_os(std::slice::from_ref(&class));
+
+        let total = at(&references, "total", 1);
+        assert_eq!(describe(total, &classes).unwrap(), "local int total");
+        let spans = rename(&references, total).unwrap();
+        assert_eq!(spans.len(), 5);
+        assert!(spans.iter().all(|span| span.of(LIST) == "total"));
+
+        let sum = at(&references, "sum", 1);
+        assert_eq!(describe(sum, &classes).unwrap(), "method int List.sum()");
+        let (class_name, span) = definition(sum, &class, &classes).unwrap();
+        assert_eq!((class_name.as_str(), span.line), ("List", 9));
+
+        let next = at(&references, "next", 2);
+        assert_eq!(describe(next, &classes).unwrap(), "field List next");
+        // only locals and arguments can be renamed
+        assert_eq!(rename(&references, next), None);
+    }
+
+    #[test]
+    fn completing() {
+        let class = parse(LIST).0.unwrap();
+        let classes = with_os(std::slice::from_ref(&class));
+        let labels = |source: &str, offset| {
+            completions(&classes, &class, source, offset)
+                .into_iter()
+                .map(|completion| completion.label)
+                .collect::<Vec<_>>()
+        };
+
+        let offset = LIST.find("next.sum").unwrap() + 5;
+        assert_eq!(labels(LIST, offset), ["sum"]);
+
+        let source = LIST.replace("let data = value;", "do Math.s");
+        let offset = source.find("Math.s").unwrap() + 6;
+        let math = labels(&source, offset);
+        assert!(math.contains(&"sqrt".to_string()));
+        assert!(!math.contains(&"sum".to_string()));
+
+        let offset = LIST.find("let total = data").unwrap();
+        let names = labels(LIST, offset);
+        assert_eq!(names[..3], ["data", "next", "total"]);
+        assert!(names.contains(&"Output".to_string()));
+    }
+}
@@ -0,0 +1,293 @@
+use super::token::{Comment, CommentKind, Dialect, Keyword, Token, SYMBOLS};
+use super::SyntaxError;
+use crate::span::{Span, Spanned};
+use std::str::FromStr;
+
+pub type LexResult = Result<Spanned<Token>, Spanned<SyntaxError>>;
+
+/// Splits a Jack program into tokens, including comments. After an error, lexing carries on from
+/// the next character that could start a token.
+pub fn lex(source: &str) -> Lexer<'_> {
+    lex_with(source, Dialect::Standard)
+}
+
+/// Splits a program written in `dialect` into tokens
+pub fn lex_with(source: &str, dialect: Dialect) -> Lexer<'_> {
+    Lexer {
+        source,
+        dialect,
+        offset: 0,
+        line: 1,
+        column: 1,
+    }
+}
+
+pub struct Lexer<'a> {
+    source: &'a str,
+    dialect: Dialect,
+    offset: usize,
+    line: u32,
+    column: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn rest(&self) -> &'a str {
+        &self.source[self.offset..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn bump_while(&mut self, mut f: impl FnMut(char) -> bool) -> &'a str {
+        let start = self.offset;
+        while self.peek().is_some_and(&mut f) {
+            self.bump();
+        }
+        &self.source[start..self.offset]
+    }
+
+    /// Reads the rest of a block comment whose opening `/*` has been read
+    fn block_comment(&mut self) -> Result<&'a str, SyntaxError> {
+        let start = self.offset;
+        let mut depth = 1;
+        loop {
+            let rest = self.rest();
+            if rest.starts_with("*/") {
+                depth -= 1;
+                if depth == 0 {
+                    let text = &self.source[start..self.offset];
+                    self.bump();
+                    self.bump();
+                    return Ok(text);
+                }
+                self.bump();
+            } else if rest.starts_with("/*") {
+                depth += 1;
+                self.bump();
+            }
+            self.bump().ok_or(SyntaxError::UnterminatedComment)?;
+        }
+    }
+
+    fn token(&mut self, c: char) -> Result<Token, SyntaxError> {
+        let rest = self.rest();
+        Ok(if let Some(comment) = rest.strip_prefix("//") {
+            self.bump_while(|c| c != '\n');
+            let text = comment.split('\n').next().unwrap_or_default();
+            Token::Comment(Comment {
+                kind: CommentKind::Line,
+                text: text.trim_end_matches('\r').to_string(),
+            })
+        } else if rest.starts_with("/*") {
+            // `/**/` is an empty block comment rather than the start of a doc comment
+            let doc = rest.starts_with("/**") && !rest.starts_with("/**/");
+            self.bump();
+            self.bump();
+            if doc {
+                self.bump();
+            }
+            let text = self.block_comment()?.to_string();
+            let kind = if doc {
+                CommentKind::Doc
+            } else {
+                CommentKind::Block
+            };
+            Token::Comment(Comment { kind, text })
+        } else if SYMBOLS.contains(c) {
+            self.bump();
+            Token::Symbol(c)
+        } else if c == '"' {
+            self.bump();
+            let text = self.bump_while(|c| c != '"' && c != '\n');
+            if self.peek() != Some('"') {
+                return Err(SyntaxError::UnterminatedString);
+            }
+            self.bump();
+            Token::String(text.to_string())
+        } else if c == '\'' && self.dialect == Dialect::Extended {
+            self.bump();
+            let text = self.bump_while(|c| c != '\'' && c != '\n');
+            if self.peek() != Some('\'') {
+                return Err(SyntaxError::UnterminatedChar);
+            }
+            self.bump();
+            let mut chars = text.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c.is_ascii() && !c.is_ascii_control() => Token::Char(c),
+                _ => return Err(SyntaxError::BadChar(text.to_string())),
+            }
+        } else if c.is_ascii_digit() {
+            let digits = self.bump_while(|c| c.is_ascii_alphanumeric() || c == '_');
+            let value = digits
+                .parse()
+                .map_err(|_| SyntaxError::BadNumber(digits.to_string()))?;
+            Token::Integer(value)
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let word = self.bump_while(|c| c.is_ascii_alphanumeric() || c == '_');
+            match Keyword::from_str(word) {
+                Ok(keyword) if !keyword.is_extended() || self.dialect == Dialect::Extended => {
+                    Token::Keyword(keyword)
+                }
+                _ => Token::Identifier(word.to_string()),
+            }
+        } else {
+            self.bump();
+            return Err(SyntaxError::UnexpectedCharacter(c));
+        })
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = LexResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.bump_while(char::is_whitespace);
+        let c = self.peek()?;
+        let (offset, line, column) = (self.offset, self.line, self.column);
+        let token = self.token(c);
+        let span = Span {
+            offset,
+            len: self.offset - offset,
+            line,
+            column,
+        };
+        Some(match token {
+            Ok(item) => Ok(Spanned { item, span }),
+            Err(item) => Err(Spanned { item, span }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tokens(source: &str) -> Vec<Token> {
+        lex(source).map(|res| res.unwrap().item).collect()
+    }
+
+    #[test]
+    fn tokens_and_spans() {
+        let source = "class Main {\n  /** doc */ field int x_1; // note\n  let s = \"a < b\";\n}";
+        let ident = |name: &str| Token::Identifier(name.to_string());
+        assert_eq!(
+            tokens(source),
+            [
+                Token::Keyword(Keyword::Class),
+                ident("Main"),
+                Token::Symbol('{'),
+                Token::Comment(Comment {
+                    kind: CommentKind::Doc,
+                    text: " doc ".to_string()
+                }),
+                Token::Keyword(Keyword::Field),
+                Token::Keyword(Keyword::Int),
+                ident("x_1"),
+                Token::Symbol(';'),
+                Token::Comment(Comment {
+                    kind: CommentKind::Line,
+                    text: " note".to_string()
+                }),
+                Token::Keyword(Keyword::Let),
+                ident("s"),
+                Token::Symbol('='),
+                Token::String("a < b".to_string()),
+                Token::Symbol(';'),
+                Token::Symbol('}'),
+            ]
+        );
+
+        // every token prints back out the way it was written
+        for token in lex(source).map(Result::unwrap) {
+            assert_eq!(token.span.of(source), token.item.to_string());
+        }
+        let string = lex(source).map(Result::unwrap).nth(12).unwrap();
+        assert_eq!((string.span.line, string.span.column), (3, 11));
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(
+            tokens("/* outer /* inner */ still outer */ 1 /**/ 2"),
+            [
+                Token::Comment(Comment {
+                    kind: CommentKind::Block,
+                    text: " outer /* inner */ still outer ".to_string()
+                }),
+                Token::Integer(1),
+                Token::Comment(Comment {
+                    kind: CommentKind::Block,
+                    text: "".to_string()
+                }),
+                Token::Integer(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let errors = lex("let x = 70000 # \"open\n/* never closed")
+            .filter_map(Result::err)
+            .map(|e| (e.item, e.span.line, e.span.column))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                (SyntaxError::BadNumber("70000".to_string()), 1, 9),
+                (SyntaxError::UnexpectedCharacter('#'), 1, 15),
+                (SyntaxError::UnterminatedString, 1, 17),
+                (SyntaxError::UnterminatedComment, 2, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn extended() {
+        let source = "for x 'a' ' '";
+        assert_eq!(
+            tokens("for x"),
+            [
+                Token::Identifier("for".to_string()),
+                Token::Identifier("x".to_string())
+            ]
+        );
+        assert!(lex(source).any(|token| token.is_err()));
+
+        let tokens = lex_with(source, Dialect::Extended)
+            .map(|token| token.unwrap().item)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            [
+                Token::Keyword(Keyword::For),
+                Token::Identifier("x".to_string()),
+                Token::Char('a'),
+                Token::Char(' ')
+            ]
+        );
+        let errors = lex_with("'ab' 'c", Dialect::Extended)
+            .filter_map(Result::err)
+            .map(|e| e.item)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                SyntaxError::BadChar("ab".to_string()),
+                SyntaxError::UnterminatedChar
+            ]
+        );
+    }
+}
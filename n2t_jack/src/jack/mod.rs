@@ -0,0 +1,26 @@
+pub mod ast;
+mod check;
+mod compile;
+mod error;
+mod format;
+pub mod ide;
+mod lex;
+mod optimize;
+mod os;
+mod parse;
+pub mod symbols;
+pub mod token;
+mod warn;
+mod xml;
+
+pub use check::check;
+pub use compile::compile;
+pub use error::{CompileError, SemanticError, SyntaxError};
+pub use format::{format, format_with};
+pub use lex::{lex, lex_with, LexResult, Lexer};
+pub use optimize::{optimize, optimize_vm, Optimizations};
+pub use os::{os_api, os_classes, os_vm, os_vm_with, OS_SOURCES};
+pub use parse::{parse, parse_with};
+pub use token::Dialect;
+pub use warn::{warnings, warnings_with, Warning};
+pub use xml::{class_xml, tokens_xml};
@@ -0,0 +1,782 @@
+use super::ast::{
+    BinaryOp, Class, Expression, KeywordConstant, Statement, Subroutine, SubroutineCall,
+    SubroutineKind, UnaryOp,
+};
+use crate::span::Spanned;
+use crate::translate::arithmetic::Arithmetic;
+use crate::translate::stack::Segment;
+use crate::vm::VmCommand;
+use std::collections::{HashMap, HashSet};
+
+/// The most nodes an expression can have for a function returning it to be inlined
+const MAX_INLINE_SIZE: usize = 8;
+
+/// Which optimizations the Jack compiler makes. Everything is off by default, so that the output
+/// matches the course's compiler.
+#[derive(Debug, Clone, Default)]
+pub struct Optimizations {
+    /// Replace arithmetic and comparisons of constants with their result
+    pub fold_constants: bool,
+    /// Multiply by powers of two with a chain of additions rather than a call to `Math.multiply`
+    pub reduce_strength: bool,
+    /// Replace `if` statements whose condition is constant with the branch taken, and remove
+    /// `while (false)` loops
+    pub simplify_branches: bool,
+    /// Remove assignments to local variables which are never read
+    pub remove_dead_stores: bool,
+    /// Replace calls to functions which only return a small expression of their parameters with
+    /// that expression
+    pub inline_functions: bool,
+}
+
+impl Optimizations {
+    pub fn all() -> Self {
+        Self::level(2)
+    }
+
+    /// The optimizations for an `-O` level. Level 1 rewrites expressions and drops branches which
+    /// can never run, and level 2 also removes dead stores and inlines functions.
+    pub fn level(level: u8) -> Self {
+        Self {
+            fold_constants: level >= 1,
+            reduce_strength: level >= 1,
+            simplify_branches: level >= 1,
+            remove_dead_stores: level >= 2,
+            inline_functions: level >= 2,
+        }
+    }
+}
+
+/// Optimizes the classes of a program before they are compiled. Functions are only inlined into
+/// calls from classes in the same slice.
+pub fn optimize(classes: &mut [Class], optimizations: &Optimizations) {
+    let inlinable = match optimizations.inline_functions {
+        true => inlinable(classes),
+        false => HashMap::new(),
+    };
+    for class in classes.iter_mut() {
+        let fields = class
+            .vars
+            .iter()
+            .flat_map(|dec| &dec.item.names)
+            .map(|name| name.item.clone());
+        let fields = fields.collect::<Vec<_>>();
+        for subroutine in &mut class.subroutines {
+            let subroutine = &mut subroutine.item;
+            let variables = subroutine
+                .parameters
+                .iter()
+                .map(|parameter| &parameter.name.item)
+                .chain(
+                    subroutine
+                        .vars
+                        .iter()
+                        .flat_map(|dec| &dec.item.names)
+                        .map(|name| &name.item),
+                )
+                .chain(&fields)
+                .cloned()
+                .collect();
+            let optimizer = Optimizer {
+                optimizations,
+                inlinable: &inlinable,
+                class: &class.name.item,
+                variables,
+            };
+            optimizer.statements(&mut subroutine.statements);
+            if optimizations.remove_dead_stores {
+                remove_dead_stores(subroutine);
+            }
+        }
+    }
+}
+
+/// Optimizes the VM code compiled from a class
+pub fn optimize_vm(
+    commands: Vec<Spanned<VmCommand>>,
+    optimizations: &Optimizations,
+) -> Vec<Spanned<VmCommand>> {
+    match optimizations.reduce_strength {
+        true => reduce_strength(commands),
+        false => commands,
+    }
+}
+
+/// A function which can be inlined, with the names of its parameters
+struct Inlinable {
+    parameters: Vec<String>,
+    value: Spanned<Expression>,
+}
+
+/// Finds the functions whose whole body is `return` of a small expression which only uses their
+/// parameters and constants
+fn inlinable(classes: &[Class]) -> HashMap<(String, String), Inlinable> {
+    let mut functions = HashMap::new();
+    for class in classes {
+        for subroutine in &class.subroutines {
+            let subroutine = &subroutine.item;
+            let [Spanned {
+                item: Statement::Return(Some(value)),
+                ..
+            }] = &subroutine.statements[..]
+            else {
+                continue;
+            };
+            let parameters = subroutine
+                .parameters
+                .iter()
+                .map(|parameter| parameter.name.item.clone())
+                .collect::<Vec<_>>();
+            if subroutine.kind == SubroutineKind::Function
+                && simple(&value.item, &parameters)
+                && size(&value.item) <= MAX_INLINE_SIZE
+            {
+                let key = (class.name.item.clone(), subroutine.name.item.clone());
+                let value = value.clone();
+                functions.insert(key, Inlinable { parameters, value });
+            }
+        }
+    }
+    functions
+}
+
+/// Whether an expression has no calls and only reads the given variables
+fn simple(expression: &Expression, parameters: &[String]) -> bool {
+    match expression {
+        Expression::Integer(_) | Expression::Char(_) => true,
+        Expression::Keyword(keyword) => *keyword != KeywordConstant::This,
+        Expression::Variable(name) => parameters.contains(name),
+        Expression::Index { name, index } => {
+            parameters.contains(&name.item) && simple(&index.item, parameters)
+        }
+        Expression::Parenthesized(inner) | Expression::Unary { operand: inner, .. } => {
+            simple(&inner.item, parameters)
+        }
+        Expression::Binary { left, right, .. } => {
+            simple(&left.item, parameters) && simple(&right.item, parameters)
+        }
+        Expression::String(_) | Expression::Call(_) => false,
+    }
+}
+
+/// How many nodes an expression has
+fn size(expression: &Expression) -> usize {
+    1 + match expression {
+        Expression::Index { index: inner, .. }
+        | Expression::Parenthesized(inner)
+        | Expression::Unary { operand: inner, .. } => size(&inner.item),
+        Expression::Binary { left, right, .. } => size(&left.item) + size(&right.item),
+        Expression::Call(call) => call.args.iter().map(|arg| size(&arg.item)).sum(),
+        _ => 0,
+    }
+}
+
+/// Whether evaluating an expression cannot have side effects
+fn pure(expression: &Expression) -> bool {
+    match expression {
+        Expression::Call(_) => false,
+        Expression::Index { index: inner, .. }
+        | Expression::Parenthesized(inner)
+        | Expression::Unary { operand: inner, .. } => pure(&inner.item),
+        Expression::Binary { left, right, .. } => pure(&left.item) && pure(&right.item),
+        _ => true,
+    }
+}
+
+/// The value of an expression, if it is a constant
+fn constant(expression: &Expression) -> Option<i16> {
+    match expression {
+        Expression::Integer(value) => Some(*value as i16),
+        Expression::Char(c) => Some(*c as i16),
+        Expression::Keyword(KeywordConstant::True) => Some(-1),
+        Expression::Keyword(KeywordConstant::False) => Some(0),
+        Expression::Parenthesized(inner) => constant(&inner.item),
+        _ => None,
+    }
+}
+
+fn boolean(value: bool) -> Expression {
+    match value {
+        true => Expression::Keyword(KeywordConstant::True),
+        false => Expression::Keyword(KeywordConstant::False),
+    }
+}
+
+/// Evaluates an operation on constants. Division by zero is left for the OS to report, as is
+/// division involving -32768, whose absolute value `Math.divide` cannot hold.
+fn evaluate(op: BinaryOp, a: i16, b: i16) -> Option<Expression> {
+    let value = match op {
+        BinaryOp::Add => a.wrapping_add(b),
+        BinaryOp::Sub => a.wrapping_sub(b),
+        BinaryOp::Mul => a.wrapping_mul(b),
+        BinaryOp::Div if b == 0 || a == i16::MIN || b == i16::MIN => return None,
+        BinaryOp::Div => a.wrapping_div(b),
+        BinaryOp::And => a & b,
+        BinaryOp::Or => a | b,
+        BinaryOp::Lt => return Some(boolean(a < b)),
+        BinaryOp::Gt => return Some(boolean(a > b)),
+        BinaryOp::Eq => return Some(boolean(a == b)),
+    };
+    Some(Expression::Integer(value as u16))
+}
+
+struct Optimizer<'a> {
+    optimizations: &'a Optimizations,
+    inlinable: &'a HashMap<(String, String), Inlinable>,
+    class: &'a str,
+    /// The names of every variable the subroutine can see, which hide classes of the same name
+    variables: HashSet<String>,
+}
+
+impl Optimizer<'_> {
+    fn statements(&self, statements: &mut Vec<Spanned<Statement>>) {
+        for mut statement in std::mem::take(statements) {
+            self.statement(&mut statement.item);
+            match statement.item {
+                Statement::If {
+                    condition,
+                    then,
+                    otherwise,
+                } if self.optimizations.simplify_branches
+                    && constant(&condition.item).is_some() =>
+                {
+                    match constant(&condition.item) {
+                        Some(0) => statements.extend(otherwise.unwrap_or_default()),
+                        _ => statements.extend(then),
+                    }
+                }
+                Statement::While { ref condition, .. }
+                    if self.optimizations.simplify_branches
+                        && constant(&condition.item) == Some(0) => {}
+                item => statements.push(Spanned {
+                    item,
+                    span: statement.span,
+                }),
+            }
+        }
+    }
+
+    fn statement(&self, statement: &mut Statement) {
+        match statement {
+            Statement::Let { index, value, .. } => {
+                if let Some(index) = index {
+                    self.expression(index);
+                }
+                self.expression(value);
+            }
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.expression(condition);
+                self.statements(then);
+                if let Some(otherwise) = otherwise {
+                    self.statements(otherwise);
+                }
+            }
+            Statement::While { condition, body } => {
+                self.expression(condition);
+                self.statements(body);
+            }
+            Statement::For {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.statement(&mut init.item);
+                }
+                if let Some(condition) = condition {
+                    self.expression(condition);
+                }
+                if let Some(update) = update {
+                    self.statement(&mut update.item);
+                }
+                self.statements(body);
+            }
+            Statement::Break | Statement::Continue => (),
+            Statement::Do(call) => call.args.iter_mut().for_each(|arg| self.expression(arg)),
+            Statement::Return(value) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+        }
+    }
+
+    fn expression(&self, expression: &mut Spanned<Expression>) {
+        match &mut expression.item {
+            Expression::Index { index: inner, .. }
+            | Expression::Parenthesized(inner)
+            | Expression::Unary { operand: inner, .. } => self.expression(inner),
+            Expression::Binary { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expression::Call(call) => call.args.iter_mut().for_each(|arg| self.expression(arg)),
+            _ => (),
+        }
+
+        if let Expression::Call(call) = &expression.item {
+            if let Some(inlined) = self.inline(call) {
+                expression.item = inlined;
+                // the arguments may have made more of the function's expression constant
+                self.expression(expression);
+            }
+        }
+        if self.optimizations.fold_constants {
+            fold(&mut expression.item);
+        }
+    }
+
+    /// The expression a call can be replaced with, if it calls an inlinable function
+    fn inline(&self, call: &SubroutineCall) -> Option<Expression> {
+        let class = match &call.receiver {
+            Some(receiver) if self.variables.contains(&receiver.item) => return None,
+            Some(receiver) => &receiver.item,
+            None => self.class,
+        };
+        let key = (class.to_string(), call.name.item.clone());
+        let function = self.inlinable.get(&key)?;
+        if function.parameters.len() != call.args.len() {
+            return None;
+        }
+        // arguments are evaluated once per use, so they must not have effects, and only variables
+        // can stand in for arrays
+        let substitutable = function
+            .parameters
+            .iter()
+            .zip(&call.args)
+            .all(|(parameter, arg)| {
+                let (count, indexed) = uses(&function.value.item, parameter);
+                match &arg.item {
+                    Expression::Variable(_) => true,
+                    _ if indexed => false,
+                    arg if count > 1 => {
+                        matches!(
+                            arg,
+                            Expression::Integer(_) | Expression::Char(_) | Expression::Keyword(_)
+                        )
+                    }
+                    arg => pure(arg),
+                }
+            });
+        if !substitutable {
+            return None;
+        }
+
+        let mut value = function.value.clone();
+        substitute(&mut value, &function.parameters, &call.args);
+        Some(Expression::Parenthesized(Box::new(value)))
+    }
+}
+
+/// How many times an expression reads a variable, and whether it indexes it as an array
+fn uses(expression: &Expression, variable: &str) -> (usize, bool) {
+    match expression {
+        Expression::Variable(name) => ((name == variable) as usize, false),
+        Expression::Index { name, index } => {
+            let (count, indexed) = uses(&index.item, variable);
+            match name.item == variable {
+                true => (count + 1, true),
+                false => (count, indexed),
+            }
+        }
+        Expression::Parenthesized(inner) | Expression::Unary { operand: inner, .. } => {
+            uses(&inner.item, variable)
+        }
+        Expression::Binary { left, right, .. } => {
+            let (left, left_indexed) = uses(&left.item, variable);
+            let (right, right_indexed) = uses(&right.item, variable);
+            (left + right, left_indexed || right_indexed)
+        }
+        _ => (0, false),
+    }
+}
+
+/// Replaces the parameters in an inlined function's expression with the arguments of the call
+fn substitute(
+    expression: &mut Spanned<Expression>,
+    parameters: &[String],
+    args: &[Spanned<Expression>],
+) {
+    let argument = |name: &str| &args[parameters.iter().position(|p| p == name).unwrap()].item;
+    match &mut expression.item {
+        Expression::Variable(name) => {
+            expression.item = Expression::Parenthesized(Box::new(Spanned {
+                item: argument(name).clone(),
+                span: expression.span,
+            }))
+        }
+        Expression::Index { name, index } => {
+            substitute(index, parameters, args);
+            if let Expression::Variable(array) = argument(&name.item) {
+                name.item = array.clone();
+            }
+        }
+        Expression::Parenthesized(inner) | Expression::Unary { operand: inner, .. } => {
+            substitute(inner, parameters, args)
+        }
+        Expression::Binary { left, right, .. } => {
+            substitute(left, parameters, args);
+            substitute(right, parameters, args);
+        }
+        _ => (),
+    }
+}
+
+/// Folds an expression whose operands have already been folded
+fn fold(expression: &mut Expression) {
+    let folded = match expression {
+        Expression::Parenthesized(inner) => match &inner.item {
+            Expression::Integer(_)
+            | Expression::Char(_)
+            | Expression::Keyword(_)
+            | Expression::Variable(_) => Some(inner.item.clone()),
+            _ => None,
+        },
+        Expression::Unary { op, operand } => constant(&operand.item).map(|value| match op {
+            UnaryOp::Neg => Expression::Integer(value.wrapping_neg() as u16),
+            UnaryOp::Not => Expression::Integer(!value as u16),
+        }),
+        Expression::Binary { op, left, right } => {
+            match (constant(&left.item), constant(&right.item)) {
+                (Some(a), Some(b)) => evaluate(*op, a, b),
+                // strength reduction looks for the constant on the right
+                (Some(_), None) if *op == BinaryOp::Mul && pure(&right.item) => {
+                    std::mem::swap(left, right);
+                    None
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    if let Some(folded) = folded {
+        *expression = folded;
+    }
+}
+
+/// Adds the names of the variables an expression reads
+fn expression_reads(expression: &Expression, names: &mut HashSet<String>) {
+    match expression {
+        Expression::Variable(name) => {
+            names.insert(name.clone());
+        }
+        Expression::Index { name, index } => {
+            names.insert(name.item.clone());
+            expression_reads(&index.item, names);
+        }
+        Expression::Parenthesized(inner) | Expression::Unary { operand: inner, .. } => {
+            expression_reads(&inner.item, names)
+        }
+        Expression::Binary { left, right, .. } => {
+            expression_reads(&left.item, names);
+            expression_reads(&right.item, names);
+        }
+        Expression::Call(call) => call_reads(call, names),
+        _ => (),
+    }
+}
+
+fn call_reads(call: &SubroutineCall, names: &mut HashSet<String>) {
+    if let Some(receiver) = &call.receiver {
+        names.insert(receiver.item.clone());
+    }
+    for arg in &call.args {
+        expression_reads(&arg.item, names);
+    }
+}
+
+/// Adds the names of the variables some statements read
+fn reads(statements: &[Spanned<Statement>], names: &mut HashSet<String>) {
+    for statement in statements {
+        match &statement.item {
+            Statement::Let { name, index, value } => {
+                if let Some(index) = index {
+                    // storing into an array reads the variable which holds it
+                    names.insert(name.item.clone());
+                    expression_reads(&index.item, names);
+                }
+                expression_reads(&value.item, names);
+            }
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                expression_reads(&condition.item, names);
+                reads(then, names);
+                reads(otherwise.as_deref().unwrap_or_default(), names);
+            }
+            Statement::While { condition, body } => {
+                expression_reads(&condition.item, names);
+                reads(body, names);
+            }
+            Statement::For {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                for statement in init.iter().chain(update) {
+                    reads(std::slice::from_ref(statement), names);
+                }
+                if let Some(condition) = condition {
+                    expression_reads(&condition.item, names);
+                }
+                reads(body, names);
+            }
+            Statement::Break | Statement::Continue => (),
+            Statement::Do(call) => call_reads(call, names),
+            Statement::Return(value) => {
+                if let Some(value) = value {
+                    expression_reads(&value.item, names);
+                }
+            }
+        }
+    }
+}
+
+fn remove_dead_stores(subroutine: &mut Subroutine) {
+    let mut read = HashSet::new();
+    reads(&subroutine.statements, &mut read);
+    let dead = subroutine
+        .vars
+        .iter()
+        .flat_map(|dec| &dec.item.names)
+        .map(|name| name.item.clone())
+        .filter(|name| !read.contains(name))
+        .collect::<HashSet<_>>();
+    if !dead.is_empty() {
+        remove_stores(&mut subroutine.statements, &dead);
+    }
+}
+
+fn remove_stores(statements: &mut Vec<Spanned<Statement>>, dead: &HashSet<String>) {
+    for mut statement in std::mem::take(statements) {
+        match &mut statement.item {
+            Statement::Let {
+                name,
+                index: None,
+                value,
+            } if dead.contains(&name.item) => match &value.item {
+                // the value is still needed for what the call does
+                Expression::Call(call) => statement.item = Statement::Do(call.clone()),
+                value if pure(value) => continue,
+                _ => (),
+            },
+            Statement::If {
+                then, otherwise, ..
+            } => {
+                remove_stores(then, dead);
+                if let Some(otherwise) = otherwise {
+                    remove_stores(otherwise, dead);
+                }
+            }
+            Statement::While { body, .. } | Statement::For { body, .. } => {
+                remove_stores(body, dead)
+            }
+            _ => (),
+        }
+        statements.push(statement);
+    }
+}
+
+/// Replaces calls to `Math.multiply` by powers of two with additions of the other operand, which
+/// is kept in `temp 1`. Dividing by powers of two would need shifts to the right, which the Hack
+/// ALU cannot do in less code than the call, so only division by 1 is removed.
+fn reduce_strength(commands: Vec<Spanned<VmCommand>>) -> Vec<Spanned<VmCommand>> {
+    let mut reduced: Vec<Spanned<VmCommand>> = Vec::with_capacity(commands.len());
+    for command in commands {
+        let factor = match (&command.item, reduced.last()) {
+            (
+                VmCommand::Call { name, args: 2 },
+                Some(Spanned {
+                    item: VmCommand::Push(Segment::Constant, value),
+                    ..
+                }),
+            ) if name == "Math.multiply" && value.is_power_of_two() => Some(*value),
+            (
+                VmCommand::Call { name, args: 2 },
+                Some(Spanned {
+                    item: VmCommand::Push(Segment::Constant, 1),
+                    ..
+                }),
+            ) if name == "Math.divide" => Some(1),
+            _ => None,
+        };
+        let Some(factor) = factor else {
+            reduced.push(command);
+            continue;
+        };
+
+        reduced.pop();
+        let span = command.span;
+        let mut emit = |item| reduced.push(Spanned { item, span });
+        for _ in 0..factor.trailing_zeros() {
+            emit(VmCommand::Pop(Segment::Temp, 1));
+            emit(VmCommand::Push(Segment::Temp, 1));
+            emit(VmCommand::Push(Segment::Temp, 1));
+            emit(VmCommand::Arithmetic(Arithmetic::Add));
+        }
+    }
+    reduced
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jack::{compile, parse};
+
+    fn compiled(source: &str, optimizations: Optimizations) -> Vec<String> {
+        let mut classes = [parse(source).0.unwrap()];
+        optimize(&mut classes, &optimizations);
+        let commands = optimize_vm(compile(&classes[0]).unwrap(), &optimizations);
+        commands.iter().map(|c| c.item.to_string()).collect()
+    }
+
+    #[test]
+    fn folding() {
+        let source = "class Main {
+    function int f(int x) {
+        return (2 + 3 * 4) + x + (~(1 = 1) | (3 > 2)) + (-7 / 2) + (1 / 0)
+            + ((-32767 - 1) / 2);
+    }
+}";
+        let optimizations = Optimizations {
+            fold_constants: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            compiled(source, optimizations),
+            [
+                "function Main.f 0",
+                "push constant 20",
+                "push argument 0",
+                "add",
+                "push constant 0",
+                "not",
+                "add",
+                "push constant 2",
+                "not",
+                "add",
+                "push constant 1",
+                "push constant 0",
+                "call Math.divide 2",
+                "add",
+                "push constant 32767",
+                "not",
+                "push constant 2",
+                "call Math.divide 2",
+                "add",
+                "return"
+            ]
+        );
+    }
+
+    #[test]
+    fn strength() {
+        let source = "class Main {
+    function int f(int x) {
+        return (8 * x) + (x * 1) + (x / 1) + (x * 3) + (x / 2);
+    }
+}";
+        let optimizations = Optimizations::level(1);
+        let double = ["pop temp 1", "push temp 1", "push temp 1", "add"];
+        let mut expected = vec!["function Main.f 0", "push argument 0"];
+        expected.extend(double.repeat(3));
+        expected.extend([
+            "push argument 0",
+            "add",
+            "push argument 0",
+            "add",
+            "push argument 0",
+            "push constant 3",
+            "call Math.multiply 2",
+            "add",
+            "push argument 0",
+            "push constant 2",
+            "call Math.divide 2",
+            "add",
+            "return",
+        ]);
+        assert_eq!(compiled(source, optimizations), expected);
+    }
+
+    #[test]
+    fn branches_and_dead_stores() {
+        let source = "class Main {
+    function void f() {
+        var int unused, used;
+        if (1 < 2) { let used = 1; } else { do Sys.halt(); }
+        while (false) { do Sys.halt(); }
+        let unused = used + 1;
+        let unused = Main.g();
+        do Output.printInt(used);
+        return;
+    }
+}";
+        assert_eq!(
+            compiled(source, Optimizations::level(2)),
+            [
+                "function Main.f 2",
+                "push constant 1",
+                "pop local 1",
+                "call Main.g 0",
+                "pop temp 0",
+                "push local 1",
+                "call Output.printInt 1",
+                "pop temp 0",
+                "push constant 0",
+                "return"
+            ]
+        );
+    }
+
+    #[test]
+    fn inlining() {
+        let source = "class Main {
+    field int size;
+    function int twice(int x) { return x + x; }
+    function int first(Array a) { return a[0]; }
+    method int f(Array b) {
+        return twice(size) + Main.first(b) + twice(1 + b) + Main.twice(Main.g()) + twice(3);
+    }
+}";
+        let commands = compiled(source, Optimizations::level(2));
+        let f = commands
+            .iter()
+            .position(|c| c == "function Main.f 0")
+            .unwrap();
+        // arguments which are used twice are only copied if they are variables or constants, and
+        // calls stay where they are
+        assert_eq!(
+            commands[f + 3..],
+            [
+                "push this 0",
+                "push this 0",
+                "add",
+                "push argument 1",
+                "push constant 0",
+                "add",
+                "pop pointer 1",
+                "push that 0",
+                "add",
+                "push constant 1",
+                "push argument 1",
+                "add",
+                "call Main.twice 1",
+                "add",
+                "call Main.g 0",
+                "call Main.twice 1",
+                "add",
+                "push constant 6",
+                "add",
+                "return"
+            ]
+        );
+    }
+}
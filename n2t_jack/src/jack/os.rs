@@ -0,0 +1,157 @@
+use super::ast::Class;
+use super::{compile, optimize, optimize_vm, parse, Optimizations};
+use crate::span::Spanned;
+use crate::vm::{VmCommand, OS_FUNCTIONS};
+
+/// The name and Jack source of each class of the Jack OS
+pub const OS_SOURCES: [(&str, &str); 8] = [
+    ("Array", include_str!("os/Array.jack")),
+    ("Keyboard", include_str!("os/Keyboard.jack")),
+    ("Math", include_str!("os/Math.jack")),
+    ("Memory", include_str!("os/Memory.jack")),
+    ("Output", include_str!("os/Output.jack")),
+    ("Screen", include_str!("os/Screen.jack")),
+    ("String", include_str!("os/String.jack")),
+    ("Sys", include_str!("os/Sys.jack")),
+];
+
+/// The classes of the Jack OS
+pub fn os_classes() -> Vec<Class> {
+    OS_SOURCES
+        .iter()
+        .map(|(_, source)| parse(source).0.expect("the OS is valid Jack"))
+        .collect()
+}
+
+/// The classes of the Jack OS with only the subroutines of its official API, which programs may
+/// call. The bundled implementation's helpers, like `Math.divideAbs`, are left out.
+pub fn os_api() -> Vec<Class> {
+    let mut classes = os_classes();
+    for class in &mut classes {
+        let name = &class.name.item;
+        class.subroutines.retain(|subroutine| {
+            let full_name = format!("{name}.{}", subroutine.item.name.item);
+            OS_FUNCTIONS.iter().any(|(os, _)| *os == full_name)
+        });
+    }
+    classes
+}
+
+/// The Jack OS compiled to VM code, with the name of each class. These are the files which the
+/// course's projects copy next to a program's own .vm files.
+pub fn os_vm() -> Vec<(&'static str, Vec<Spanned<VmCommand>>)> {
+    os_vm_with(&Optimizations::default())
+}
+
+/// The Jack OS compiled to VM code with the given optimizations
+pub fn os_vm_with(optimizations: &Optimizations) -> Vec<(&'static str, Vec<Spanned<VmCommand>>)> {
+    let mut classes = os_classes();
+    optimize(&mut classes, optimizations);
+    OS_SOURCES
+        .iter()
+        .zip(classes)
+        .map(|((name, _), class)| {
+            let commands = compile(&class).expect("the OS compiles");
+            (*name, optimize_vm(commands, optimizations))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jack::ast::SubroutineKind;
+    use crate::jack::{check, warnings};
+    use crate::vm::{link, VmMachine};
+
+    const MAIN: &str = "class Main {
+    function void main() {
+        var Array a, b, c, d;
+        var String s;
+        do Memory.poke(8000, Math.multiply(-7, 13));
+        do Memory.poke(8001, 100 / -7);
+        do Memory.poke(8002, Math.sqrt(30000));
+        let s = String.new(6);
+        do s.setInt(-32767 - 1);
+        do Memory.poke(8003, s.length());
+        do Memory.poke(8004, s.intValue());
+        let a = Array.new(3);
+        let b = Array.new(3);
+        let c = Array.new(3);
+        do a.dispose();
+        do c.dispose();
+        do b.dispose();
+        let d = Array.new(3);
+        do Memory.poke(8005, d = a);
+        do Memory.poke(8006, Math.divide(-32767 - 1, 2));
+        do Memory.poke(8007, Math.divide(-32767 - 1, -3));
+        do Output.printString(\"Hi\");
+        return;
+    }
+}";
+
+    #[test]
+    fn check_os() {
+        let main = parse(MAIN).0.unwrap();
+        let classes = os_classes().into_iter().chain([main]).collect::<Vec<_>>();
+        assert!(check(&classes).iter().all(Vec::is_empty));
+        for (name, source) in OS_SOURCES {
+            let class = parse(source).0.unwrap();
+            assert_eq!(warnings(source, &class), [], "{name}");
+        }
+    }
+
+    #[test]
+    fn api() {
+        let mut api = os_api()
+            .iter()
+            .flat_map(|class| {
+                class.subroutines.iter().map(|subroutine| {
+                    let subroutine = &subroutine.item;
+                    let this = (subroutine.kind == SubroutineKind::Method) as u16;
+                    let name = format!("{}.{}", class.name.item, subroutine.name.item);
+                    (name, subroutine.parameters.len() as u16 + this)
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut expected = OS_FUNCTIONS
+            .iter()
+            .map(|(name, args)| (name.to_string(), *args))
+            .collect::<Vec<_>>();
+        api.sort();
+        expected.sort();
+        assert_eq!(api, expected);
+    }
+
+    #[test]
+    fn run_os() {
+        run_main(Optimizations::default());
+    }
+
+    #[test]
+    fn run_optimized_os() {
+        run_main(Optimizations::all());
+    }
+
+    fn run_main(optimizations: Optimizations) {
+        let mut main = [parse(MAIN).0.unwrap()];
+        optimize(&mut main, &optimizations);
+        let main = optimize_vm(compile(&main[0]).unwrap(), &optimizations);
+        let files = os_vm_with(&optimizations)
+            .into_iter()
+            .map(|(_, commands)| commands);
+        let mut vm = VmMachine::new(link(files.chain([main])));
+        vm.bootstrap().unwrap();
+        while vm.call_stack().last().unwrap().function != "Sys.halt" {
+            vm.run(1000).unwrap();
+        }
+
+        let ram = vm.ram();
+        assert_eq!(
+            ram[8000..8008],
+            [-91, -14, 173, 6, -32768, -1, -16384, 10922]
+        );
+        // 'H' and 'i' share the first word of the screen
+        assert_eq!(ram[16384], 51 | (12 << 8));
+    }
+}
@@ -0,0 +1,929 @@
+use super::ast::*;
+use super::token::{Dialect, Keyword, Token};
+use super::{lex_with, SyntaxError};
+use crate::span::{Span, Spanned};
+
+/// The error has already been recorded, and the caller should recover
+type ParseResult<T> = Result<T, ()>;
+
+/// Parses a Jack class, returning every syntax error found. Parsing carries on after an error by
+/// skipping to the next statement or declaration, so the class may be missing parts when there are
+/// errors. It is `None` if not even the class name could be read.
+pub fn parse(source: &str) -> (Option<Class>, Vec<Spanned<SyntaxError>>) {
+    parse_with(source, Dialect::Standard)
+}
+
+/// Parses a Jack class written in `dialect`
+pub fn parse_with(source: &str, dialect: Dialect) -> (Option<Class>, Vec<Spanned<SyntaxError>>) {
+    let mut errors = Vec::new();
+    let tokens = lex_with(source, dialect)
+        .filter_map(|token| match token {
+            Ok(token) if token.item.is_comment() => None,
+            Ok(token) => Some(token),
+            Err(e) => {
+                errors.push(e);
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let end = tokens.last().map_or(
+        Span {
+            line: 1,
+            column: 1,
+            ..Span::default()
+        },
+        |last| Span {
+            offset: last.span.offset + last.span.len,
+            len: 0,
+            column: last.span.column + last.span.len,
+            ..last.span
+        },
+    );
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        errors,
+        end,
+        dialect,
+        loops: 0,
+    };
+    let class = parser.class().ok();
+    parser.errors.sort_by_key(|e| e.span.offset);
+    (class, parser.errors)
+}
+
+fn starts_statement(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Keyword(
+            Keyword::Let
+                | Keyword::If
+                | Keyword::While
+                | Keyword::Do
+                | Keyword::Return
+                | Keyword::For
+                | Keyword::Break
+                | Keyword::Continue
+        )
+    )
+}
+
+fn starts_member(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Keyword(
+            Keyword::Static
+                | Keyword::Field
+                | Keyword::Const
+                | Keyword::Constructor
+                | Keyword::Function
+                | Keyword::Method
+        )
+    )
+}
+
+struct Parser {
+    tokens: Vec<Spanned<Token>>,
+    pos: usize,
+    errors: Vec<Spanned<SyntaxError>>,
+    /// An empty span just after the last token
+    end: Span,
+    dialect: Dialect,
+    /// How many loops the statement being read is inside
+    loops: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|token| &token.item)
+    }
+
+    /// Whether the next tokens are the start of a call, like `f(` or `a.f(`
+    fn starts_call(&self) -> bool {
+        let token = |i: usize| self.tokens.get(self.pos + i).map(|token| &token.item);
+        matches!(
+            (token(1), token(3)),
+            (Some(Token::Symbol('(')), _) | (Some(Token::Symbol('.')), Some(Token::Symbol('(')))
+        )
+    }
+
+    /// The span of the next token
+    fn span(&self) -> Span {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.end, |token| token.span)
+    }
+
+    fn bump(&mut self) -> Option<&Spanned<Token>> {
+        let token = self.tokens.get(self.pos)?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    /// Wraps an item in a span from `start` to the end of the last token read
+    fn spanned<T>(&self, start: Span, item: T) -> Spanned<T> {
+        let last = self
+            .pos
+            .checked_sub(1)
+            .map_or(start, |i| self.tokens[i].span);
+        let span = Span {
+            len: (last.offset + last.len).saturating_sub(start.offset),
+            ..start
+        };
+        Spanned { item, span }
+    }
+
+    fn error<T>(&mut self, expected: &'static str) -> ParseResult<T> {
+        let found = match self.peek() {
+            Some(token) => format!("`{token}`"),
+            None => "the end of the file".to_string(),
+        };
+        self.errors.push(Spanned {
+            item: SyntaxError::Expected { expected, found },
+            span: self.span(),
+        });
+        Err(())
+    }
+
+    fn eat_symbol(&mut self, symbol: char) -> bool {
+        let found = self.peek() == Some(&Token::Symbol(symbol));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_symbol(&mut self, symbol: char, expected: &'static str) -> ParseResult<()> {
+        match self.eat_symbol(symbol) {
+            true => Ok(()),
+            false => self.error(expected),
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: Keyword, expected: &'static str) -> ParseResult<()> {
+        if self.peek() == Some(&Token::Keyword(keyword)) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.error(expected)
+        }
+    }
+
+    fn identifier(&mut self, expected: &'static str) -> ParseResult<Name> {
+        match self.tokens.get(self.pos) {
+            Some(Spanned {
+                item: Token::Identifier(name),
+                span,
+            }) => {
+                let name = Spanned {
+                    item: name.clone(),
+                    span: *span,
+                };
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => self.error(expected),
+        }
+    }
+
+    /// Skips past the end of a broken statement
+    fn recover_statement(&mut self) {
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Symbol(';') => {
+                    self.pos += 1;
+                    return;
+                }
+                Token::Symbol('}') => return,
+                token if starts_statement(token) => return,
+                _ => self.pos += 1,
+            }
+        }
+    }
+
+    /// Skips to the next class variable or subroutine
+    fn recover_member(&mut self) {
+        while self.peek().is_some_and(|token| !starts_member(token)) {
+            self.pos += 1;
+        }
+    }
+
+    fn class(&mut self) -> ParseResult<Class> {
+        self.expect_keyword(Keyword::Class, "`class`")?;
+        let name = self.identifier("a class name")?;
+        self.expect_symbol('{', "`{`")?;
+
+        let mut class = Class {
+            name,
+            vars: Vec::new(),
+            subroutines: Vec::new(),
+        };
+        let mut recovered = false;
+        loop {
+            let start = self.span();
+            match self.peek() {
+                Some(Token::Keyword(Keyword::Static | Keyword::Field | Keyword::Const)) => {
+                    match self.class_var_dec() {
+                        Ok(var) => class.vars.push(self.spanned(start, var)),
+                        Err(()) => {
+                            self.recover_member();
+                            recovered = true;
+                        }
+                    }
+                }
+                Some(token) if starts_member(token) => match self.subroutine() {
+                    Ok(subroutine) => class.subroutines.push(self.spanned(start, subroutine)),
+                    Err(()) => {
+                        self.recover_member();
+                        recovered = true;
+                    }
+                },
+                Some(Token::Symbol('}')) => {
+                    self.pos += 1;
+                    break;
+                }
+                // the class's `}` was probably skipped while recovering, so don't report it again
+                None if recovered => break,
+                _ => {
+                    let _ = self.error::<()>("a class variable, a subroutine, or `}`");
+                    if self.bump().is_none() {
+                        break;
+                    }
+                    self.recover_member();
+                    recovered = true;
+                }
+            }
+        }
+
+        if let Some(token) = self.tokens.get(self.pos) {
+            self.errors.push(Spanned {
+                item: SyntaxError::Trailing(token.item.to_string()),
+                span: token.span,
+            });
+        }
+        Ok(class)
+    }
+
+    fn ty(&mut self, expected: &'static str) -> ParseResult<Spanned<Type>> {
+        let start = self.span();
+        let ty = match self.peek() {
+            Some(Token::Keyword(Keyword::Int)) => Type::Int,
+            Some(Token::Keyword(Keyword::Char)) => Type::Char,
+            Some(Token::Keyword(Keyword::Boolean)) => Type::Boolean,
+            Some(Token::Identifier(name)) => Type::Class(name.clone()),
+            _ => return self.error(expected),
+        };
+        self.pos += 1;
+        Ok(self.spanned(start, ty))
+    }
+
+    /// Reads names separated by commas, followed by a `;`
+    fn names(&mut self) -> ParseResult<Vec<Name>> {
+        let mut names = vec![self.identifier("a variable name")?];
+        while self.eat_symbol(',') {
+            names.push(self.identifier("a variable name")?);
+        }
+        self.expect_symbol(';', "`,` or `;`")?;
+        Ok(names)
+    }
+
+    fn class_var_dec(&mut self) -> ParseResult<ClassVarDec> {
+        let kind = match self.bump().map(|token| &token.item) {
+            Some(Token::Keyword(Keyword::Static)) => ClassVarKind::Static,
+            Some(Token::Keyword(Keyword::Const)) => ClassVarKind::Const,
+            _ => ClassVarKind::Field,
+        };
+        let ty = self.ty("a type")?;
+        if kind != ClassVarKind::Const {
+            let names = self.names()?;
+            return Ok(ClassVarDec {
+                kind,
+                ty,
+                names,
+                value: None,
+            });
+        }
+
+        let name = self.identifier("a constant name")?;
+        self.expect_symbol('=', "`=`")?;
+        let value = self.expression()?;
+        self.expect_symbol(';', "`;`")?;
+        Ok(ClassVarDec {
+            kind,
+            ty,
+            names: vec![name],
+            value: Some(value),
+        })
+    }
+
+    fn subroutine(&mut self) -> ParseResult<Subroutine> {
+        let kind = match self.bump().map(|token| &token.item) {
+            Some(Token::Keyword(Keyword::Constructor)) => SubroutineKind::Constructor,
+            Some(Token::Keyword(Keyword::Function)) => SubroutineKind::Function,
+            _ => SubroutineKind::Method,
+        };
+        let return_type = match self.peek() {
+            Some(Token::Keyword(Keyword::Void)) => {
+                let start = self.span();
+                self.pos += 1;
+                self.spanned(start, None)
+            }
+            _ => {
+                let ty = self.ty("a return type")?;
+                Spanned {
+                    item: Some(ty.item),
+                    span: ty.span,
+                }
+            }
+        };
+        let name = self.identifier("a subroutine name")?;
+
+        self.expect_symbol('(', "`(`")?;
+        let mut parameters = Vec::new();
+        if self.peek() != Some(&Token::Symbol(')')) {
+            loop {
+                let ty = self.ty("a parameter type")?;
+                let name = self.identifier("a parameter name")?;
+                parameters.push(Parameter { ty, name });
+                if !self.eat_symbol(',') {
+                    break;
+                }
+            }
+        }
+        self.expect_symbol(')', "`,` or `)`")?;
+
+        self.expect_symbol('{', "`{`")?;
+        let mut vars = Vec::new();
+        while self.peek() == Some(&Token::Keyword(Keyword::Var)) {
+            let start = self.span();
+            self.pos += 1;
+            match self.ty("a type").and_then(|ty| {
+                Ok(VarDec {
+                    ty,
+                    names: self.names()?,
+                })
+            }) {
+                Ok(var) => vars.push(self.spanned(start, var)),
+                Err(()) => self.recover_statement(),
+            }
+        }
+        let statements = self.statements();
+        self.expect_symbol('}', "`}`")?;
+
+        Ok(Subroutine {
+            kind,
+            return_type,
+            name,
+            parameters,
+            vars,
+            statements,
+        })
+    }
+
+    /// Reads statements up to a `}` or the end of the file, skipping any which are broken
+    fn statements(&mut self) -> Vec<Spanned<Statement>> {
+        let mut statements = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some(Token::Symbol('}')) => return statements,
+                Some(token) if starts_statement(token) => {
+                    let start = self.span();
+                    match self.statement() {
+                        Ok(statement) => statements.push(self.spanned(start, statement)),
+                        Err(()) => self.recover_statement(),
+                    }
+                }
+                // a call with its `do` left out, which the warnings point out
+                Some(Token::Identifier(_)) if self.starts_call() => {
+                    let start = self.span();
+                    let call = self.identifier("a subroutine name").and_then(|name| {
+                        let call = self.call(name)?;
+                        self.expect_symbol(';', "`;`")?;
+                        Ok(call)
+                    });
+                    match call {
+                        Ok(call) => statements.push(self.spanned(start, Statement::Do(call))),
+                        Err(()) => self.recover_statement(),
+                    }
+                }
+                Some(_) => {
+                    let _ = self.error::<()>("a statement");
+                    self.pos += 1;
+                    self.recover_statement();
+                }
+            }
+        }
+    }
+
+    fn block(&mut self) -> ParseResult<Vec<Spanned<Statement>>> {
+        self.expect_symbol('{', "`{`")?;
+        let statements = self.statements();
+        self.expect_symbol('}', "`}`")?;
+        Ok(statements)
+    }
+
+    fn condition(&mut self) -> ParseResult<Spanned<Expression>> {
+        self.expect_symbol('(', "`(`")?;
+        let condition = self.expression()?;
+        self.expect_symbol(')', "`)`")?;
+        Ok(condition)
+    }
+
+    /// Reads the body of a loop, in which `break` and `continue` can be used
+    fn loop_body(&mut self) -> ParseResult<Vec<Spanned<Statement>>> {
+        self.loops += 1;
+        let body = self.block();
+        self.loops -= 1;
+        body
+    }
+
+    /// Reads a `let` or `do` statement without its `;`, as found in the header of a `for` loop
+    fn simple_statement(&mut self) -> ParseResult<Spanned<Statement>> {
+        let start = self.span();
+        let statement = match self.peek() {
+            Some(Token::Keyword(Keyword::Let)) => {
+                self.pos += 1;
+                self.assignment()?
+            }
+            Some(Token::Keyword(Keyword::Do)) => {
+                self.pos += 1;
+                let name = self.identifier("a subroutine name")?;
+                Statement::Do(self.call(name)?)
+            }
+            _ => return self.error("`let` or `do`"),
+        };
+        Ok(self.spanned(start, statement))
+    }
+
+    /// Reads the rest of a `let` statement up to its `;`
+    fn assignment(&mut self) -> ParseResult<Statement> {
+        let name = self.identifier("a variable name")?;
+        let index = match self.eat_symbol('[') {
+            true => {
+                let index = self.expression()?;
+                self.expect_symbol(']', "`]`")?;
+                Some(index)
+            }
+            false => None,
+        };
+        self.expect_symbol('=', "`=`")?;
+        let value = self.expression()?;
+        Ok(Statement::Let { name, index, value })
+    }
+
+    fn statement(&mut self) -> ParseResult<Statement> {
+        let start = self.span();
+        let keyword = match self.bump().map(|token| &token.item) {
+            Some(Token::Keyword(keyword)) => *keyword,
+            _ => unreachable!("statements start with a keyword"),
+        };
+        Ok(match keyword {
+            Keyword::Let => {
+                let statement = self.assignment()?;
+                self.expect_symbol(';', "`;`")?;
+                statement
+            }
+            Keyword::If => {
+                let condition = self.condition()?;
+                let then = self.block()?;
+                let otherwise = match self.peek() {
+                    Some(Token::Keyword(Keyword::Else)) => {
+                        self.pos += 1;
+                        match self.peek() {
+                            // `else if` needs no braces in the extended dialect
+                            Some(Token::Keyword(Keyword::If))
+                                if self.dialect == Dialect::Extended =>
+                            {
+                                let start = self.span();
+                                let chained = self.statement()?;
+                                Some(vec![self.spanned(start, chained)])
+                            }
+                            _ => Some(self.block()?),
+                        }
+                    }
+                    _ => None,
+                };
+                Statement::If {
+                    condition,
+                    then,
+                    otherwise,
+                }
+            }
+            Keyword::While => {
+                let condition = self.condition()?;
+                let body = self.loop_body()?;
+                Statement::While { condition, body }
+            }
+            Keyword::For => {
+                self.expect_symbol('(', "`(`")?;
+                let init = match self.eat_symbol(';') {
+                    true => None,
+                    false => {
+                        let init = self.simple_statement()?;
+                        self.expect_symbol(';', "`;`")?;
+                        Some(Box::new(init))
+                    }
+                };
+                let condition = match self.eat_symbol(';') {
+                    true => None,
+                    false => {
+                        let condition = self.expression()?;
+                        self.expect_symbol(';', "`;`")?;
+                        Some(condition)
+                    }
+                };
+                let update = match self.eat_symbol(')') {
+                    true => None,
+                    false => {
+                        let update = self.simple_statement()?;
+                        self.expect_symbol(')', "`)`")?;
+                        Some(Box::new(update))
+                    }
+                };
+                let body = self.loop_body()?;
+                Statement::For {
+                    init,
+                    condition,
+                    update,
+                    body,
+                }
+            }
+            Keyword::Break | Keyword::Continue => {
+                if self.loops == 0 {
+                    let keyword = match keyword {
+                        Keyword::Break => "break",
+                        _ => "continue",
+                    };
+                    self.errors.push(Spanned {
+                        item: SyntaxError::OutsideLoop(keyword),
+                        span: start,
+                    });
+                }
+                self.expect_symbol(';', "`;`")?;
+                match keyword {
+                    Keyword::Break => Statement::Break,
+                    _ => Statement::Continue,
+                }
+            }
+            Keyword::Do => {
+                let name = self.identifier("a subroutine name")?;
+                let call = self.call(name)?;
+                self.expect_symbol(';', "`;`")?;
+                Statement::Do(call)
+            }
+            _ => {
+                let value = match self.peek() {
+                    Some(Token::Symbol(';')) => None,
+                    _ => Some(self.expression()?),
+                };
+                self.expect_symbol(';', "`;`")?;
+                Statement::Return(value)
+            }
+        })
+    }
+
+    /// Reads the rest of a subroutine call whose first name has been read
+    fn call(&mut self, first: Name) -> ParseResult<SubroutineCall> {
+        let (receiver, name) = match self.eat_symbol('.') {
+            true => (Some(first), self.identifier("a subroutine name")?),
+            false => (None, first),
+        };
+        self.expect_symbol('(', "`(`")?;
+        let mut args = Vec::new();
+        if self.peek() != Some(&Token::Symbol(')')) {
+            loop {
+                args.push(self.expression()?);
+                if !self.eat_symbol(',') {
+                    break;
+                }
+            }
+        }
+        self.expect_symbol(')', "`,` or `)`")?;
+        Ok(SubroutineCall {
+            receiver,
+            name,
+            args,
+        })
+    }
+
+    fn expression(&mut self) -> ParseResult<Spanned<Expression>> {
+        self.binary(0)
+    }
+
+    /// Reads an expression whose operators bind at least as tightly as `precedence`. In standard
+    /// Jack every operator binds the same, so they group from left to right.
+    fn binary(&mut self, precedence: u8) -> ParseResult<Spanned<Expression>> {
+        let start = self.span();
+        let mut left = self.term()?;
+        let dialect = self.dialect;
+        let binds = move |op: BinaryOp| match dialect {
+            Dialect::Standard => 0,
+            Dialect::Extended => op.precedence(),
+        };
+        while let Some(op) = match self.peek() {
+            Some(Token::Symbol(symbol)) => BinaryOp::from_symbol(*symbol),
+            _ => None,
+        }
+        .filter(|op| binds(*op) >= precedence)
+        {
+            self.pos += 1;
+            let right = self.binary(binds(op) + 1)?;
+            let binary = Expression::Binary {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+            left = self.spanned(start, binary);
+        }
+        Ok(left)
+    }
+
+    fn term(&mut self) -> ParseResult<Spanned<Expression>> {
+        let start = self.span();
+        let term = match self.peek() {
+            Some(Token::Integer(value)) => {
+                let value = *value;
+                self.pos += 1;
+                Expression::Integer(value)
+            }
+            Some(Token::String(text)) => {
+                let text = text.clone();
+                self.pos += 1;
+                Expression::String(text)
+            }
+            Some(Token::Char(c)) => {
+                let c = *c;
+                self.pos += 1;
+                Expression::Char(c)
+            }
+            Some(Token::Keyword(keyword)) => {
+                let constant = match keyword {
+                    Keyword::True => KeywordConstant::True,
+                    Keyword::False => KeywordConstant::False,
+                    Keyword::Null => KeywordConstant::Null,
+                    Keyword::This => KeywordConstant::This,
+                    _ => return self.error("an expression"),
+                };
+                self.pos += 1;
+                Expression::Keyword(constant)
+            }
+            Some(Token::Identifier(_)) => {
+                let name = self.identifier("a name")?;
+                match self.peek() {
+                    Some(Token::Symbol('[')) => {
+                        self.pos += 1;
+                        let index = self.expression()?;
+                        self.expect_symbol(']', "`]`")?;
+                        Expression::Index {
+                            name,
+                            index: Box::new(index),
+                        }
+                    }
+                    Some(Token::Symbol('(' | '.')) => Expression::Call(self.call(name)?),
+                    _ => Expression::Variable(name.item),
+                }
+            }
+            Some(Token::Symbol('(')) => {
+                self.pos += 1;
+                let inner = self.expression()?;
+                self.expect_symbol(')', "`)`")?;
+                Expression::Parenthesized(Box::new(inner))
+            }
+            Some(Token::Symbol(symbol @ ('-' | '~'))) => {
+                let op = match symbol {
+                    '-' => UnaryOp::Neg,
+                    _ => UnaryOp::Not,
+                };
+                self.pos += 1;
+                Expression::Unary {
+                    op,
+                    operand: Box::new(self.term()?),
+                }
+            }
+            _ => return self.error("an expression"),
+        };
+        Ok(self.spanned(start, term))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn name(text: &str, source: &str) -> Name {
+        let offset = source.find(text).unwrap();
+        Spanned {
+            item: text.to_string(),
+            span: Span {
+                offset,
+                len: text.len(),
+                line: 1,
+                column: offset + 1,
+            },
+        }
+    }
+
+    #[test]
+    fn class() {
+        let source = "class Point { field int x, y; static Point origin; \
+            method int sum(int dx, Point p) { var Array a; \
+            let a[dx] = -x + (y * 2); \
+            if (~(p = null)) { do p.move(dx, 1); } else { return 0; } \
+            while (true) { } \
+            return Math.max(a[0], sum()); } }";
+        let (class, errors) = parse(source);
+        assert_eq!(errors, []);
+        let class = class.unwrap();
+
+        assert_eq!(class.name, name("Point", source));
+        assert_eq!(class.vars.len(), 2);
+        assert_eq!(class.vars[0].item.kind, ClassVarKind::Field);
+        assert_eq!(class.vars[0].item.names[1], name("y", source));
+        assert_eq!(class.vars[1].span.of(source), "static Point origin;");
+
+        let sum = &class.subroutines[0].item;
+        assert_eq!(sum.kind, SubroutineKind::Method);
+        assert_eq!(sum.return_type.item, Some(Type::Int));
+        assert_eq!(sum.parameters[1].ty.item, Type::Class("Point".to_string()));
+        assert_eq!(sum.vars[0].item.ty.item, Type::Class("Array".to_string()));
+
+        let statements = sum
+            .statements
+            .iter()
+            .map(|statement| statement.span.of(source))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            statements,
+            [
+                "let a[dx] = -x + (y * 2);",
+                "if (~(p = null)) { do p.move(dx, 1); } else { return 0; }",
+                "while (true) { }",
+                "return Math.max(a[0], sum());",
+            ]
+        );
+
+        // operators group from left to right, and parentheses are kept
+        let Statement::Let { value, .. } = &sum.statements[0].item else {
+            panic!("expected a let statement");
+        };
+        let Expression::Binary { op, left, right } = &value.item else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(*op, BinaryOp::Add);
+        assert_eq!(left.span.of(source), "-x");
+        assert!(matches!(
+            left.item,
+            Expression::Unary {
+                op: UnaryOp::Neg,
+                ..
+            }
+        ));
+        assert!(matches!(right.item, Expression::Parenthesized(_)));
+
+        let Statement::If { otherwise, .. } = &sum.statements[1].item else {
+            panic!("expected an if statement");
+        };
+        assert!(matches!(
+            otherwise.as_deref(),
+            Some([Spanned {
+                item: Statement::Return(Some(_)),
+                ..
+            }])
+        ));
+    }
+
+    #[test]
+    fn recovery() {
+        let source = "class Main {
+    field int x
+    function void main() {
+        let x = 1
+        let y = ;
+        do Output.printInt(x);
+        x = 2;
+        return;
+    }
+    method int f() { return 1 + ; }
+}
+extra";
+        let (class, errors) = parse(source);
+        let errors = errors
+            .iter()
+            .map(|e| (e.span.line, e.item.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                (3, "Expected `,` or `;`, but found `function`".to_string()),
+                (5, "Expected `;`, but found `let`".to_string()),
+                (5, "Expected an expression, but found `;`".to_string()),
+                (7, "Expected a statement, but found `x`".to_string()),
+                (10, "Expected an expression, but found `;`".to_string()),
+                (
+                    12,
+                    "Unexpected `extra` after the end of the class".to_string()
+                ),
+            ]
+        );
+
+        // the statements which could be read are still there
+        let class = class.unwrap();
+        let main = &class.subroutines[0].item;
+        assert_eq!(main.statements.len(), 2);
+        assert!(matches!(main.statements[0].item, Statement::Do(_)));
+        assert!(matches!(main.statements[1].item, Statement::Return(None)));
+        assert_eq!(class.subroutines[1].item.statements.len(), 0);
+    }
+
+    #[test]
+    fn extended() {
+        let source = "class Main { const int N = 'a' + 1;
+    function void main() {
+        for (let i = 0; i < N; let i = i + 1) {
+            if (i = 1) { continue; } else if (i > 2 | i < 4 * 2 + 1) { break; } else { }
+        }
+        for (;;) { }
+        return;
+    } }";
+        assert!(!parse(source).1.is_empty());
+        let (class, errors) = parse_with(source, Dialect::Extended);
+        assert_eq!(errors, []);
+        let class = class.unwrap();
+        assert_eq!(class.vars[0].item.kind, ClassVarKind::Const);
+        let value = class.vars[0].item.value.as_ref().unwrap();
+        assert_eq!(value.span.of(source), "'a' + 1");
+
+        let statements = &class.subroutines[0].item.statements;
+        let Statement::For {
+            init: Some(init),
+            condition: Some(condition),
+            update: Some(update),
+            body,
+        } = &statements[0].item
+        else {
+            panic!("expected a for loop");
+        };
+        assert_eq!(init.span.of(source), "let i = 0");
+        assert_eq!(condition.span.of(source), "i < N");
+        assert_eq!(update.span.of(source), "let i = i + 1");
+        assert!(matches!(
+            &statements[1].item,
+            Statement::For {
+                init: None,
+                condition: None,
+                update: None,
+                ..
+            }
+        ));
+
+        // `else if` is an `if` alone in the `else` branch
+        let Statement::If {
+            then,
+            otherwise: Some(otherwise),
+            ..
+        } = &body[0].item
+        else {
+            panic!("expected an if statement");
+        };
+        assert_eq!(then[0].item, Statement::Continue);
+        let Statement::If {
+            condition, then, ..
+        } = &otherwise[0].item
+        else {
+            panic!("expected an else if");
+        };
+        assert_eq!(then[0].item, Statement::Break);
+
+        // `|` binds loosest, and `*` tightest
+        let Expression::Binary { op, right, .. } = &condition.item else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(*op, BinaryOp::Or);
+        let Expression::Binary { op, left, .. } = &right.item else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(*op, BinaryOp::Lt);
+        assert_eq!(left.span.of(source), "i");
+        let Expression::Binary { op, left, .. } = &right_of(right).item else {
+            panic!("expected a binary expression");
+        };
+        assert_eq!(*op, BinaryOp::Add);
+        assert_eq!(left.span.of(source), "4 * 2");
+
+        let errors = parse_with(
+            "class Main { function void f() { break; while (true) { continue; } } }",
+            Dialect::Extended,
+        )
+        .1;
+        let errors = errors.iter().map(|e| e.item.clone()).collect::<Vec<_>>();
+        assert_eq!(errors, [SyntaxError::OutsideLoop("break")]);
+    }
+
+    fn right_of(expression: &Spanned<Expression>) -> &Spanned<Expression> {
+        match &expression.item {
+            Expression::Binary { right, .. } => right,
+            _ => panic!("expected a binary expression"),
+        }
+    }
+}
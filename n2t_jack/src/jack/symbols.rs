@@ -0,0 +1,195 @@
+use super::ast::{
+    BinaryOp, Class, ClassVarKind, Expression, KeywordConstant, Subroutine, SubroutineKind, Type,
+    UnaryOp,
+};
+use crate::span::Span;
+use crate::translate::stack::Segment;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VarKind {
+    Static,
+    Field,
+    Argument,
+    Local,
+    /// A constant of the extended dialect, which has a value instead of a place in memory
+    Constant,
+}
+
+impl Display for VarKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            VarKind::Static => "static",
+            VarKind::Field => "field",
+            VarKind::Argument => "argument",
+            VarKind::Local => "local",
+            VarKind::Constant => "constant",
+        };
+        write!(f, "{kind}")
+    }
+}
+
+impl VarKind {
+    /// The VM segment variables of this kind are kept in
+    pub fn segment(self) -> Segment {
+        match self {
+            VarKind::Static => Segment::Static,
+            VarKind::Field => Segment::This,
+            VarKind::Argument => Segment::Argument,
+            VarKind::Local => Segment::Local,
+            VarKind::Constant => Segment::Constant,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub ty: Type,
+    pub kind: VarKind,
+    pub index: u16,
+    /// Where the variable was declared
+    pub span: Span,
+}
+
+/// The variables visible in a subroutine, which are its own locals and arguments along with its
+/// class's statics and fields
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    class: HashMap<String, Symbol>,
+    subroutine: HashMap<String, Symbol>,
+    counts: HashMap<VarKind, u16>,
+    /// The values of the class's constants which could be worked out
+    values: HashMap<String, i16>,
+}
+
+impl SymbolTable {
+    /// Creates a table holding the class variables and constants of `class`
+    pub fn new(class: &Class) -> Self {
+        let mut table = Self::default();
+        for var in &class.vars {
+            let kind = match var.item.kind {
+                ClassVarKind::Static => VarKind::Static,
+                ClassVarKind::Field => VarKind::Field,
+                ClassVarKind::Const => VarKind::Constant,
+            };
+            for name in &var.item.names {
+                table.define(&name.item, var.item.ty.item.clone(), kind, name.span);
+            }
+            if let (Some(value), [name]) = (&var.item.value, &var.item.names[..]) {
+                if let Some(value) = table.evaluate(&value.item) {
+                    table.values.insert(name.item.clone(), value);
+                }
+            }
+        }
+        table
+    }
+
+    /// The value of a constant, unless it was not given a constant expression
+    pub fn value(&self, name: &str) -> Option<i16> {
+        match self.get(name)?.kind {
+            VarKind::Constant => self.values.get(name).copied(),
+            _ => None,
+        }
+    }
+
+    /// Works out the value of an expression made of literals and the constants declared so far
+    pub fn evaluate(&self, expression: &Expression) -> Option<i16> {
+        Some(match expression {
+            Expression::Integer(value) => *value as i16,
+            Expression::Char(c) => *c as i16,
+            Expression::Keyword(KeywordConstant::True) => -1,
+            Expression::Keyword(KeywordConstant::False | KeywordConstant::Null) => 0,
+            Expression::Variable(name) => self.value(name)?,
+            Expression::Parenthesized(inner) => self.evaluate(&inner.item)?,
+            Expression::Unary { op, operand } => {
+                let value = self.evaluate(&operand.item)?;
+                match op {
+                    UnaryOp::Neg => value.wrapping_neg(),
+                    UnaryOp::Not => !value,
+                }
+            }
+            Expression::Binary { op, left, right } => {
+                let (a, b) = (self.evaluate(&left.item)?, self.evaluate(&right.item)?);
+                match op {
+                    BinaryOp::Add => a.wrapping_add(b),
+                    BinaryOp::Sub => a.wrapping_sub(b),
+                    BinaryOp::Mul => a.wrapping_mul(b),
+                    BinaryOp::Div => a.checked_div(b)?,
+                    BinaryOp::And => a & b,
+                    BinaryOp::Or => a | b,
+                    BinaryOp::Lt => -((a < b) as i16),
+                    BinaryOp::Gt => -((a > b) as i16),
+                    BinaryOp::Eq => -((a == b) as i16),
+                }
+            }
+            _ => return None,
+        })
+    }
+
+    /// Replaces the subroutine scope with the arguments and locals of `subroutine`. Methods take
+    /// the object they are called on as argument 0.
+    pub fn enter(&mut self, subroutine: &Subroutine) {
+        self.subroutine.clear();
+        self.counts.remove(&VarKind::Argument);
+        self.counts.remove(&VarKind::Local);
+        if subroutine.kind == SubroutineKind::Method {
+            self.counts.insert(VarKind::Argument, 1);
+        }
+
+        for parameter in &subroutine.parameters {
+            let (name, ty) = (&parameter.name, parameter.ty.item.clone());
+            self.define(&name.item, ty, VarKind::Argument, name.span);
+        }
+        for var in &subroutine.vars {
+            for name in &var.item.names {
+                self.define(
+                    &name.item,
+                    var.item.ty.item.clone(),
+                    VarKind::Local,
+                    name.span,
+                );
+            }
+        }
+    }
+
+    /// Adds a variable, giving it the next index of its kind. Returns `false` if a variable of the
+    /// same name was already declared in the same scope, in which case it is replaced.
+    pub fn define(&mut self, name: &str, ty: Type, kind: VarKind, span: Span) -> bool {
+        let count = self.counts.entry(kind).or_default();
+        let symbol = Symbol {
+            ty,
+            kind,
+            index: *count,
+            span,
+        };
+        *count += 1;
+        let scope = match kind {
+            VarKind::Static | VarKind::Field | VarKind::Constant => &mut self.class,
+            VarKind::Argument | VarKind::Local => &mut self.subroutine,
+        };
+        scope.insert(name.to_string(), symbol).is_none()
+    }
+
+    /// Finds a variable, preferring the subroutine's variables over the class's
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.subroutine.get(name).or_else(|| self.class.get(name))
+    }
+
+    /// Every visible variable, leaving out class variables hidden by the subroutine's own
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Symbol)> {
+        let class = self
+            .class
+            .iter()
+            .filter(|(name, _)| !self.subroutine.contains_key(*name));
+        self.subroutine
+            .iter()
+            .chain(class)
+            .map(|(name, symbol)| (name.as_str(), symbol))
+    }
+
+    /// How many variables of a kind have been defined
+    pub fn count(&self, kind: VarKind) -> u16 {
+        self.counts.get(&kind).copied().unwrap_or_default()
+    }
+}
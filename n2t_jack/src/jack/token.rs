@@ -0,0 +1,119 @@
+use std::fmt::{Display, Formatter};
+use strum_macros::{Display, EnumString};
+
+#[derive(EnumString, Display, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[strum(serialize_all = "camelCase")]
+pub enum Keyword {
+    Class,
+    Constructor,
+    Function,
+    Method,
+    Field,
+    Static,
+    Var,
+    Int,
+    Char,
+    Boolean,
+    Void,
+    True,
+    False,
+    Null,
+    This,
+    Let,
+    Do,
+    If,
+    Else,
+    While,
+    Return,
+    For,
+    Const,
+    Break,
+    Continue,
+}
+
+impl Keyword {
+    /// Whether the keyword only exists in the extended dialect. In standard Jack it is a name.
+    pub fn is_extended(self) -> bool {
+        matches!(
+            self,
+            Keyword::For | Keyword::Const | Keyword::Break | Keyword::Continue
+        )
+    }
+}
+
+/// Which version of the Jack language a program is written in
+#[derive(EnumString, Display, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[strum(serialize_all = "lowercase")]
+pub enum Dialect {
+    /// The language as the course defines it
+    #[default]
+    Standard,
+    /// Jack with `for` loops, character literals, `else if`, operator precedence, `const` class
+    /// constants, and `break` and `continue`, all of which compile to ordinary VM code
+    Extended,
+}
+
+/// The characters which are tokens on their own
+pub const SYMBOLS: &str = "{}()[].,;+-*/&|<>=~";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentKind {
+    /// `// ...`
+    Line,
+    /// `/* ... */`, which may contain other block comments
+    Block,
+    /// `/** ... */`
+    Doc,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    pub kind: CommentKind,
+    /// The text between the delimiters
+    pub text: String,
+}
+
+/// A single token of a Jack program. Comments are kept so that tools like the formatter can put
+/// them back, but have no meaning to the compiler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Keyword(Keyword),
+    Symbol(char),
+    Integer(u16),
+    String(String),
+    /// A character literal like `'a'`, which only the extended dialect has
+    Char(char),
+    Identifier(String),
+    Comment(Comment),
+}
+
+impl Token {
+    pub fn is_comment(&self) -> bool {
+        matches!(self, Token::Comment(_))
+    }
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Keyword(keyword) => write!(f, "{keyword}"),
+            Token::Symbol(symbol) => write!(f, "{symbol}"),
+            Token::Integer(value) => write!(f, "{value}"),
+            Token::String(text) => write!(f, "\"{text}\""),
+            Token::Char(c) => write!(f, "'{c}'"),
+            Token::Identifier(name) => write!(f, "{name}"),
+            Token::Comment(Comment {
+                kind: CommentKind::Line,
+                text,
+            }) => write!(f, "//{text}"),
+            Token::Comment(Comment {
+                kind: CommentKind::Block,
+                text,
+            }) => write!(f, "/*{text}*/"),
+            Token::Comment(Comment {
+                kind: CommentKind::Doc,
+                text,
+            }) => write!(f, "/**{text}*/"),
+        }
+    }
+}
@@ -0,0 +1,483 @@
+use super::ast::*;
+use super::check::always_returns;
+use super::lex_with;
+use super::token::{Dialect, Token};
+use crate::span::{Span, Spanned};
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+
+/// Something in a Jack program which is allowed, but is probably a mistake
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    #[error("`{0}` is never used")]
+    UnusedVariable(String),
+
+    #[error("The parameter `{0}` is never used")]
+    UnusedParameter(String),
+
+    #[error("`{0}` is read before it is given a value")]
+    Uninitialized(String),
+
+    #[error("Functions have no object, so the field `{0}` cannot be set")]
+    FieldInFunction(String),
+
+    #[error("`{0}` hides the field of the same name")]
+    ShadowedField(String),
+
+    #[error("{0} is bigger than 32767, so it is the same as {}", *.0 as i16)]
+    LargeInteger(u16),
+
+    #[error("This statement can never run, because the ones before it return")]
+    Unreachable,
+
+    #[error("Calls whose result is thrown away should start with `do`")]
+    MissingDo,
+}
+
+impl Warning {
+    /// The name of the warning, which can be given to `allow` to silence it
+    pub fn code(&self) -> &'static str {
+        match self {
+            Warning::UnusedVariable(_) => "unused-variable",
+            Warning::UnusedParameter(_) => "unused-parameter",
+            Warning::Uninitialized(_) => "uninitialized",
+            Warning::FieldInFunction(_) => "field-in-function",
+            Warning::ShadowedField(_) => "shadowed-field",
+            Warning::LargeInteger(_) => "large-integer",
+            Warning::Unreachable => "unreachable",
+            Warning::MissingDo => "missing-do",
+        }
+    }
+}
+
+/// The codes of the warnings which a file silences with comments like `// allow: unreachable`.
+/// Several codes can be given, separated by commas.
+fn allowed(source: &str, dialect: Dialect) -> HashSet<String> {
+    lex_with(source, dialect)
+        .flatten()
+        .filter_map(|token| match token.item {
+            Token::Comment(comment) => Some(comment.text),
+            _ => None,
+        })
+        .filter_map(|text| Some(text.trim().strip_prefix("allow:")?.to_string()))
+        .flat_map(|codes| {
+            codes
+                .split(',')
+                .map(|code| code.trim().to_string())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Looks for likely mistakes in a class, leaving out those which `source` allows. The warnings
+/// are in the order they appear in the source.
+pub fn warnings(source: &str, class: &Class) -> Vec<Spanned<Warning>> {
+    warnings_with(source, class, Dialect::Standard)
+}
+
+/// Looks for likely mistakes in a class written in `dialect`
+pub fn warnings_with(source: &str, class: &Class, dialect: Dialect) -> Vec<Spanned<Warning>> {
+    let mut warner = Warner {
+        fields: HashMap::new(),
+        statics: HashSet::new(),
+        used: HashSet::new(),
+        warnings: Vec::new(),
+    };
+    for var in &class.vars {
+        for name in &var.item.names {
+            warner.fields.insert(&name.item, name.span);
+            if var.item.kind != ClassVarKind::Field {
+                warner.statics.insert(&name.item);
+            }
+        }
+    }
+    for subroutine in &class.subroutines {
+        warner.subroutine(&subroutine.item);
+    }
+    let mut unused = warner
+        .fields
+        .iter()
+        .filter(|(name, _)| !warner.used.contains(*name))
+        .map(|(name, span)| Spanned {
+            item: Warning::UnusedVariable(name.to_string()),
+            span: *span,
+        })
+        .collect::<Vec<_>>();
+    warner.warnings.append(&mut unused);
+
+    let allowed = allowed(source, dialect);
+    let mut warnings = warner.warnings;
+    warnings.retain(|warning| !allowed.contains(warning.item.code()));
+    warnings.sort_by_key(|warning| warning.span.offset);
+    warnings
+}
+
+/// A variable of the subroutine being looked at
+struct Local {
+    span: Span,
+    parameter: bool,
+    used: bool,
+}
+
+struct Warner<'a> {
+    /// The class's variables, which are used if any subroutine uses them
+    fields: HashMap<&'a str, Span>,
+    /// The class's statics and constants, which functions may use
+    statics: HashSet<&'a str>,
+    used: HashSet<&'a str>,
+    warnings: Vec<Spanned<Warning>>,
+}
+
+/// The state of a subroutine being looked at
+struct Scope<'a> {
+    kind: SubroutineKind,
+    locals: HashMap<&'a str, Local>,
+    /// The locals which are given a value on every path to the current statement
+    assigned: HashSet<&'a str>,
+    /// The locals which have already been warned about being read too early
+    uninitialized: HashSet<&'a str>,
+}
+
+impl<'a> Warner<'a> {
+    fn warn(&mut self, span: Span, warning: Warning) {
+        self.warnings.push(Spanned {
+            item: warning,
+            span,
+        });
+    }
+
+    fn subroutine(&mut self, subroutine: &'a Subroutine) {
+        let mut scope = Scope {
+            kind: subroutine.kind,
+            locals: HashMap::new(),
+            assigned: HashSet::new(),
+            uninitialized: HashSet::new(),
+        };
+        let parameters = subroutine.parameters.iter().map(|p| (&p.name, true));
+        let vars = subroutine.vars.iter().flat_map(|var| &var.item.names);
+        for (name, parameter) in parameters.chain(vars.map(|name| (name, false))) {
+            if self.fields.contains_key(name.item.as_str()) && !self.statics.contains(&*name.item) {
+                self.warn(name.span, Warning::ShadowedField(name.item.clone()));
+            }
+            if parameter {
+                scope.assigned.insert(&name.item);
+            }
+            let local = Local {
+                span: name.span,
+                parameter,
+                used: false,
+            };
+            scope.locals.insert(&name.item, local);
+        }
+
+        self.statements(&mut scope, &subroutine.statements);
+
+        for (name, local) in scope.locals {
+            if !local.used {
+                let warning = match local.parameter {
+                    true => Warning::UnusedParameter(name.to_string()),
+                    false => Warning::UnusedVariable(name.to_string()),
+                };
+                self.warn(local.span, warning);
+            }
+        }
+    }
+
+    fn statements(&mut self, scope: &mut Scope<'a>, statements: &'a [Spanned<Statement>]) {
+        let mut reachable = true;
+        for (i, statement) in statements.iter().enumerate() {
+            if reachable && i > 0 && always_returns(&statements[i - 1..i]) {
+                self.warn(statement.span, Warning::Unreachable);
+                reachable = false;
+            }
+            self.statement(scope, statement);
+        }
+    }
+
+    fn statement(&mut self, scope: &mut Scope<'a>, statement: &'a Spanned<Statement>) {
+        match &statement.item {
+            Statement::Let { name, index, value } => {
+                if let Some(index) = index {
+                    self.expression(scope, index);
+                    self.read(scope, name);
+                }
+                self.expression(scope, value);
+                if index.is_none() {
+                    self.assign(scope, name);
+                }
+            }
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.expression(scope, condition);
+                let before = scope.assigned.clone();
+                self.statements(scope, then);
+                let after_then = std::mem::replace(&mut scope.assigned, before);
+                // without an `else`, the `if` either returns or leaves what was assigned before it
+                if let Some(otherwise) = otherwise {
+                    self.statements(scope, otherwise);
+                    // a branch which returns doesn't reach the code after the `if`
+                    if always_returns(otherwise) {
+                        scope.assigned = after_then;
+                    } else if !always_returns(then) {
+                        scope.assigned.retain(|name| after_then.contains(name));
+                    }
+                }
+            }
+            Statement::While { condition, body } => {
+                self.expression(scope, condition);
+                let before = scope.assigned.clone();
+                self.statements(scope, body);
+                scope.assigned = before;
+            }
+            Statement::For {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.statement(scope, init);
+                }
+                if let Some(condition) = condition {
+                    self.expression(scope, condition);
+                }
+                let before = scope.assigned.clone();
+                self.statements(scope, body);
+                if let Some(update) = update {
+                    self.statement(scope, update);
+                }
+                scope.assigned = before;
+            }
+            Statement::Break | Statement::Continue => (),
+            Statement::Do(call) => {
+                let start = call.receiver.as_ref().unwrap_or(&call.name).span;
+                if start.offset == statement.span.offset {
+                    self.warn(statement.span, Warning::MissingDo);
+                }
+                self.call(scope, call);
+            }
+            Statement::Return(value) => {
+                if let Some(value) = value {
+                    self.expression(scope, value);
+                }
+            }
+        }
+    }
+
+    fn call(&mut self, scope: &mut Scope<'a>, call: &'a SubroutineCall) {
+        if let Some(receiver) = &call.receiver {
+            // the receiver may be a class name, which is not a variable
+            if scope.locals.contains_key(receiver.item.as_str())
+                || self.fields.contains_key(receiver.item.as_str())
+            {
+                self.read(scope, receiver);
+            }
+        }
+        for arg in &call.args {
+            self.expression(scope, arg);
+        }
+    }
+
+    fn expression(&mut self, scope: &mut Scope<'a>, expression: &'a Spanned<Expression>) {
+        match &expression.item {
+            Expression::Integer(value) if *value > 32767 => {
+                self.warn(expression.span, Warning::LargeInteger(*value))
+            }
+            Expression::Integer(_)
+            | Expression::Char(_)
+            | Expression::String(_)
+            | Expression::Keyword(_) => (),
+            Expression::Variable(name) => {
+                let name = Spanned {
+                    item: name.clone(),
+                    span: expression.span,
+                };
+                self.read_name(scope, &name, lookup(scope, self, name.item.as_str()));
+            }
+            Expression::Index { name, index } => {
+                self.read(scope, name);
+                self.expression(scope, index);
+            }
+            Expression::Call(call) => self.call(scope, call),
+            Expression::Parenthesized(inner) | Expression::Unary { operand: inner, .. } => {
+                self.expression(scope, inner)
+            }
+            Expression::Binary { left, right, .. } => {
+                self.expression(scope, left);
+                self.expression(scope, right);
+            }
+        }
+    }
+
+    fn read(&mut self, scope: &mut Scope<'a>, name: &'a Name) {
+        let variable = lookup(scope, self, &name.item);
+        self.read_name(scope, name, variable);
+    }
+
+    /// Marks a variable as used, warning if it is a local which may not have a value yet
+    fn read_name(&mut self, scope: &mut Scope<'a>, name: &Name, variable: Option<&'a str>) {
+        let Some(variable) = variable else {
+            return;
+        };
+        match scope.locals.get_mut(variable) {
+            Some(local) => {
+                local.used = true;
+                if !scope.assigned.contains(variable) && scope.uninitialized.insert(variable) {
+                    self.warn(name.span, Warning::Uninitialized(variable.to_string()));
+                }
+            }
+            None => {
+                self.used.insert(variable);
+            }
+        }
+    }
+
+    fn assign(&mut self, scope: &mut Scope<'a>, name: &'a Name) {
+        let Some(variable) = lookup(scope, self, &name.item) else {
+            return;
+        };
+        match scope.locals.get_mut(variable) {
+            Some(_) => {
+                scope.assigned.insert(variable);
+            }
+            None => {
+                self.used.insert(variable);
+                if scope.kind == SubroutineKind::Function && !self.statics.contains(variable) {
+                    self.warn(name.span, Warning::FieldInFunction(variable.to_string()));
+                }
+            }
+        }
+    }
+}
+
+/// Finds the variable a name refers to, as a key of the subroutine's or the class's variables
+fn lookup<'a>(scope: &Scope<'a>, warner: &Warner<'a>, name: &str) -> Option<&'a str> {
+    scope
+        .locals
+        .get_key_value(name)
+        .map(|(name, _)| *name)
+        .or_else(|| warner.fields.get_key_value(name).map(|(name, _)| *name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jack::{parse, parse_with};
+
+    fn warnings_in(source: &str) -> Vec<(u32, String)> {
+        let (class, errors) = parse(source);
+        assert_eq!(errors, []);
+        warnings(source, &class.unwrap())
+            .into_iter()
+            .map(|w| (w.span.line, format!("{} [{}]", w.item, w.item.code())))
+            .collect()
+    }
+
+    #[test]
+    fn warnings_found() {
+        let source = "class Main {
+    field int x, unused;
+    static int count;
+    function void main(int ignored) {
+        var int a, b, x;
+        let x = 1;
+        let count = a + x;
+        if (count > 0) { let b = 1; } else { let b = 2; }
+        do Output.printInt(b + 40000);
+        Output.println();
+        return;
+        let a = 1;
+    }
+    function void set() { let x = 1; return; }
+}";
+        assert_eq!(
+            warnings_in(source),
+            [
+                (2, "`unused` is never used [unused-variable]".to_string()),
+                (
+                    4,
+                    "The parameter `ignored` is never used [unused-parameter]".to_string()
+                ),
+                (
+                    5,
+                    "`x` hides the field of the same name [shadowed-field]".to_string()
+                ),
+                (
+                    7,
+                    "`a` is read before it is given a value [uninitialized]".to_string()
+                ),
+                (
+                    9,
+                    "40000 is bigger than 32767, so it is the same as -25536 [large-integer]"
+                        .to_string()
+                ),
+                (
+                    10,
+                    "Calls whose result is thrown away should start with `do` [missing-do]"
+                        .to_string()
+                ),
+                (
+                    12,
+                    "This statement can never run, because the ones before it return \
+                     [unreachable]"
+                        .to_string()
+                ),
+                (
+                    14,
+                    "Functions have no object, so the field `x` cannot be set \
+                     [field-in-function]"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn allow() {
+        let source = "// allow: unused-variable, missing-do
+class Main {
+    function void main() {
+        var int a;
+        Output.println();
+        /* allow: unreachable */
+        return;
+        return;
+    }
+}";
+        assert_eq!(warnings_in(source), []);
+    }
+
+    #[test]
+    fn written_but_not_read() {
+        let source = "class Main {
+    function void main() {
+        var int q;
+        let q = 1;
+        return;
+    }
+}";
+        assert_eq!(
+            warnings_in(source),
+            [(3, "`q` is never used [unused-variable]".to_string())]
+        );
+    }
+
+    #[test]
+    fn allow_extended() {
+        let source = "class Main {
+    function void main() {
+        var char q; let q = '\"'; // allow: unused-variable
+        return;
+    }
+}";
+        let (class, errors) = parse_with(source, Dialect::Extended);
+        assert_eq!(errors, []);
+        assert_eq!(
+            warnings_with(source, &class.unwrap(), Dialect::Extended),
+            []
+        );
+    }
+}
@@ -0,0 +1,533 @@
+use super::ast::*;
+use super::token::{Keyword, Token};
+use crate::span::Spanned;
+use std::fmt::Write;
+
+/// Lines end in `\r\n`, like the comparison files that come with the course
+const NEWLINE: &str = "\r\n";
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The tag and contents of a token's element, or `None` for comments
+fn element(token: &Token) -> Option<(&'static str, String)> {
+    Some(match token {
+        Token::Keyword(keyword) => ("keyword", keyword.to_string()),
+        Token::Symbol(symbol) => ("symbol", escape(&symbol.to_string())),
+        Token::Integer(value) => ("integerConstant", value.to_string()),
+        Token::Char(c) => ("charConstant", escape(&c.to_string())),
+        Token::String(text) => ("stringConstant", escape(text)),
+        Token::Identifier(name) => ("identifier", name.clone()),
+        Token::Comment(_) => return None,
+    })
+}
+
+/// Writes a token as an element on its own line, indented by `indent` spaces
+fn write_token(out: &mut String, token: &Token, indent: usize) {
+    if let Some((tag, contents)) = element(token) {
+        write!(out, "{:indent$}<{tag}> {contents} </{tag}>{NEWLINE}", "").unwrap();
+    }
+}
+
+/// Prints tokens in the format of project 10's `xxxT.xml` files
+pub fn tokens_xml<'a>(tokens: impl IntoIterator<Item = &'a Token>) -> String {
+    let mut out = format!("<tokens>{NEWLINE}");
+    tokens
+        .into_iter()
+        .for_each(|token| write_token(&mut out, token, 0));
+    out.push_str("</tokens>");
+    out.push_str(NEWLINE);
+    out
+}
+
+/// Writes the elements of a parse tree, keeping track of how deeply they are nested
+#[derive(Default)]
+struct Tree {
+    out: String,
+    indent: usize,
+}
+
+impl Tree {
+    fn open(&mut self, tag: &str) {
+        write!(
+            self.out,
+            "{:indent$}<{tag}>{NEWLINE}",
+            "",
+            indent = self.indent
+        )
+        .unwrap();
+        self.indent += 2;
+    }
+
+    fn close(&mut self, tag: &str) {
+        self.indent -= 2;
+        write!(
+            self.out,
+            "{:indent$}</{tag}>{NEWLINE}",
+            "",
+            indent = self.indent
+        )
+        .unwrap();
+    }
+
+    fn token(&mut self, token: Token) {
+        write_token(&mut self.out, &token, self.indent);
+    }
+
+    fn keyword(&mut self, keyword: Keyword) {
+        self.token(Token::Keyword(keyword));
+    }
+
+    fn symbol(&mut self, symbol: char) {
+        self.token(Token::Symbol(symbol));
+    }
+
+    fn identifier(&mut self, name: &str) {
+        self.token(Token::Identifier(name.to_string()));
+    }
+
+    /// Writes any token whose text is a keyword as a keyword, and any other as an identifier
+    fn word(&mut self, word: &str) {
+        match word.parse() {
+            Ok(keyword) => self.keyword(keyword),
+            Err(_) => self.identifier(word),
+        }
+    }
+
+    fn ty(&mut self, ty: &Type) {
+        self.word(&ty.to_string());
+    }
+
+    fn names(&mut self, names: &[Name]) {
+        for (i, name) in names.iter().enumerate() {
+            if i > 0 {
+                self.symbol(',');
+            }
+            self.identifier(&name.item);
+        }
+        self.symbol(';');
+    }
+
+    fn class(&mut self, class: &Class) {
+        self.open("class");
+        self.keyword(Keyword::Class);
+        self.identifier(&class.name.item);
+        self.symbol('{');
+        for var in &class.vars {
+            self.open("classVarDec");
+            self.word(&var.item.kind.to_string());
+            self.ty(&var.item.ty.item);
+            match &var.item.value {
+                Some(value) => {
+                    self.identifier(&var.item.names[0].item);
+                    self.symbol('=');
+                    self.expression(&value.item);
+                    self.symbol(';');
+                }
+                None => self.names(&var.item.names),
+            }
+            self.close("classVarDec");
+        }
+        for subroutine in &class.subroutines {
+            self.subroutine(&subroutine.item);
+        }
+        self.symbol('}');
+        self.close("class");
+    }
+
+    fn subroutine(&mut self, subroutine: &Subroutine) {
+        self.open("subroutineDec");
+        self.word(&subroutine.kind.to_string());
+        match &subroutine.return_type.item {
+            Some(ty) => self.ty(ty),
+            None => self.keyword(Keyword::Void),
+        }
+        self.identifier(&subroutine.name.item);
+        self.symbol('(');
+        self.open("parameterList");
+        for (i, parameter) in subroutine.parameters.iter().enumerate() {
+            if i > 0 {
+                self.symbol(',');
+            }
+            self.ty(&parameter.ty.item);
+            self.identifier(&parameter.name.item);
+        }
+        self.close("parameterList");
+        self.symbol(')');
+
+        self.open("subroutineBody");
+        self.symbol('{');
+        for var in &subroutine.vars {
+            self.open("varDec");
+            self.keyword(Keyword::Var);
+            self.ty(&var.item.ty.item);
+            self.names(&var.item.names);
+            self.close("varDec");
+        }
+        self.statements(&subroutine.statements);
+        self.symbol('}');
+        self.close("subroutineBody");
+        self.close("subroutineDec");
+    }
+
+    fn block(&mut self, statements: &[Spanned<Statement>]) {
+        self.symbol('{');
+        self.statements(statements);
+        self.symbol('}');
+    }
+
+    fn statements(&mut self, statements: &[Spanned<Statement>]) {
+        self.open("statements");
+        for statement in statements {
+            self.statement(&statement.item);
+        }
+        self.close("statements");
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        self.statement_ending(statement, ';');
+    }
+
+    /// Writes a statement, ending a `let` or `do` with `end` rather than `;`, as the header of a
+    /// `for` loop does
+    fn statement_ending(&mut self, statement: &Statement, end: char) {
+        match statement {
+            Statement::Let { name, index, value } => {
+                self.open("letStatement");
+                self.keyword(Keyword::Let);
+                self.identifier(&name.item);
+                if let Some(index) = index {
+                    self.symbol('[');
+                    self.expression(&index.item);
+                    self.symbol(']');
+                }
+                self.symbol('=');
+                self.expression(&value.item);
+                self.symbol(end);
+                self.close("letStatement");
+            }
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.open("ifStatement");
+                self.keyword(Keyword::If);
+                self.symbol('(');
+                self.expression(&condition.item);
+                self.symbol(')');
+                self.block(then);
+                if let Some(otherwise) = otherwise {
+                    self.keyword(Keyword::Else);
+                    self.block(otherwise);
+                }
+                self.close("ifStatement");
+            }
+            Statement::While { condition, body } => {
+                self.open("whileStatement");
+                self.keyword(Keyword::While);
+                self.symbol('(');
+                self.expression(&condition.item);
+                self.symbol(')');
+                self.block(body);
+                self.close("whileStatement");
+            }
+            Statement::For {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                self.open("forStatement");
+                self.keyword(Keyword::For);
+                self.symbol('(');
+                match init {
+                    Some(init) => self.statement_ending(&init.item, ';'),
+                    None => self.symbol(';'),
+                }
+                if let Some(condition) = condition {
+                    self.expression(&condition.item);
+                }
+                self.symbol(';');
+                match update {
+                    Some(update) => self.statement_ending(&update.item, ')'),
+                    None => self.symbol(')'),
+                }
+                self.block(body);
+                self.close("forStatement");
+            }
+            Statement::Break => {
+                self.open("breakStatement");
+                self.keyword(Keyword::Break);
+                self.symbol(';');
+                self.close("breakStatement");
+            }
+            Statement::Continue => {
+                self.open("continueStatement");
+                self.keyword(Keyword::Continue);
+                self.symbol(';');
+                self.close("continueStatement");
+            }
+            Statement::Do(call) => {
+                self.open("doStatement");
+                self.keyword(Keyword::Do);
+                self.call(call);
+                self.symbol(end);
+                self.close("doStatement");
+            }
+            Statement::Return(value) => {
+                self.open("returnStatement");
+                self.keyword(Keyword::Return);
+                if let Some(value) = value {
+                    self.expression(&value.item);
+                }
+                self.symbol(';');
+                self.close("returnStatement");
+            }
+        }
+    }
+
+    fn call(&mut self, call: &SubroutineCall) {
+        if let Some(receiver) = &call.receiver {
+            self.identifier(&receiver.item);
+            self.symbol('.');
+        }
+        self.identifier(&call.name.item);
+        self.symbol('(');
+        self.open("expressionList");
+        for (i, arg) in call.args.iter().enumerate() {
+            if i > 0 {
+                self.symbol(',');
+            }
+            self.expression(&arg.item);
+        }
+        self.close("expressionList");
+        self.symbol(')');
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        self.open("expression");
+        self.operations(expression);
+        self.close("expression");
+    }
+
+    /// Writes the terms and operators of an expression without the surrounding element
+    fn operations(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Binary { op, left, right } => {
+                self.operations(&left.item);
+                self.symbol(op.symbol());
+                self.operations(&right.item);
+            }
+            term => self.term(term),
+        }
+    }
+
+    fn term(&mut self, term: &Expression) {
+        self.open("term");
+        match term {
+            Expression::Integer(value) => self.token(Token::Integer(*value)),
+            Expression::Char(c) => self.token(Token::Char(*c)),
+            Expression::String(text) => self.token(Token::String(text.clone())),
+            Expression::Keyword(constant) => self.keyword(match constant {
+                KeywordConstant::True => Keyword::True,
+                KeywordConstant::False => Keyword::False,
+                KeywordConstant::Null => Keyword::Null,
+                KeywordConstant::This => Keyword::This,
+            }),
+            Expression::Variable(name) => self.identifier(name),
+            Expression::Index { name, index } => {
+                self.identifier(&name.item);
+                self.symbol('[');
+                self.expression(&index.item);
+                self.symbol(']');
+            }
+            Expression::Call(call) => self.call(call),
+            Expression::Parenthesized(inner) => {
+                self.symbol('(');
+                self.expression(&inner.item);
+                self.symbol(')');
+            }
+            Expression::Unary { op, operand } => {
+                self.symbol(op.symbol());
+                self.term(&operand.item);
+            }
+            Expression::Binary { .. } => self.operations(term),
+        }
+        self.close("term");
+    }
+}
+
+/// Prints a class in the format of project 10's `xxx.xml` parse trees
+pub fn class_xml(class: &Class) -> String {
+    let mut tree = Tree::default();
+    tree.class(class);
+    tree.out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jack::lex;
+
+    #[test]
+    fn tokens() {
+        let source = "if (x < 0) { // negative\n  let s = \"a&b\";\n}";
+        let tokens = lex(source).map(|res| res.unwrap().item).collect::<Vec<_>>();
+        let expected = "\
+<tokens>
+<keyword> if </keyword>
+<symbol> ( </symbol>
+<identifier> x </identifier>
+<symbol> &lt; </symbol>
+<integerConstant> 0 </integerConstant>
+<symbol> ) </symbol>
+<symbol> { </symbol>
+<keyword> let </keyword>
+<identifier> s </identifier>
+<symbol> = </symbol>
+<stringConstant> a&amp;b </stringConstant>
+<symbol> ; </symbol>
+<symbol> } </symbol>
+</tokens>
+";
+        assert_eq!(tokens_xml(&tokens), expected.replace('\n', NEWLINE));
+    }
+
+    #[test]
+    fn parse_tree() {
+        let source = "class Main {
+    static int n;
+    function void main(int a) {
+        var Array x;
+        if (a) { let x[1] = -a + (2); } else { }
+        do Output.printInt(f(), n);
+        return;
+    }
+}";
+        let expected = "\
+<class>
+  <keyword> class </keyword>
+  <identifier> Main </identifier>
+  <symbol> { </symbol>
+  <classVarDec>
+    <keyword> static </keyword>
+    <keyword> int </keyword>
+    <identifier> n </identifier>
+    <symbol> ; </symbol>
+  </classVarDec>
+  <subroutineDec>
+    <keyword> function </keyword>
+    <keyword> void </keyword>
+    <identifier> main </identifier>
+    <symbol> ( </symbol>
+    <parameterList>
+      <keyword> int </keyword>
+      <identifier> a </identifier>
+    </parameterList>
+    <symbol> ) </symbol>
+    <subroutineBody>
+      <symbol> { </symbol>
+      <varDec>
+        <keyword> var </keyword>
+        <identifier> Array </identifier>
+        <identifier> x </identifier>
+        <symbol> ; </symbol>
+      </varDec>
+      <statements>
+        <ifStatement>
+          <keyword> if </keyword>
+          <symbol> ( </symbol>
+          <expression>
+            <term>
+              <identifier> a </identifier>
+            </term>
+          </expression>
+          <symbol> ) </symbol>
+          <symbol> { </symbol>
+          <statements>
+            <letStatement>
+              <keyword> let </keyword>
+              <identifier> x </identifier>
+              <symbol> [ </symbol>
+              <expression>
+                <term>
+                  <integerConstant> 1 </integerConstant>
+                </term>
+              </expression>
+              <symbol> ] </symbol>
+              <symbol> = </symbol>
+              <expression>
+                <term>
+                  <symbol> - </symbol>
+                  <term>
+                    <identifier> a </identifier>
+                  </term>
+                </term>
+                <symbol> + </symbol>
+                <term>
+                  <symbol> ( </symbol>
+                  <expression>
+                    <term>
+                      <integerConstant> 2 </integerConstant>
+                    </term>
+                  </expression>
+                  <symbol> ) </symbol>
+                </term>
+              </expression>
+              <symbol> ; </symbol>
+            </letStatement>
+          </statements>
+          <symbol> } </symbol>
+          <keyword> else </keyword>
+          <symbol> { </symbol>
+          <statements>
+          </statements>
+          <symbol> } </symbol>
+        </ifStatement>
+        <doStatement>
+          <keyword> do </keyword>
+          <identifier> Output </identifier>
+          <symbol> . </symbol>
+          <identifier> printInt </identifier>
+          <symbol> ( </symbol>
+          <expressionList>
+            <expression>
+              <term>
+                <identifier> f </identifier>
+                <symbol> ( </symbol>
+                <expressionList>
+                </expressionList>
+                <symbol> ) </symbol>
+              </term>
+            </expression>
+            <symbol> , </symbol>
+            <expression>
+              <term>
+                <identifier> n </identifier>
+              </term>
+            </expression>
+          </expressionList>
+          <symbol> ) </symbol>
+          <symbol> ; </symbol>
+        </doStatement>
+        <returnStatement>
+          <keyword> return </keyword>
+          <symbol> ; </symbol>
+        </returnStatement>
+      </statements>
+      <symbol> } </symbol>
+    </subroutineBody>
+  </subroutineDec>
+  <symbol> } </symbol>
+</class>
+";
+        let (class, errors) = crate::jack::parse(source);
+        assert_eq!(errors, []);
+        assert_eq!(class_xml(&class.unwrap()), expected.replace('\n', NEWLINE));
+    }
+}
@@ -1 +1,7 @@
+pub mod debug;
+pub mod jack;
+pub mod pipeline;
+pub mod script;
+pub mod span;
 pub mod translate;
+pub mod vm;
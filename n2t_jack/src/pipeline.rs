@@ -0,0 +1,577 @@
+//! Running a program through every stage from its source to Hack machine code. Jack is compiled
+//! to VM code, VM code is translated to assembly, and assembly is assembled, with each stage's
+//! output kept for callers which want to show it.
+
+use crate::jack::ast::Class;
+use crate::jack::token::Token;
+use crate::jack::{self, Dialect, Warning};
+use crate::span::{Span, Spanned};
+use crate::translate::{self, SourceMap, TranslateOptions};
+use crate::vm::{self, VmCommand};
+use n2t_asm::assemble::{resolve_labels, to_vec, SymbolTable};
+use n2t_asm::parse::Item;
+use std::fmt::{Display, Formatter};
+use strum_macros::Display;
+
+/// The number of words of ROM the Hack platform has
+const ROM_SIZE: usize = 0x8000;
+
+/// The language a pipeline starts from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Jack,
+    Vm,
+    Asm,
+}
+
+/// The stage of a pipeline which found a problem, in the order they run
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[strum(serialize_all = "lowercase")]
+pub enum Stage {
+    Parse,
+    Check,
+    Compile,
+    Translate,
+    Assemble,
+}
+
+/// Whether a problem stops a pipeline
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+#[strum(serialize_all = "lowercase")]
+pub enum Severity {
+    Error,
+    /// Something which is allowed, but is probably a mistake
+    Warning,
+}
+
+/// A problem found by any stage of a pipeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: String,
+    /// Where in the file the problem is, if it is in one place
+    pub span: Option<Span>,
+    pub stage: Stage,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let warning = match self.severity {
+            Severity::Error => "",
+            Severity::Warning => "warning: ",
+        };
+        match self.span {
+            Some(Span { line, column, .. }) => {
+                write!(
+                    f,
+                    "{}:{line}:{column}: {warning}{}",
+                    self.file, self.message
+                )
+            }
+            None => write!(f, "{}: {warning}{}", self.file, self.message),
+        }
+    }
+}
+
+/// A file of source code given to a pipeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    pub name: String,
+    pub text: String,
+}
+
+/// What each stage of a pipeline produced. The stages after one which found problems are not run,
+/// so their artifacts are left empty.
+#[derive(Debug, Clone, Default)]
+pub struct Artifacts {
+    /// The tokens of each Jack file, including comments
+    pub tokens: Vec<Vec<Spanned<Token>>>,
+    /// The class in each Jack file
+    pub classes: Vec<Class>,
+    /// The VM code of each file, named after the file its spans refer to. The code compiled from
+    /// Jack has the spans of the Jack code, and the OS classes a Jack program needs come last.
+    pub vm: Vec<(String, Vec<Spanned<VmCommand>>)>,
+    /// The VM files linked into one program, with a command on each line
+    pub linked_vm: String,
+    /// The assembly of the whole program, with its labels
+    pub asm: Vec<Item>,
+    /// The assembly as text, with a comment before the code of each VM command
+    pub asm_text: String,
+    /// Maps ROM addresses to the files and lines the code came from, which are Jack files for a
+    /// Jack program
+    pub source_map: SourceMap,
+    /// The labels and variables of the assembly
+    pub symbols: Option<SymbolTable>,
+    pub binary: Vec<u16>,
+}
+
+impl Artifacts {
+    /// The machine code as text, with a word of binary on each line, like the course's .hack files
+    pub fn hack(&self) -> String {
+        self.binary
+            .iter()
+            .map(|word| format!("{word:016b}\n"))
+            .collect()
+    }
+}
+
+/// The settings for compiling a program, which are the same for every stage
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    pub dialect: Dialect,
+    pub optimizations: jack::Optimizations,
+    /// Link the Jack OS classes which a Jack program does not define itself. Since the OS alone
+    /// nearly fills the ROM, calls and returns are then translated to shared routines.
+    pub os: bool,
+    pub translate: TranslateOptions,
+    /// Other Jack files which a program's classes may call, like the rest of the directory when
+    /// only one of its files is compiled. They are only used to check the program, and any which
+    /// do not parse are left out.
+    pub library: Vec<SourceFile>,
+}
+
+impl Pipeline {
+    /// Runs `files`, written in `language`, through each stage from there to machine code. Only
+    /// one assembly file can be given, since assembly has no way of linking files. The
+    /// diagnostics include warnings, which do not stop the pipeline.
+    pub fn run(&self, language: Language, files: &[SourceFile]) -> (Artifacts, Vec<Diagnostic>) {
+        self.run_until(language, files, Stage::Assemble)
+    }
+
+    /// Runs `files` like [`Pipeline::run`], but stops after `last`, so callers which only want
+    /// the assembly are not held to the limits of assembling it
+    pub fn run_until(
+        &self,
+        language: Language,
+        files: &[SourceFile],
+        last: Stage,
+    ) -> (Artifacts, Vec<Diagnostic>) {
+        let mut run = Run {
+            pipeline: self,
+            files,
+            artifacts: Artifacts::default(),
+            diagnostics: Vec::new(),
+        };
+        let done = match language {
+            Language::Jack => run.jack(last),
+            Language::Vm => run.vm(),
+            Language::Asm => run.asm(),
+        } && (language == Language::Asm || last < Stage::Translate || run.translate())
+            && (last < Stage::Assemble || run.assemble());
+        debug_assert!(done || run.failed());
+        (run.artifacts, run.diagnostics)
+    }
+}
+
+/// The state of a pipeline as it runs. Each stage returns whether it succeeded.
+struct Run<'a> {
+    pipeline: &'a Pipeline,
+    files: &'a [SourceFile],
+    artifacts: Artifacts,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Run<'_> {
+    fn report<E: Display>(&mut self, file: &str, stage: Stage, errors: &[Spanned<E>]) {
+        self.report_with(file, stage, Severity::Error, errors);
+    }
+
+    fn report_with<E: Display>(
+        &mut self,
+        file: &str,
+        stage: Stage,
+        severity: Severity,
+        problems: &[Spanned<E>],
+    ) {
+        self.diagnostics
+            .extend(problems.iter().map(|problem| Diagnostic {
+                file: file.to_string(),
+                span: Some(problem.span),
+                stage,
+                severity,
+                message: problem.item.to_string(),
+            }));
+    }
+
+    /// Whether any stage so far found an error
+    fn failed(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    /// Parses, checks and compiles Jack files, stopping early if `last` is before compiling
+    fn jack(&mut self, last: Stage) -> bool {
+        let dialect = self.pipeline.dialect;
+        for file in self.files {
+            let tokens = jack::lex_with(&file.text, dialect).flatten().collect();
+            self.artifacts.tokens.push(tokens);
+            match jack::parse_with(&file.text, dialect) {
+                (Some(class), errors) if errors.is_empty() => self.artifacts.classes.push(class),
+                (_, errors) => self.report(&file.name, Stage::Parse, &errors),
+            }
+        }
+        if self.failed() || last == Stage::Parse {
+            return !self.failed();
+        }
+
+        let classes = &self.artifacts.classes;
+        let library = self.pipeline.library.iter().filter_map(|file| {
+            match jack::parse_with(&file.text, dialect) {
+                (Some(class), errors) if errors.is_empty() => Some(class),
+                _ => None,
+            }
+        });
+        let library = library.filter(|class| {
+            !classes
+                .iter()
+                .any(|defined| defined.name.item == class.name.item)
+        });
+        let checked = classes.iter().cloned().chain(library).collect::<Vec<_>>();
+        // the library's classes come last, so their errors are left out here
+        let errors = jack::check(&checked);
+        for (file, errors) in self.files.iter().zip(&errors) {
+            self.report(&file.name, Stage::Check, errors);
+        }
+        if self.failed() {
+            return false;
+        }
+        let warnings = self
+            .files
+            .iter()
+            .zip(&self.artifacts.classes)
+            .map(|(file, class)| {
+                let warnings = jack::warnings_with(&file.text, class, dialect);
+                let message = |warning: &Warning| format!("{warning} [{}]", warning.code());
+                warnings
+                    .iter()
+                    .map(|warning| Spanned {
+                        item: message(&warning.item),
+                        span: warning.span,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        for (file, warnings) in self.files.iter().zip(&warnings) {
+            self.report_with(&file.name, Stage::Check, Severity::Warning, warnings);
+        }
+        if last == Stage::Check {
+            return true;
+        }
+
+        let optimizations = &self.pipeline.optimizations;
+        let mut classes = self.artifacts.classes.clone();
+        jack::optimize(&mut classes, optimizations);
+        for (file, class) in self.files.iter().zip(&classes) {
+            match jack::compile(class) {
+                Ok(commands) => {
+                    let commands = jack::optimize_vm(commands, optimizations);
+                    self.artifacts.vm.push((file.name.clone(), commands));
+                }
+                Err(errors) => self.report(&file.name, Stage::Compile, &errors),
+            }
+        }
+        if self.pipeline.os {
+            let defined = |name: &str| classes.iter().any(|class| class.name.item == name);
+            let os = jack::os_vm_with(optimizations).into_iter();
+            self.artifacts.vm.extend(
+                os.filter(|(name, _)| !defined(name))
+                    .map(|(name, commands)| (format!("{name}.jack"), commands)),
+            );
+        }
+        !self.failed()
+    }
+
+    fn vm(&mut self) -> bool {
+        for file in self.files {
+            let (commands, errors): (Vec<_>, Vec<_>) =
+                vm::parse(&file.text).partition(Result::is_ok);
+            let errors = errors.into_iter().flat_map(Result::err).collect::<Vec<_>>();
+            self.report(&file.name, Stage::Parse, &errors);
+            let commands = commands.into_iter().flatten().collect();
+            self.artifacts.vm.push((file.name.clone(), commands));
+        }
+        !self.failed()
+    }
+
+    /// Links the VM files and translates them, keeping the files and lines each command came from
+    fn translate(&mut self) -> bool {
+        let origins = self
+            .artifacts
+            .vm
+            .iter()
+            .flat_map(|(file, commands)| commands.iter().map(move |command| (file, command.span)))
+            .collect::<Vec<_>>();
+        let files = self
+            .artifacts
+            .vm
+            .iter()
+            .map(|(_, commands)| commands.clone());
+        self.artifacts.linked_vm = vm::link(files)
+            .iter()
+            .map(|command| format!("{}\n", command.item))
+            .collect();
+
+        let mut options = self.pipeline.translate.clone();
+        options.shared_routines |= self.pipeline.os;
+        let blocks = translate::translate_blocks(&self.artifacts.linked_vm, options)
+            .collect::<Result<Vec<_>, _>>();
+        let blocks = match blocks {
+            Ok(blocks) => blocks,
+            Err(e) => {
+                let (file, span) = origins[e.span.line as usize - 1];
+                self.diagnostics.push(Diagnostic {
+                    file: file.clone(),
+                    span: Some(span),
+                    stage: Stage::Translate,
+                    severity: Severity::Error,
+                    message: e.item.to_string(),
+                });
+                return false;
+            }
+        };
+
+        let mut source_map = SourceMap::new("", &blocks);
+        for entry in &mut source_map.entries {
+            let (file, span) = origins[entry.line as usize - 1];
+            entry.file = file.clone();
+            entry.line = span.line;
+        }
+        self.artifacts.source_map = source_map;
+        self.artifacts.asm = blocks
+            .iter()
+            .flat_map(|block| block.items.iter().cloned())
+            .collect();
+        self.artifacts.asm_text = translate::render_asm(blocks);
+        true
+    }
+
+    fn asm(&mut self) -> bool {
+        let [file] = self.files else {
+            self.diagnostics.push(Diagnostic {
+                file: self
+                    .files
+                    .get(1)
+                    .map_or_else(String::new, |f| f.name.clone()),
+                span: None,
+                stage: Stage::Parse,
+                severity: Severity::Error,
+                message: format!(
+                    "Only one assembly file can be assembled at a time, but {} were given",
+                    self.files.len()
+                ),
+            });
+            return false;
+        };
+        match n2t_asm::parse::items(&file.text) {
+            Ok(items) => {
+                self.artifacts.asm = items;
+                self.artifacts.asm_text = file.text.clone();
+                true
+            }
+            Err((line, e)) => {
+                let offset = file.text.split_inclusive('\n').take(line as usize - 1);
+                let span = Span {
+                    offset: offset.map(str::len).sum(),
+                    len: 0,
+                    line,
+                    column: 1,
+                };
+                self.diagnostics.push(Diagnostic {
+                    file: file.name.clone(),
+                    span: Some(span),
+                    stage: Stage::Parse,
+                    severity: Severity::Error,
+                    message: e.raise().to_string(),
+                });
+                false
+            }
+        }
+    }
+
+    fn assemble(&mut self) -> bool {
+        let (program, mut symbols) = resolve_labels(self.artifacts.asm.iter().cloned());
+        if program.0.len() > ROM_SIZE {
+            self.diagnostics.push(Diagnostic {
+                file: self
+                    .files
+                    .first()
+                    .map_or_else(String::new, |f| f.name.clone()),
+                span: None,
+                stage: Stage::Assemble,
+                severity: Severity::Error,
+                message: format!(
+                    "The program needs {} words of ROM, but there are only {ROM_SIZE}",
+                    program.0.len()
+                ),
+            });
+            return false;
+        }
+        self.artifacts.binary = to_vec(&mut symbols, &program);
+        self.artifacts.symbols = Some(symbols);
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn file(name: &str, text: &str) -> SourceFile {
+        SourceFile {
+            name: name.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn every_language() {
+        let main = "class Main {
+    function void main() {
+        do Main.add(2, 3);
+        return;
+    }
+    function int add(int a, int b) {
+        return a + b;
+    }
+}";
+        let pipeline = Pipeline {
+            os: true,
+            ..Default::default()
+        };
+        let (jack, errors) = pipeline.run(Language::Jack, &[file("Main.jack", main)]);
+        assert_eq!(errors, []);
+        assert_eq!(jack.classes.len(), 1);
+        assert_eq!(jack.vm[0].0, "Main.jack");
+        assert!(jack.vm.iter().any(|(name, _)| name == "Sys.jack"));
+        // the code of `return a + b;` maps back to line 7 of the Jack file
+        let add = jack
+            .asm
+            .iter()
+            .position(|item| matches!(item, Item::Label(label) if label == "Main.add"));
+        assert!(add.is_some());
+        assert!(jack
+            .source_map
+            .entries
+            .iter()
+            .any(|entry| entry.file == "Main.jack" && entry.line == 7));
+        assert!(jack.symbols.is_some());
+
+        // the linked VM code and the assembly produce the same machine code
+        let vm = file("Main.vm", &jack.linked_vm);
+        let (from_vm, errors) = pipeline.run(Language::Vm, &[vm]);
+        assert_eq!(errors, []);
+        assert_eq!(from_vm.binary, jack.binary);
+        let asm = file("Main.asm", &from_vm.asm_text);
+        let (from_asm, errors) = pipeline.run(Language::Asm, &[asm]);
+        assert_eq!(errors, []);
+        assert_eq!(from_asm.binary, jack.binary);
+        assert_eq!(from_asm.hack().lines().count(), jack.binary.len());
+    }
+
+    #[test]
+    fn diagnostics() {
+        let pipeline = Pipeline::default();
+        let (artifacts, errors) = pipeline.run(
+            Language::Jack,
+            &[file(
+                "Main.jack",
+                "class Main {\n  function void f() { let x = 1; return; } }",
+            )],
+        );
+        assert!(artifacts.binary.is_empty());
+        assert_eq!(
+            errors
+                .iter()
+                .map(|e| (e.to_string(), e.stage))
+                .collect::<Vec<_>>(),
+            [(
+                "Main.jack:2:27: `x` is not declared".to_string(),
+                Stage::Check
+            )]
+        );
+
+        let (_, errors) = pipeline.run(
+            Language::Vm,
+            &[
+                file("A.vm", "push constant 1\npop static 200\n"),
+                file("B.vm", "push constant 1\npop static 100\n"),
+            ],
+        );
+        // linking moves B's static past the end of the static segment
+        assert_eq!(errors[0].file, "B.vm");
+        assert_eq!(errors[0].span.map(|span| span.line), Some(2));
+        assert_eq!(errors[0].stage, Stage::Translate);
+
+        let (_, errors) = pipeline.run(Language::Asm, &[file("A.asm", "@1\n\nD=Q\n")]);
+        assert_eq!(errors[0].span.map(|span| span.line), Some(3));
+        assert_eq!(errors[0].stage, Stage::Parse);
+
+        let (_, errors) = pipeline.run(Language::Asm, &[]);
+        assert_eq!(errors[0].stage, Stage::Parse);
+        assert_eq!(errors[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn warnings() {
+        let pipeline = Pipeline::default();
+        let (artifacts, diagnostics) = pipeline.run(
+            Language::Jack,
+            &[file(
+                "Main.jack",
+                "class Main {\n  function void f() { var int x; return; } }",
+            )],
+        );
+        // warnings do not stop the pipeline
+        assert!(!artifacts.binary.is_empty());
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|d| (d.to_string(), d.severity))
+                .collect::<Vec<_>>(),
+            [(
+                "Main.jack:2:31: warning: `x` is never used [unused-variable]".to_string(),
+                Severity::Warning
+            )]
+        );
+    }
+
+    #[test]
+    fn library() {
+        let main = [file(
+            "Main.jack",
+            "class Main { function void main() { do Point.draw(); return; } }",
+        )];
+        let point = file(
+            "Point.jack",
+            "class Point { function void draw() { return; } }",
+        );
+        let (_, errors) = Pipeline::default().run_until(Language::Jack, &main, Stage::Compile);
+        assert_eq!(errors[0].stage, Stage::Check);
+
+        // the library is checked against, but not compiled
+        let pipeline = Pipeline {
+            library: vec![point, file("Broken.jack", "class {")],
+            ..Default::default()
+        };
+        let (artifacts, errors) = pipeline.run_until(Language::Jack, &main, Stage::Compile);
+        assert_eq!(errors, []);
+        assert_eq!(artifacts.vm.len(), 1);
+        assert!(artifacts.asm.is_empty());
+    }
+
+    #[test]
+    fn run_until() {
+        // more code than fits in ROM can still be translated
+        let text = "push constant 1\npop temp 0\n".repeat(5000);
+        let pipeline = Pipeline::default();
+        let (_, errors) = pipeline.run(Language::Vm, &[file("Main.vm", &text)]);
+        assert_eq!(errors[0].stage, Stage::Assemble);
+        let (artifacts, errors) =
+            pipeline.run_until(Language::Vm, &[file("Main.vm", &text)], Stage::Translate);
+        assert_eq!(errors, []);
+        assert!(!artifacts.asm_text.is_empty());
+        assert!(artifacts.binary.is_empty());
+    }
+}
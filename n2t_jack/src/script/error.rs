@@ -0,0 +1,55 @@
+use crate::span::Spanned;
+use crate::vm::{VmParseError, VmRuntimeError};
+use n2t_asm::err::AssemblyError;
+use n2t_script::ParseError;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ScriptError {
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+
+    #[error("Could not access {path:?}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("{0} programs cannot be run by this target")]
+    CannotLoad(&'static str),
+
+    #[error("`{0}` is not supported by this target")]
+    Unsupported(String),
+
+    #[error("`{0}` is not a variable of this target")]
+    UnknownVariable(String),
+
+    #[error("`{0}` is not a value")]
+    BadValue(String),
+
+    #[error("line {}:{}: {}", .0.span.line, .0.span.column, .0.item)]
+    VmParse(Spanned<VmParseError>),
+
+    #[error(transparent)]
+    VmRuntime(#[from] VmRuntimeError),
+
+    #[error(transparent)]
+    Assembly(#[from] AssemblyError),
+
+    #[error("A VM command did not finish within {0} instructions")]
+    Runaway(u64),
+
+    #[error("Comparison failure at line {line}\nexpected: {expected}\nactual:   {actual}")]
+    Comparison {
+        line: usize,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl From<Spanned<VmParseError>> for ScriptError {
+    fn from(e: Spanned<VmParseError>) -> Self {
+        Self::VmParse(e)
+    }
+}
@@ -0,0 +1,371 @@
+//! Test scripts in the course's `.tst` dialect, which load a program, drive it, and record values
+//! from it in an output file which is checked against a comparison file
+
+mod error;
+mod target;
+
+pub use error::ScriptError;
+pub use n2t_script::{Column, Comparison, Condition, Radix, Variable};
+pub use target::{CpuTarget, Program, Target, VmTarget};
+
+use crate::translate::stack::{Location, Segment};
+use crate::vm;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Something that advances the simulation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    VmStep,
+    Tick,
+    Tock,
+    TickTock,
+}
+
+impl n2t_script::Step for Step {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "vmstep" => Some(Step::VmStep),
+            "tick" => Some(Step::Tick),
+            "tock" => Some(Step::Tock),
+            "ticktock" => Some(Step::TickTock),
+            _ => None,
+        }
+    }
+}
+
+pub type Command = n2t_script::Command<Step>;
+pub type Statement = n2t_script::Statement<Step>;
+
+/// Parses a script for the VM emulator or the CPU emulator
+pub fn parse(script: &str) -> Result<Vec<Statement>, ScriptError> {
+    Ok(n2t_script::parse(script)?)
+}
+
+/// Runs scripts against a target, keeping track of the output and comparison files
+pub struct Runner<T> {
+    target: T,
+    /// Files named by the script are relative to this directory
+    dir: PathBuf,
+    output_list: Vec<Column>,
+    output_file: Option<File>,
+    compare: Option<Vec<String>>,
+    /// Every line that has been output so far
+    output: Vec<String>,
+    echoes: Vec<String>,
+}
+
+fn io_error(path: &Path) -> impl FnOnce(std::io::Error) -> ScriptError + '_ {
+    |source| ScriptError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+/// Reads every VM file in a directory as one program, linking them so that each file has its own
+/// statics
+fn read_vm_dir(dir: &Path) -> Result<String, ScriptError> {
+    let mut files = fs::read_dir(dir)
+        .map_err(io_error(dir))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(io_error(dir))?;
+    files.retain(|path| path.extension().is_some_and(|ext| ext == "vm"));
+    files.sort();
+
+    let files = files
+        .iter()
+        .map(|path| {
+            let source = fs::read_to_string(path).map_err(io_error(path))?;
+            Ok(vm::parse(&source).collect::<Result<Vec<_>, _>>()?)
+        })
+        .collect::<Result<Vec<_>, ScriptError>>()?;
+    Ok(vm::link(files)
+        .iter()
+        .map(|command| format!("{}\n", command.item))
+        .collect())
+}
+
+impl<T: Target> Runner<T> {
+    pub fn new(target: T, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            target,
+            dir: dir.into(),
+            output_list: Vec::new(),
+            output_file: None,
+            compare: None,
+            output: Vec::new(),
+            echoes: Vec::new(),
+        }
+    }
+
+    pub fn target(&self) -> &T {
+        &self.target
+    }
+
+    pub fn output(&self) -> &[String] {
+        &self.output
+    }
+
+    /// Whether the output has been checked against a comparison file
+    pub fn compared(&self) -> bool {
+        self.compare.is_some()
+    }
+
+    /// Messages given to `echo`, in order
+    pub fn echoes(&self) -> &[String] {
+        &self.echoes
+    }
+
+    fn load(&mut self, file: Option<&str>) -> Result<(), ScriptError> {
+        let path = match file {
+            Some(file) => self.dir.join(file),
+            None => self.dir.clone(),
+        };
+        let read = |path: &Path| fs::read_to_string(path).map_err(io_error(path));
+
+        let program = match path.extension().and_then(|ext| ext.to_str()) {
+            _ if path.is_dir() => Program::Vm(read_vm_dir(&path)?),
+            Some("vm") => Program::Vm(read(&path)?),
+            Some("asm") => Program::Asm(read(&path)?),
+            Some("hack") => Program::Hack(
+                read(&path)?
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(|line| {
+                        u16::from_str_radix(line.trim(), 2)
+                            .map_err(|_| ScriptError::BadValue(line.to_string()))
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            _ => return Err(ScriptError::Unsupported(format!("load {}", path.display()))),
+        };
+        self.target.load(program)
+    }
+
+    /// Finds where a variable lives in RAM
+    fn address(&self, variable: &Variable) -> Option<usize> {
+        let (ram, config) = (self.target.ram(), self.target.config());
+        match (variable.name.as_str(), variable.index) {
+            ("RAM", Some(index)) => Some(index as usize),
+            ("sp", None) => Some(config.pointers.sp as usize),
+            (name, index) => match Segment::from_str(name)
+                .ok()?
+                .location(index.unwrap_or(0), config)?
+            {
+                Location::Indirect { table, .. } if index.is_none() => Some(table as usize),
+                Location::Indirect { table, offset } => {
+                    usize::try_from(ram[table as usize] as i32 + offset as i32).ok()
+                }
+                Location::Direct(addr) if index.is_some() => Some(addr as usize),
+                _ => None,
+            },
+        }
+        .filter(|addr| *addr < ram.len())
+    }
+
+    fn get(&self, variable: &Variable) -> Result<i16, ScriptError> {
+        let unknown = || ScriptError::UnknownVariable(variable.to_string());
+        match (self.target.register(&variable.name), variable.index) {
+            (Some(value), None) => Ok(value),
+            _ => Ok(self.target.ram()[self.address(variable).ok_or_else(unknown)?]),
+        }
+    }
+
+    fn set(&mut self, variable: &Variable, value: i16) -> Result<(), ScriptError> {
+        let unknown = || ScriptError::UnknownVariable(variable.to_string());
+        if variable.index.is_none() && self.target.set_register(&variable.name, value).is_some() {
+            return Ok(());
+        }
+        let addr = self.address(variable).ok_or_else(unknown)?;
+        self.target.ram_mut()[addr] = value;
+        Ok(())
+    }
+
+    /// Records a line of output, writing it to the output file and checking it against the
+    /// comparison file
+    fn output_line(&mut self, line: String) -> Result<(), ScriptError> {
+        if let Some(file) = &mut self.output_file {
+            writeln!(file, "{line}").map_err(io_error(&self.dir))?;
+        }
+        self.output.push(line);
+
+        let Some(compare) = &self.compare else {
+            return Ok(());
+        };
+        let number = self.output.len();
+        let actual = &self.output[number - 1];
+        let expected = compare.get(number - 1).map_or("", |line| line.trim_end());
+        if actual.trim_end() != expected {
+            return Err(ScriptError::Comparison {
+                line: number,
+                expected: expected.to_string(),
+                actual: actual.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    fn row(
+        &self,
+        cell: impl Fn(&Column) -> Result<String, ScriptError>,
+    ) -> Result<String, ScriptError> {
+        let cells = self
+            .output_list
+            .iter()
+            .map(cell)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(format!("|{}|", cells.join("|")))
+    }
+
+    fn command(&mut self, command: &Command) -> Result<(), ScriptError> {
+        match command {
+            Command::Load(file) => self.load(file.as_deref())?,
+            Command::OutputFile(file) => {
+                let path = self.dir.join(file);
+                self.output_file = Some(File::create(&path).map_err(io_error(&path))?);
+            }
+            Command::CompareTo(file) => {
+                let path = self.dir.join(file);
+                let compare = fs::read_to_string(&path).map_err(io_error(&path))?;
+                self.compare = Some(compare.lines().map(str::to_string).collect());
+            }
+            Command::OutputList(columns) => {
+                self.output_list = columns.clone();
+                let header = self.row(|column| Ok(column.header()))?;
+                self.output_line(header)?;
+            }
+            Command::Set(variable, value) => self.set(variable, *value)?,
+            Command::Output => {
+                let row = self.row(|column| Ok(column.format(self.get(&column.variable)?)))?;
+                self.output_line(row)?;
+            }
+            Command::Step(step) => self.target.step(*step)?,
+            Command::Echo(text) => self.echoes.push(text.clone()),
+            Command::Ignored => (),
+        }
+        Ok(())
+    }
+
+    /// Runs statements. Loops without an end stop once the program has halted.
+    pub fn run(&mut self, statements: &[Statement]) -> Result<(), ScriptError> {
+        for statement in statements {
+            match statement {
+                Statement::Command { command, .. } => self.command(command)?,
+                Statement::Repeat {
+                    count: Some(count),
+                    body,
+                } => (0..*count).try_for_each(|_| self.run(body))?,
+                Statement::Repeat { count: None, body } => loop {
+                    self.run(body)?;
+                    if self.target.halted() {
+                        break;
+                    }
+                },
+                Statement::While { condition, body } => {
+                    while condition
+                        .comparison
+                        .test(self.get(&condition.variable)?, condition.value)
+                    {
+                        self.run(body)?;
+                        if self.target.halted() {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs the script at `path` on `target`, with files it names relative to the script
+pub fn run_file<T: Target>(path: &Path, target: T) -> Result<Runner<T>, ScriptError> {
+    let script = fs::read_to_string(path).map_err(io_error(path))?;
+    let statements = parse(&script)?;
+    let mut runner = Runner::new(target, n2t_script::script_dir(path));
+    runner.run(&statements)?;
+    Ok(runner)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BASIC_TEST: &str = "
+        push constant 10
+        pop local 0
+        push constant 21
+        push constant 22
+        pop argument 2
+        pop argument 1
+        push constant 36
+        pop this 6
+        push constant 510
+        pop temp 6
+        push local 0
+        push argument 1
+        sub
+        push this 6
+        add
+        push temp 6
+        add
+    ";
+
+    const SCRIPT: &str = "
+        output-list RAM[256]%D1.6.1 local[0]%D1.6.1 argument[1]%D1.6.1 this[6]%D1.6.1 RAM[11]%D1.6.1;
+        set sp 256, set local 300, set argument 400, set this 3000;
+        repeat 17 { vmstep; }
+        output;
+    ";
+
+    fn run_on(target: impl Target) -> Vec<String> {
+        let mut runner = Runner::new(target, ".");
+        runner
+            .target
+            .load(Program::Vm(BASIC_TEST.to_string()))
+            .unwrap();
+        runner.run(&parse(SCRIPT).unwrap()).unwrap();
+        runner.output
+    }
+
+    #[test]
+    fn vm_and_cpu_agree() {
+        let expected = [
+            "|RAM[256]|local[0]|argument|this[6] |RAM[11] |",
+            "|    535 |     10 |     21 |     36 |    510 |",
+        ];
+        assert_eq!(run_on(VmTarget::default()), expected);
+        assert_eq!(run_on(CpuTarget::default()), expected);
+    }
+
+    #[test]
+    fn statics_per_file() {
+        let dir = std::env::temp_dir().join("n2t_jack_script_statics");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("Class1.vm"), "push constant 5\npop static 0\n").unwrap();
+        fs::write(dir.join("Class2.vm"), "push constant 7\npop static 0\n").unwrap();
+
+        let mut runner = Runner::new(VmTarget::default(), &dir);
+        let script = "
+            load, output-list RAM[16]%D1.6.1 RAM[17]%D1.6.1;
+            set sp 256, repeat 4 { vmstep; } output;
+        ";
+        runner.run(&parse(script).unwrap()).unwrap();
+        assert_eq!(runner.output[1], "|      5 |      7 |");
+    }
+
+    #[test]
+    fn comparison() {
+        let mut runner = Runner::new(VmTarget::default(), ".");
+        runner.compare = Some(vec!["|  sp  |".to_string(), "|   256 |".to_string()]);
+
+        let script = parse("output-list sp%D1.4.1; set sp 257; output;").unwrap();
+        let Err(ScriptError::Comparison { line, actual, .. }) = runner.run(&script) else {
+            panic!("expected the comparison to fail")
+        };
+        assert_eq!((line, actual.as_str()), (2, "|  257 |"));
+    }
+}
@@ -0,0 +1,253 @@
+use super::{ScriptError, Step};
+use crate::translate::{translate_blocks, SourceMap, TranslateOptions};
+use crate::vm::{VmCommand, VmConfig, VmMachine, VmRuntimeError};
+use n2t_asm::assemble::{resolve_labels, to_vec};
+use n2t_asm::emulate::Cpu;
+use std::collections::HashSet;
+
+/// The most instructions a single `vmstep` may take on the CPU before the script is stopped
+const MAX_VMSTEP: u64 = 100_000;
+
+/// A program which a script has asked to load
+pub enum Program {
+    Vm(String),
+    Asm(String),
+    Hack(Vec<u16>),
+}
+
+/// Something a script can run programs on
+pub trait Target {
+    fn load(&mut self, program: Program) -> Result<(), ScriptError>;
+    /// The memory layout that segment variables like `local[2]` are found with
+    fn config(&self) -> &VmConfig;
+    fn ram(&self) -> &[i16];
+    fn ram_mut(&mut self) -> &mut [i16];
+    /// Reads a variable which does not live in RAM, such as a CPU register
+    fn register(&self, name: &str) -> Option<i16>;
+    /// Sets a variable which does not live in RAM, returning `None` if there is no such variable
+    fn set_register(&mut self, name: &str, value: i16) -> Option<()>;
+    fn step(&mut self, step: Step) -> Result<(), ScriptError>;
+    /// Whether the program has run off its end. Steps taken after this do nothing.
+    fn halted(&self) -> bool;
+}
+
+/// Runs VM code with [`VmMachine`], like the course's VM emulator
+pub struct VmTarget {
+    machine: VmMachine,
+}
+
+impl Default for VmTarget {
+    fn default() -> Self {
+        Self {
+            machine: VmMachine::new(Vec::new()),
+        }
+    }
+}
+
+impl VmTarget {
+    pub fn machine(&self) -> &VmMachine {
+        &self.machine
+    }
+}
+
+impl Target for VmTarget {
+    /// Loads VM code. As in the VM emulator, programs with a `Sys.init` function start there, OS
+    /// functions which the program does not define run natively, and programs with a `Main.main`
+    /// but no `Sys.init` are given one which starts the OS.
+    fn load(&mut self, program: Program) -> Result<(), ScriptError> {
+        let Program::Vm(source) = program else {
+            return Err(ScriptError::CannotLoad("Assembled"));
+        };
+        self.machine = VmMachine::from_source(&source)?;
+        let sys_init = |machine: &VmMachine| {
+            machine.commands().iter().position(|command| {
+                matches!(&command.item, VmCommand::Function { name, .. } if name == "Sys.init")
+            })
+        };
+        let defines_init = sys_init(&self.machine).is_some();
+        self.machine.set_native_os(true);
+        match sys_init(&self.machine) {
+            Some(init) if defines_init => self.machine.set_pc(init),
+            Some(_) => self.machine.bootstrap()?,
+            None => self.machine.set_pc(0),
+        }
+        Ok(())
+    }
+
+    fn config(&self) -> &VmConfig {
+        self.machine.config()
+    }
+
+    fn ram(&self) -> &[i16] {
+        self.machine.ram()
+    }
+
+    fn ram_mut(&mut self) -> &mut [i16] {
+        self.machine.ram_mut()
+    }
+
+    fn register(&self, _: &str) -> Option<i16> {
+        None
+    }
+
+    fn set_register(&mut self, _: &str, _: i16) -> Option<()> {
+        None
+    }
+
+    fn step(&mut self, step: Step) -> Result<(), ScriptError> {
+        match step {
+            Step::VmStep => {
+                // the VM emulator, like the translated code, has nothing to run for a label, so
+                // labels are passed over without taking a step of their own
+                while let Some(VmCommand::Label(_)) =
+                    self.machine.current_command().map(|command| &command.item)
+                {
+                    self.machine.step()?;
+                }
+                match self.machine.step() {
+                    Ok(()) | Err(VmRuntimeError::Halted) => Ok(()),
+                    Err(e) => Err(e.into()),
+                }
+            }
+            _ => Err(ScriptError::Unsupported(format!("{step:?}").to_lowercase())),
+        }
+    }
+
+    fn halted(&self) -> bool {
+        self.machine.current_command().is_none()
+    }
+}
+
+/// Runs Hack code with the CPU emulator. VM code is translated first, which lets VM emulator
+/// scripts check the translator.
+pub struct CpuTarget {
+    cpu: Cpu,
+    options: TranslateOptions,
+    /// The addresses where the code for each VM command starts, if VM code was loaded
+    vm_boundaries: Option<HashSet<u16>>,
+}
+
+impl CpuTarget {
+    pub fn new(options: TranslateOptions) -> Self {
+        Self {
+            cpu: Cpu::new(Vec::new()),
+            options,
+            vm_boundaries: None,
+        }
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+}
+
+impl Default for CpuTarget {
+    fn default() -> Self {
+        Self::new(TranslateOptions::default())
+    }
+}
+
+impl Target for CpuTarget {
+    /// Loads a program. VM code is translated without the bootstrap, so that scripts can set up
+    /// memory themselves, and starts at `Sys.init` if there is one.
+    fn load(&mut self, program: Program) -> Result<(), ScriptError> {
+        self.vm_boundaries = None;
+        let (rom, start) = match program {
+            Program::Hack(rom) => (rom, 0),
+            Program::Asm(source) => {
+                let (program, mut symbols) = n2t_asm::parse::program(&source)?;
+                (to_vec(&mut symbols, &program), 0)
+            }
+            Program::Vm(source) => {
+                let options = TranslateOptions {
+                    config: VmConfig {
+                        bootstrap: false,
+                        ..self.options.config.clone()
+                    },
+                    ..self.options.clone()
+                };
+                let blocks = translate_blocks(&source, options).collect::<Result<Vec<_>, _>>()?;
+
+                let map = SourceMap::new("", &blocks);
+                let boundaries = map
+                    .entries
+                    .iter()
+                    .map(|entry| entry.start)
+                    .collect::<HashSet<_>>();
+
+                let (program, mut symbols) =
+                    resolve_labels(blocks.into_iter().flat_map(|block| block.items));
+                let start = symbols
+                    .get("Sys.init")
+                    .map(|address| address.unwrap())
+                    .unwrap_or(0);
+                self.vm_boundaries = Some(boundaries);
+                (to_vec(&mut symbols, &program), start)
+            }
+        };
+        self.cpu = Cpu::new(rom);
+        self.cpu.pc = start;
+        Ok(())
+    }
+
+    fn config(&self) -> &VmConfig {
+        &self.options.config
+    }
+
+    fn ram(&self) -> &[i16] {
+        &self.cpu.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [i16] {
+        &mut self.cpu.ram
+    }
+
+    fn register(&self, name: &str) -> Option<i16> {
+        match name {
+            "A" => Some(self.cpu.a),
+            "D" => Some(self.cpu.d),
+            "PC" => Some(self.cpu.pc as i16),
+            "time" => Some(self.cpu.time as i16),
+            _ => None,
+        }
+    }
+
+    fn set_register(&mut self, name: &str, value: i16) -> Option<()> {
+        match name {
+            "A" => self.cpu.a = value,
+            "D" => self.cpu.d = value,
+            "PC" => self.cpu.pc = value as u16,
+            _ => return None,
+        }
+        Some(())
+    }
+
+    fn step(&mut self, step: Step) -> Result<(), ScriptError> {
+        match step {
+            // the instruction is executed on the falling edge
+            Step::Tick => (),
+            Step::Tock | Step::TickTock => self.cpu.step(),
+            Step::VmStep => {
+                let boundaries = self
+                    .vm_boundaries
+                    .as_ref()
+                    .ok_or_else(|| ScriptError::Unsupported("vmstep".to_string()))?;
+                let start = self.cpu.time;
+                loop {
+                    self.cpu.step();
+                    if self.halted() || boundaries.contains(&self.cpu.pc) {
+                        break;
+                    }
+                    if self.cpu.time - start >= MAX_VMSTEP {
+                        return Err(ScriptError::Runaway(MAX_VMSTEP));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn halted(&self) -> bool {
+        self.cpu.halted()
+    }
+}
@@ -0,0 +1,26 @@
+/// A region of source text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    /// Byte offset of the first character
+    pub offset: usize,
+    /// Length in bytes
+    pub len: usize,
+    /// Line of the first character, starting from 1
+    pub line: u32,
+    /// Column of the first character in characters, starting from 1
+    pub column: usize,
+}
+
+impl Span {
+    /// Gets the text this span covers
+    pub fn of<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.offset..self.offset + self.len]
+    }
+}
+
+/// Anything which has been read from a source file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spanned<T> {
+    pub item: T,
+    pub span: Span,
+}
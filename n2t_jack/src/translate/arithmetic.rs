@@ -1,126 +1,277 @@
+use super::{prelude, TranslateOptions};
 use n2t_asm::n2tasm;
 use n2t_asm::parse::Item;
-use strum_macros::EnumString;
-
-const ADD: &[Item] = &n2tasm! {
-    {@0}      //Addressing stack pointer
-    {M=(M-1)} // pop stack
-    {A=(M)}
-    {D=(M)}   // retrieve popped stack value
-    {A=(A-1)}
-    {M=(D+M)} // add popped value and stack end value
-};
-
-const SUB: &[Item] = &n2tasm! {
-    {@0}
-    {M=(M-1)}
-    {A=(M)}
-    {D=(M)}
-    {A=(A-1)}
-    {M=(M-D)}
-};
-
-const NEG: &[Item] = &n2tasm! {
-    {@0}
-    {A=(M-1)} // addressing stack end value
-    {M=(-M)}
-};
-
-const AND: &[Item] = &n2tasm! {
-    {@0}
-    {M=(M-1)}
-    {A=(M)}
-    {D=(M)}
-    {A=(A-1)}
-    {M=(D&M)}
-};
-
-const OR: &[Item] = &n2tasm!(
-    {@0}
-    {M=(M-1)}
-    {A=(M)}
-    {D=(M)}
-    {A=(A-1)}
-    {M=(D|M)}
-);
-
-const NOT: &[Item] = &n2tasm!(
-    {@0}
-    {A=(M-1)}
-    {M=(!M)}
-);
-
-const EQ: &[Item] = &n2tasm![
-    {@0}
-    {M=(M-1)}
-    {A=(M)}
-    {D=(M)}
-    {A=(A-1)}
-    {M=(M-D)} // high bit of M is now set if M < D
-    {D=(M+D)} // D is now the initial value of arg1
-    {A=(A+1)}
-    {D=(M-D)} // high bit of D is now set iff M < D
-    {A=(A-1)}
-    {M=(M|D)} // high bit set iff arg1 < arg2 or arg2 < arg1
-    {M=(!M)}  // high bit set iff arg1 = arg2
-
-    {@n:u16::MAX}
-    {D=(A+1)} // loading -1 through overflow
-    {@0}
-    {A=(M-1)}
-    {M=(D&M)} // mask out high bit
-];
-
-const LT: &[Item] = &n2tasm! {
-    {@0}
-    {M=(M-1)}
-    {A=(M)}
-    {D=(M)}
-    {A=(A-1)}
-    {M=(M-D)} // high bit of M is now set iff M < D
-
-    {@n:u16::MAX}
-    {D=(A+1)}
-    {@0}
-    {A=(M-1)}
-    {M=(D&M)} // mask out high bit
-};
-
-const GT: &[Item] = &n2tasm! {
-    {@0}
-    {M=(M-1)}
-    {A=(M)}
-    {D=(M)}
-    {A=(A-1)}
-    {M=(D-M)} // high bit of M is now set iff D < M
-
-    {@n:u16::MAX}
-    {D=(A+1)}
-    {@0}
-    {A=(M-1)}
-    {M=(D&M)} // mask out high bit
-};
+use strum_macros::{Display, EnumString};
+
+fn add(sp: u16) -> Vec<Item> {
+    n2tasm! {
+        {@n:sp}   //Addressing stack pointer
+        {M=(M-1)} // pop stack
+        {A=(M)}
+        {D=(M)}   // retrieve popped stack value
+        {A=(A-1)}
+        {M=(D+M)} // add popped value and stack end value
+    }
+    .to_vec()
+}
+
+fn sub(sp: u16) -> Vec<Item> {
+    n2tasm! {
+        {@n:sp}
+        {M=(M-1)}
+        {A=(M)}
+        {D=(M)}
+        {A=(A-1)}
+        {M=(M-D)}
+    }
+    .to_vec()
+}
+
+fn neg(sp: u16) -> Vec<Item> {
+    n2tasm! {
+        {@n:sp}
+        {A=(M-1)} // addressing stack end value
+        {M=(-M)}
+    }
+    .to_vec()
+}
+
+fn and(sp: u16) -> Vec<Item> {
+    n2tasm! {
+        {@n:sp}
+        {M=(M-1)}
+        {A=(M)}
+        {D=(M)}
+        {A=(A-1)}
+        {M=(D&M)}
+    }
+    .to_vec()
+}
+
+fn or(sp: u16) -> Vec<Item> {
+    n2tasm!(
+        {@n:sp}
+        {M=(M-1)}
+        {A=(M)}
+        {D=(M)}
+        {A=(A-1)}
+        {M=(D|M)}
+    )
+    .to_vec()
+}
+
+fn not(sp: u16) -> Vec<Item> {
+    n2tasm!(
+        {@n:sp}
+        {A=(M-1)}
+        {M=(!M)}
+    )
+    .to_vec()
+}
+
+fn high_bit_eq(sp: u16) -> Vec<Item> {
+    n2tasm![
+        {@n:sp}
+        {M=(M-1)}
+        {A=(M)}
+        {D=(M)}
+        {A=(A-1)}
+        {M=(M-D)} // high bit of M is now set if M < D
+        {D=(M+D)} // D is now the initial value of arg1
+        {A=(A+1)}
+        {D=(M-D)} // high bit of D is now set iff M < D
+        {A=(A-1)}
+        {M=(M|D)} // high bit set iff arg1 < arg2 or arg2 < arg1
+        {M=(!M)}  // high bit set iff arg1 = arg2
+
+        {@n:u16::MAX}
+        {D=(A+1)} // loading -1 through overflow
+        {@n:sp}
+        {A=(M-1)}
+        {M=(D&M)} // mask out high bit
+    ]
+    .to_vec()
+}
+
+fn high_bit_lt(sp: u16) -> Vec<Item> {
+    n2tasm! {
+        {@n:sp}
+        {M=(M-1)}
+        {A=(M)}
+        {D=(M)}
+        {A=(A-1)}
+        {M=(M-D)} // high bit of M is now set iff M < D
+
+        {@n:u16::MAX}
+        {D=(A+1)}
+        {@n:sp}
+        {A=(M-1)}
+        {M=(D&M)} // mask out high bit
+    }
+    .to_vec()
+}
+
+fn high_bit_gt(sp: u16) -> Vec<Item> {
+    n2tasm! {
+        {@n:sp}
+        {M=(M-1)}
+        {A=(M)}
+        {D=(M)}
+        {A=(A-1)}
+        {M=(D-M)} // high bit of M is now set iff D < M
+
+        {@n:u16::MAX}
+        {D=(A+1)}
+        {@n:sp}
+        {A=(M-1)}
+        {M=(D&M)} // mask out high bit
+    }
+    .to_vec()
+}
+
+/// Compares the top two values of the stack for equality. Labels are created by appending to
+/// `prefix`, which must be unique in the program.
+pub(super) fn eq(prefix: &str, sp: u16) -> Vec<Item> {
+    let end = format!("{prefix}_END");
+    n2tasm! {
+        {@n:sp}
+        {AM=(M-1)}
+        {D=(M)}     // pop arg2
+        {A=(A-1)}
+        {D=(M-D)}   // D = arg1 - arg2, which cannot be zero through overflow
+        {M=(-1)}    // assume equality
+
+        {@s:end}
+        {(D);JEQ}
+        {@n:sp}
+        {A=(M-1)}
+        {M=(0)}     // correct the assumption
+        {(s:end)}
+    }
+    .to_vec()
+}
+
+/// Produces `arg1 - arg2` in D in a way that cannot overflow, then jumps to `test`. When the
+/// operands have different signs, the subtraction is skipped and D is set to an arbitrary value of
+/// the correct sign instead.
+fn overflow_safe_difference(prefix: &str, test: &str, sp: u16) -> Vec<Item> {
+    let arg1_neg = format!("{prefix}_NEG");
+    let diff = format!("{prefix}_DIFF");
+    n2tasm! {
+        {@n:sp}
+        {AM=(M-1)}
+        {D=(M)}
+        {@13}
+        {M=(D)}     // pop arg2 into R13
+        {@n:sp}
+        {A=(M-1)}
+        {D=(M)}     // load arg1
+
+        {@s:arg1_neg}
+        {(D);JLT}
+        {@13}
+        {D=(M)}
+        {@s:diff}
+        {(D);JGE}   // both operands are non-negative
+        {D=(1)}     // arg1 >= 0 > arg2
+        {@s:test}
+        {(0);JMP}
+
+        {(s:arg1_neg)}
+        {@13}
+        {D=(M)}
+        {@s:diff}
+        {(D);JLT}   // both operands are negative
+        {D=(-1)}    // arg1 < 0 <= arg2
+        {@s:test}
+        {(0);JMP}
+
+        {(s:diff)}
+        {@13}
+        {D=(M)}
+        {@n:sp}
+        {A=(M-1)}
+        {D=(M-D)}   // operands share a sign, so this subtraction is exact
+    }
+    .to_vec()
+}
+
+pub(super) fn gt(prefix: &str, sp: u16) -> Vec<Item> {
+    let test = format!("{prefix}_TEST");
+    let end = format!("{prefix}_END");
+    let mut items = overflow_safe_difference(prefix, &test, sp);
+    items.extend(n2tasm! {
+        {(s:test)}
+        {@n:sp}
+        {A=(M-1)}
+        {M=(-1)}
+        {@s:end}
+        {(D);JGT}
+        {@n:sp}
+        {A=(M-1)}
+        {M=(0)}
+        {(s:end)}
+    });
+    items
+}
+
+pub(super) fn lt(prefix: &str, sp: u16) -> Vec<Item> {
+    let test = format!("{prefix}_TEST");
+    let end = format!("{prefix}_END");
+    let mut items = overflow_safe_difference(prefix, &test, sp);
+    items.extend(n2tasm! {
+        {(s:test)}
+        {@n:sp}
+        {A=(M-1)}
+        {M=(-1)}
+        {@s:end}
+        {(D);JLT}
+        {@n:sp}
+        {A=(M-1)}
+        {M=(0)}
+        {(s:end)}
+    });
+    items
+}
 
 impl Arithmetic {
-    pub fn translate(self) -> &'static [Item] {
+    /// Translates the operation into assembly. `next_id` is used to create labels which are unique
+    /// within the program, and is advanced whenever one is used.
+    pub fn translate(self, options: &TranslateOptions, next_id: &mut usize) -> Vec<Item> {
+        let mut unique = || {
+            *next_id += 1;
+            *next_id - 1
+        };
+
+        let sp = options.config.pointers.sp;
         match self {
-            Arithmetic::Add => ADD,
-            Arithmetic::Sub => SUB,
-            Arithmetic::Neg => NEG,
-            Arithmetic::Eq => EQ,
-            Arithmetic::Gt => GT,
-            Arithmetic::Lt => LT,
-            Arithmetic::And => AND,
-            Arithmetic::Or => OR,
-            Arithmetic::Not => NOT,
+            Arithmetic::Add => add(sp),
+            Arithmetic::Sub => sub(sp),
+            Arithmetic::Neg => neg(sp),
+            Arithmetic::And => and(sp),
+            Arithmetic::Or => or(sp),
+            Arithmetic::Not => not(sp),
+            Arithmetic::Eq if options.high_bit_comparisons => high_bit_eq(sp),
+            Arithmetic::Gt if options.high_bit_comparisons => high_bit_gt(sp),
+            Arithmetic::Lt if options.high_bit_comparisons => high_bit_lt(sp),
+            Arithmetic::Eq | Arithmetic::Gt | Arithmetic::Lt if options.shared_routines => {
+                let routine = format!("__{}", self.to_string().to_uppercase());
+                prelude::jump_and_link(&routine, format!("{routine}_RET_{}", unique()))
+            }
+            Arithmetic::Eq => eq(&format!("__EQ_{}", unique()), sp),
+            Arithmetic::Gt => gt(&format!("__GT_{}", unique()), sp),
+            Arithmetic::Lt => lt(&format!("__LT_{}", unique()), sp),
         }
     }
 }
 
+/// The arithmetic and logical commands of the VM language.
 ///
-/// Note that for test operations (`Eq`, `Lt`, `Gt`), only the highest bit
-/// marks the boolean (1 for success, 0 for failure)
-#[derive(EnumString, Debug)]
+/// Test operations (`Eq`, `Lt`, `Gt`) push -1 for true and 0 for false, as the VM specification
+/// requires. If [`TranslateOptions::high_bit_comparisons`] is set, they instead only mark the
+/// boolean in the highest bit (1 for success, 0 for failure), and `Lt` and `Gt` may give the wrong
+/// answer when the difference of their operands overflows.
+#[derive(EnumString, Display, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[strum(serialize_all = "lowercase")]
 pub enum Arithmetic {
     Add,
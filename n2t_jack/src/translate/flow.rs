@@ -0,0 +1,33 @@
+use n2t_asm::n2tasm;
+use n2t_asm::parse::Item;
+
+/// Gives the assembly name of a VM label, which is scoped to the function it appears in
+pub fn scoped_label(function: Option<&str>, label: &str) -> String {
+    match function {
+        Some(function) => format!("{function}${label}"),
+        None => label.to_string(),
+    }
+}
+
+pub fn label(name: String) -> Vec<Item> {
+    vec![Item::Label(name)]
+}
+
+pub fn goto(name: String) -> Vec<Item> {
+    n2tasm!(
+        {@s:name}
+        {(0);JMP}
+    )
+    .to_vec()
+}
+
+pub fn if_goto(name: String, sp: u16) -> Vec<Item> {
+    n2tasm!(
+        {@n:sp}
+        {AM=(M-1)}
+        {D=(M)}             // pop the condition
+        {@s:name}
+        {(D);JNE}           // jump if it is not false
+    )
+    .to_vec()
+}
@@ -0,0 +1,195 @@
+use crate::vm::{Pointers, VmConfig};
+use n2t_asm::n2tasm;
+use n2t_asm::parse::Item;
+
+pub fn function(name: String, locals: u16, sp: u16) -> Vec<Item> {
+    let mut items = vec![Item::Label(name)];
+    for _ in 0..locals {
+        items.extend(n2tasm!(
+            {@n:sp}
+            {M=(M+1)}
+            {A=(M-1)}
+            {M=(0)}             // initialize the local to 0
+        ));
+    }
+    items
+}
+
+/// Pushes the value at `addr` onto the stack
+fn push_pointer(addr: u16, sp: u16) -> [Item; 6] {
+    n2tasm!(
+        {@n:addr}
+        {D=(M)}
+        {@n:sp}
+        {M=(M+1)}
+        {A=(M-1)}
+        {M=(D)}
+    )
+}
+
+/// Pushes the return address in D, then saves LCL, ARG, THIS, and THAT
+fn push_frame(config: &VmConfig) -> Vec<Item> {
+    let Pointers {
+        sp,
+        local,
+        argument,
+        this,
+        that,
+    } = config.pointers;
+    let mut items = n2tasm!(
+        {@n:sp}
+        {M=(M+1)}
+        {A=(M-1)}
+        {M=(D)}             // push the return address
+    )
+    .to_vec();
+    [local, argument, this, that]
+        .into_iter()
+        .for_each(|addr| items.extend(push_pointer(addr, sp)));
+    items
+}
+
+pub fn call(name: String, args: u16, return_label: String, config: &VmConfig) -> Vec<Item> {
+    let Pointers { sp, local, argument, .. } = config.pointers;
+    let arg_offset = args + 5;
+    let mut items = n2tasm!(
+        {@s:return_label}
+        {D=(A)}
+    )
+    .to_vec();
+    items.extend(push_frame(config));
+    items.extend(n2tasm!(
+        {@n:sp}
+        {D=(M)}
+        {@n:arg_offset}
+        {D=(D-A)}
+        {@n:argument}
+        {M=(D)}             // ARG = SP - args - 5
+        {@n:sp}
+        {D=(M)}
+        {@n:local}
+        {M=(D)}             // LCL = SP
+
+        {@s:name}
+        {(0);JMP}
+        {(s:return_label)}
+    ));
+    items
+}
+
+/// Restores the pointer at `addr` from the frame saved by the caller, which is walked backwards
+/// through R13
+fn restore_pointer(addr: u16) -> [Item; 5] {
+    n2tasm!(
+        {@13}
+        {AM=(M-1)}
+        {D=(M)}
+        {@n:addr}
+        {M=(D)}
+    )
+}
+
+/// Calls through the shared routine made by [`call_routine`]
+pub fn shared_call(name: String, args: u16, return_label: String) -> Vec<Item> {
+    n2tasm!(
+        {@s:name}
+        {D=(A)}
+        {@13}
+        {M=(D)}             // R13 = function address
+        {@n:args}
+        {D=(A)}
+        {@14}
+        {M=(D)}             // R14 = number of arguments
+        {@s:return_label}
+        {D=(A)}
+        {@__CALL}
+        {(0);JMP}
+        {(s:return_label)}
+    )
+    .to_vec()
+}
+
+/// A routine which calls the function at the address in R13 with the number of arguments in R14,
+/// returning to the address in D
+pub fn call_routine(label: &str, config: &VmConfig) -> Vec<Item> {
+    let Pointers { sp, local, argument, .. } = config.pointers;
+    let label = label.to_string();
+    let mut items = vec![Item::Label(label)];
+    items.extend(push_frame(config));
+    items.extend(n2tasm!(
+        {@n:sp}
+        {D=(M)}
+        {@14}
+        {D=(D-M)}
+        {@5}
+        {D=(D-A)}
+        {@n:argument}
+        {M=(D)}             // ARG = SP - args - 5
+        {@n:sp}
+        {D=(M)}
+        {@n:local}
+        {M=(D)}             // LCL = SP
+
+        {@13}
+        {A=(M)}
+        {(0);JMP}
+    ));
+    items
+}
+
+/// A routine containing the code of [`return_`], which use sites can simply jump to
+pub fn return_routine(label: &str, config: &VmConfig) -> Vec<Item> {
+    let mut items = vec![Item::Label(label.to_string())];
+    items.extend(return_(config));
+    items
+}
+
+pub fn shared_return() -> Vec<Item> {
+    n2tasm!(
+        {@__RETURN}
+        {(0);JMP}
+    )
+    .to_vec()
+}
+
+pub fn return_(config: &VmConfig) -> Vec<Item> {
+    let Pointers {
+        sp,
+        local,
+        argument,
+        this,
+        that,
+    } = config.pointers;
+    let mut items = n2tasm!(
+        {@n:local}
+        {D=(M)}
+        {@13}
+        {M=(D)}             // R13 = frame = LCL
+        {@5}
+        {A=(D-A)}
+        {D=(M)}
+        {@14}
+        {M=(D)}             // R14 = return address = *(frame - 5)
+
+        {@n:sp}
+        {AM=(M-1)}
+        {D=(M)}
+        {@n:argument}
+        {A=(M)}
+        {M=(D)}             // *ARG = pop()
+        {@n:argument}
+        {D=(M+1)}
+        {@n:sp}
+        {M=(D)}             // SP = ARG + 1
+    )
+    .to_vec();
+    [that, this, argument, local]
+        .into_iter()
+        .for_each(|addr| items.extend(restore_pointer(addr)));
+    items.extend(n2tasm!(
+        {@14}
+        {A=(M)}
+        {(0);JMP}
+    ));
+    items
+}
@@ -1,42 +1,483 @@
-mod arithmetic;
+pub mod arithmetic;
+mod flow;
+mod function;
 mod prelude;
-mod stack;
+mod source_map;
+pub mod stack;
 
+use crate::span::Spanned;
+use crate::vm::{self, Op, Optimizations, VmCommand, VmConfig, VmParseError};
 use n2t_asm::parse::Item;
-use std::str::FromStr;
-
-pub fn translate(program: &str) -> impl Iterator<Item = Result<Item, ()>> + '_ {
-    prelude::instruction_prelude().chain(
-        program
-            .lines()
-            .filter_map(|line| {
-                let line = line.split_once("//").map(|(x, _)| x).unwrap_or(line);
-                match line.trim() {
-                    x if x.is_empty() => None,
-                    x => Some(x),
-                }
+use stack::{Segment, Stack};
+
+pub use source_map::{SourceMap, SourceMapEntry};
+
+/// Switches which change the code produced by the translator
+#[derive(Debug, Clone, Default)]
+pub struct TranslateOptions {
+    /// Emit shorter code for `eq`, `gt` and `lt` which leaves the result in the highest bit instead
+    /// of pushing -1 or 0. This is not compatible with the course's test scripts or the Jack OS.
+    pub high_bit_comparisons: bool,
+    pub optimizations: Optimizations,
+    /// Put the code for `eq`, `gt`, `lt`, `call` and `return` in routines which are emitted once
+    /// and jumped to from every use. This is much smaller, but slightly slower.
+    pub shared_routines: bool,
+    /// The memory layout to translate for, and whether to bootstrap the program
+    pub config: VmConfig,
+}
+
+/// The assembly produced by a single line of VM code, or by several lines which the optimizer
+/// combined
+#[derive(Debug, Clone)]
+pub struct Block<'a> {
+    /// The VM code this block was translated from, or `None` for code the translator adds itself
+    pub source: Option<&'a str>,
+    pub command: Option<Spanned<Op>>,
+    pub items: Vec<Item>,
+}
+
+/// State carried from one command to the next
+#[derive(Default)]
+struct Context {
+    options: TranslateOptions,
+    function: Option<String>,
+    next_id: usize,
+}
+
+impl Context {
+    fn unique(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+}
+
+pub fn translate(program: &str) -> impl Iterator<Item = Result<Item, Spanned<VmParseError>>> + '_ {
+    translate_with(program, TranslateOptions::default())
+}
+
+pub fn translate_with(
+    program: &str,
+    options: TranslateOptions,
+) -> impl Iterator<Item = Result<Item, Spanned<VmParseError>>> + '_ {
+    translate_blocks(program, options).flat_map(|block| match block {
+        Ok(block) => block.items.into_iter().map(Ok).collect::<Vec<_>>(),
+        Err(e) => vec![Err(e)],
+    })
+}
+
+/// Translates a program, keeping the assembly for each VM line together with the line itself
+pub fn translate_blocks(
+    program: &str,
+    options: TranslateOptions,
+) -> impl Iterator<Item = Result<Block<'_>, Spanned<VmParseError>>> + '_ {
+    let (ops, error) = match vm::parse(program).collect::<Result<Vec<_>, _>>() {
+        Ok(commands) => (vm::optimize(commands, &options.optimizations), None),
+        Err(e) => (Vec::new(), Some(e)),
+    };
+
+    let sys_init = ops.iter().any(|op| {
+        matches!(&op.item, Op::Command(VmCommand::Function { name, .. }) if name == "Sys.init")
+    });
+    let prelude = prelude::instruction_prelude(&options, sys_init).collect();
+    let mut context = Context {
+        options,
+        ..Default::default()
+    };
+    std::iter::once(Ok(Block {
+        source: None,
+        command: None,
+        items: prelude,
+    }))
+    .chain(error.map(Err))
+    .chain(ops.into_iter().map(move |op| {
+        translate_op(&op.item, &mut context)
+            .map(|items| Block {
+                source: Some(op.span.of(program)),
+                command: Some(op.clone()),
+                items,
             })
-            .flat_map(|instr| {
-                if let Ok(items) = translate_instruction(instr) {
-                    items.into_iter().map(Ok).collect::<Vec<_>>()
-                } else {
-                    vec![]
-                }
-            }),
-    )
-}
-
-fn translate_instruction(instruction: &str) -> Result<Vec<Item>, ()> {
-    let mut commands = instruction.split_whitespace();
-    if let Some(command) = commands.next() {
-        if let Ok(op) = arithmetic::Arithmetic::from_str(command) {
-            Ok(op.translate().iter().cloned().collect::<Vec<_>>())
-        } else if let Ok(stack_access) = stack::Stack::from_str(command) {
-            stack_access.translate(commands)
-        } else {
-            todo!()
+            .map_err(|item| Spanned {
+                item,
+                span: op.span,
+            })
+    }))
+}
+
+/// Counts the instructions a program translates to, which is the amount of ROM it occupies
+pub fn code_size(program: &str, options: TranslateOptions) -> Result<usize, Spanned<VmParseError>> {
+    translate_with(program, options).try_fold(0, |size, item| {
+        Ok(size + matches!(item?, Item::Instruction(_)) as usize)
+    })
+}
+
+/// Renders translated blocks as Hack assembly, with each block preceded by a comment naming the VM
+/// line it came from
+pub fn render_asm<'a>(blocks: impl IntoIterator<Item = Block<'a>>) -> String {
+    blocks
+        .into_iter()
+        .flat_map(|block| {
+            block
+                .source
+                .into_iter()
+                .flat_map(str::lines)
+                .map(|line| format!("// vm: {}", line.trim()))
+                .chain(block.items.iter().map(ToString::to_string))
+                .collect::<Vec<_>>()
+        })
+        .map(|line| line + "\n")
+        .collect()
+}
+
+/// Explains why `segment` has no `index`
+fn bad_index(segment: Segment, index: u16) -> VmParseError {
+    match segment {
+        Segment::Pointer => VmParseError::PointerIndex,
+        Segment::Constant => VmParseError::PopConstant,
+        _ => VmParseError::IndexOutOfRange(segment, index),
+    }
+}
+
+fn translate_op(op: &Op, context: &mut Context) -> Result<Vec<Item>, VmParseError> {
+    let config = &context.options.config;
+    match op {
+        Op::Command(command) => translate_command(command, context),
+        Op::Move { from, to } => {
+            let source = from.0.location(from.1, config);
+            let from = source.ok_or_else(|| bad_index(from.0, from.1))?;
+            match to.0.location(to.1, config) {
+                Some(stack::Location::Constant(_)) => Err(VmParseError::PopConstant),
+                Some(to) => Ok(stack::move_value(from, to)),
+                None => Err(bad_index(to.0, to.1)),
+            }
         }
-    } else {
-        Err(())
+    }
+}
+
+fn translate_command(command: &VmCommand, context: &mut Context) -> Result<Vec<Item>, VmParseError> {
+    let function = context.function.as_deref();
+    let config = &context.options.config;
+    let sp = config.pointers.sp;
+    Ok(match command {
+        VmCommand::Arithmetic(op) => op.translate(&context.options, &mut context.next_id),
+        VmCommand::Push(segment, index) if context.options.optimizations.small_constants => segment
+            .translate_push_small(*index, config)
+            .map_err(|()| bad_index(*segment, *index))?,
+        VmCommand::Push(segment, index) => segment
+            .translate(*index, &Stack::Push, config)
+            .map_err(|()| bad_index(*segment, *index))?,
+        VmCommand::Pop(segment, index) => segment
+            .translate(*index, &Stack::Pop, config)
+            .map_err(|()| bad_index(*segment, *index))?,
+        VmCommand::Label(label) => flow::label(flow::scoped_label(function, label)),
+        VmCommand::Goto(label) => flow::goto(flow::scoped_label(function, label)),
+        VmCommand::IfGoto(label) => flow::if_goto(flow::scoped_label(function, label), sp),
+        VmCommand::Function { name, locals } => {
+            context.function = Some(name.clone());
+            function::function(name.clone(), *locals, sp)
+        }
+        VmCommand::Call { name, args } => {
+            let id = context.unique();
+            let return_label = format!("{}$ret.{id}", context.function.as_deref().unwrap_or(""));
+            if context.options.shared_routines {
+                function::shared_call(name.clone(), *args, return_label)
+            } else {
+                function::call(name.clone(), *args, return_label, &context.options.config)
+            }
+        }
+        VmCommand::Return if context.options.shared_routines => function::shared_return(),
+        VmCommand::Return => function::return_(config),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use n2t_asm::assemble::{resolve_labels, to_vec};
+    use n2t_asm::emulate::Cpu;
+
+    /// Runs a program on the CPU emulator until it runs off the end of the ROM or the step limit is
+    /// reached
+    fn run(items: Vec<Item>, ram: &mut [i16]) {
+        let (program, mut symbols) = resolve_labels(items);
+        let mut cpu = Cpu::new(to_vec(&mut symbols, &program));
+        cpu.run(10_000);
+        ram.copy_from_slice(&cpu.ram[..ram.len()]);
+    }
+
+    #[test]
+    fn render() {
+        let blocks = translate_blocks("push constant 7 // seven\nneg", TranslateOptions::default())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let asm = render_asm(blocks);
+
+        assert!(asm.starts_with("@256\nD=A\n@0\nM=D\n"));
+        assert!(asm.contains("// vm: push constant 7\n@7\nD=A\n"));
+        assert!(asm.ends_with("// vm: neg\n@0\nA=M-1\nM=-M\n"));
+    }
+
+    fn stack_after(program: &str, options: TranslateOptions) -> Vec<i16> {
+        let mut ram = vec![0; 512];
+        run(translate_with(program, options).map(Result::unwrap).collect(), &mut ram);
+        ram[256..ram[0] as usize].to_vec()
+    }
+
+    #[test]
+    fn comparisons() {
+        let program = "
+            push constant 7
+            push constant 7
+            eq
+            push constant 7
+            push constant 8
+            eq
+            push constant 8
+            push constant 7
+            gt
+            push constant 7
+            push constant 8
+            gt
+            push constant 7
+            push constant 8
+            lt
+            push constant 8
+            push constant 7
+            lt
+        ";
+
+        assert_eq!(
+            stack_after(program, TranslateOptions::default()),
+            [-1, 0, -1, 0, -1, 0]
+        );
+    }
+
+    #[test]
+    fn comparisons_do_not_overflow() {
+        // 32767 and -32767 (through 0 - 32767), whose difference overflows
+        let program = "
+            push constant 32767
+            push constant 0
+            push constant 32767
+            sub
+            gt
+            push constant 0
+            push constant 32767
+            sub
+            push constant 32767
+            gt
+            push constant 32767
+            push constant 0
+            push constant 32767
+            sub
+            lt
+            push constant 0
+            push constant 32767
+            sub
+            push constant 32767
+            lt
+        ";
+
+        assert_eq!(
+            stack_after(program, TranslateOptions::default()),
+            [-1, 0, 0, -1]
+        );
+    }
+
+    #[test]
+    fn call_and_return() {
+        let program = "
+            push constant 3
+            push constant 4
+            call Add.two 2
+            label END
+            goto END
+
+            function Add.two 1
+            push argument 0
+            push argument 1
+            add
+            pop local 0
+            push local 0
+            return
+        ";
+
+        let mut ram = vec![0; 512];
+        run(translate(program).map(Result::unwrap).collect(), &mut ram);
+        assert_eq!(ram[0..5], [257, 0, 0, 0, 0]);
+        assert_eq!(ram[256], 7);
+    }
+
+    #[test]
+    fn optimizations_preserve_behavior() {
+        let program = "
+            push constant 1
+            push constant 2
+            add
+            pop temp 0
+            push constant 300
+            pop pointer 1
+            push temp 0
+            pop that 0
+            push constant 0
+            push constant 1
+            push that 0
+            pop that 5
+            push that 5
+            push that 0
+            push constant 0
+            goto END
+            push constant 9
+            label END
+        ";
+
+        let plain = stack_after(program, TranslateOptions::default());
+        let optimized = stack_after(
+            program,
+            TranslateOptions {
+                optimizations: Optimizations::all(),
+                ..Default::default()
+            },
+        );
+        assert_eq!(plain, [0, 1, 3, 3, 0]);
+        assert_eq!(plain, optimized);
+
+        let size = |optimizations| {
+            let options = TranslateOptions {
+                optimizations,
+                ..Default::default()
+            };
+            translate_with(program, options).count()
+        };
+        assert!(size(Optimizations::all()) < size(Optimizations::default()));
+    }
+
+    #[test]
+    fn shared_routines() {
+        let program = "
+            push constant 3
+            push constant 4
+            call Cmp.all 2
+            label END
+            goto END
+
+            function Cmp.all 0
+            push argument 0
+            push argument 1
+            lt
+            push argument 0
+            push argument 1
+            gt
+            push argument 0
+            push argument 0
+            eq
+            add
+            add
+            return
+        ";
+        let shared = TranslateOptions {
+            shared_routines: true,
+            ..Default::default()
+        };
+
+        let mut ram = vec![0; 512];
+        run(translate_with(program, shared.clone()).map(Result::unwrap).collect(), &mut ram);
+        assert_eq!(ram[0..5], [257, 0, 0, 0, 0]);
+        assert_eq!(ram[256], -2);
+
+        // the routines only pay for themselves once there are enough use sites
+        let repeated = program.replace("call Cmp.all 2", &"call Cmp.all 2\n".repeat(20));
+        assert!(
+            code_size(&repeated, shared).unwrap()
+                < code_size(&repeated, TranslateOptions::default()).unwrap()
+        );
+    }
+
+    #[test]
+    fn memory_layout() {
+        let program = "
+            function Sys.init 1
+            push constant 3
+            pop static 2
+            push constant 7
+            pop local 0
+            push constant 4
+            pop temp 1
+            push static 2
+            push local 0
+            add
+            label END
+            goto END
+        ";
+        let config = VmConfig {
+            pointers: vm::Pointers {
+                sp: 20,
+                local: 21,
+                argument: 22,
+                this: 23,
+                that: 24,
+            },
+            stack_base: 1000,
+            temp: 30..38,
+            statics: 40..50,
+            ..Default::default()
+        };
+        let options = TranslateOptions {
+            config: config.clone(),
+            ..Default::default()
+        };
+
+        let mut ram = vec![0; 1024];
+        run(translate_with(program, options.clone()).map(Result::unwrap).collect(), &mut ram);
+        assert_eq!((ram[0], ram[20], ram[21]), (0, 1007, 1005));
+        assert_eq!((ram[42], ram[31], ram[1006]), (3, 4, 10));
+
+        let mut machine =
+            vm::VmMachine::with_config(vm::parse(program).map(Result::unwrap).collect(), config);
+        machine.bootstrap().unwrap();
+        machine.run(100).unwrap();
+        // the saved return addresses at 1000 differ, since the machine counts in commands
+        assert_eq!(machine.ram()[..1000], ram[..1000]);
+        assert_eq!(machine.ram()[1001..1024], ram[1001..]);
+
+        let out_of_range = translate_with("push static 10", options.clone()).last().unwrap();
+        assert_eq!(
+            out_of_range.unwrap_err().item,
+            VmParseError::IndexOutOfRange(Segment::Static, 10)
+        );
+    }
+
+    #[test]
+    fn without_bootstrap() {
+        let options = TranslateOptions {
+            config: VmConfig {
+                bootstrap: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let blocks = translate_blocks("function Sys.init 0\npush constant 1", options)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(blocks[0].items.is_empty());
+
+        let bootstrapped = translate_blocks("function Sys.init 0", TranslateOptions::default())
+            .next()
+            .unwrap()
+            .unwrap();
+        assert!(bootstrapped
+            .items
+            .iter()
+            .any(|item| matches!(item, Item::Label(label) if label == "__BOOTSTRAP_RET")));
+    }
+
+    #[test]
+    fn high_bit_comparisons() {
+        let options = TranslateOptions {
+            high_bit_comparisons: true,
+            ..Default::default()
+        };
+        let stack = stack_after("push constant 3\npush constant 5\nlt", options);
+
+        assert!(stack[0] < 0);
     }
 }
@@ -1,17 +1,91 @@
+use super::{arithmetic, function, TranslateOptions};
+use crate::vm::VmConfig;
 use n2t_asm::{n2tasm, parse::Item};
 
-pub fn instruction_prelude() -> impl Iterator<Item = Result<Item, ()>> {
-    INSTRUCTION_PRELUDE.into_iter().cloned().map(|it| Ok(it))
+/// Code which runs before the program. `sys_init` says whether the program defines `Sys.init`,
+/// which the bootstrap calls if it is enabled.
+pub fn instruction_prelude(
+    options: &TranslateOptions,
+    sys_init: bool,
+) -> impl Iterator<Item = Item> {
+    let config = &options.config;
+    let mut items = Vec::new();
+    if config.bootstrap {
+        items.extend(bootstrap(config, sys_init));
+    }
+    if options.shared_routines {
+        items.extend(n2tasm!(
+            {@__START}
+            {(0);JMP}           // skip over the shared routines
+        ));
+        items.extend(shared_routine_definitions(config));
+        items.extend(n2tasm!({(__START)}));
+    }
+    items.into_iter()
 }
 
-const INSTRUCTION_PRELUDE: &[Item] = &n2tasm!(
-    {@256}
-    {D=(A)}
-    {@0}
-    {M=(D)} // set stack pointer to 256
+fn bootstrap(config: &VmConfig, sys_init: bool) -> Vec<Item> {
+    let (base, sp) = (config.stack_base, config.pointers.sp);
+    let mut items = n2tasm!(
+        {@n:base}
+        {D=(A)}
+        {@n:sp}
+        {M=(D)}             // set the stack pointer to the base of the stack
+    )
+    .to_vec();
+    if sys_init {
+        items.extend(function::call(
+            "Sys.init".to_string(),
+            0,
+            "__BOOTSTRAP_RET".to_string(),
+            config,
+        ));
+    }
+    items
+}
+
+/// Jumps to a shared routine, which will come back to `return_label` once it is done. The return
+/// address is passed to the routine through D.
+pub fn jump_and_link(routine: &str, return_label: String) -> Vec<Item> {
+    let routine = routine.to_string();
+    n2tasm!(
+        {@s:return_label}
+        {D=(A)}
+        {@s:routine}
+        {(0);JMP}
+        {(s:return_label)}
+    )
+    .to_vec()
+}
 
-    // {@1015}
-    // {D=(A)}
-    // {@1}
-    // {M=(D)} // set local pointer to 1015
-);
+/// Wraps the body of a routine so that it saves the return address it was given in R15 and jumps
+/// back there once it is done
+fn routine(name: &str, body: Vec<Item>) -> Vec<Item> {
+    let name = name.to_string();
+    let mut items = n2tasm!(
+        {(s:name)}
+        {@15}
+        {M=(D)}
+    )
+    .to_vec();
+    items.extend(body);
+    items.extend(n2tasm!(
+        {@15}
+        {A=(M)}
+        {(0);JMP}
+    ));
+    items
+}
+
+/// One copy each of the code behind `eq`, `gt`, `lt`, `call` and `return`, which use sites jump to
+/// instead of repeating it
+fn shared_routine_definitions(config: &VmConfig) -> Vec<Item> {
+    let sp = config.pointers.sp;
+    let mut items = Vec::new();
+    items.extend(routine("__EQ", arithmetic::eq("__EQ", sp)));
+    items.extend(routine("__GT", arithmetic::gt("__GT", sp)));
+    items.extend(routine("__LT", arithmetic::lt("__LT", sp)));
+    items.extend(function::call_routine("__CALL", config));
+    items.extend(function::return_routine("__RETURN", config));
+    items
+}
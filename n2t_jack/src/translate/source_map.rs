@@ -0,0 +1,93 @@
+use super::Block;
+use n2t_asm::parse::Item;
+use serde::{Deserialize, Serialize};
+
+/// The ROM addresses produced by a single VM command, or by several that the optimizer combined
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceMapEntry {
+    /// The first address of the command's code
+    pub start: u16,
+    /// One past the last address of the command's code
+    pub end: u16,
+    pub file: String,
+    /// The line of the command, starting from 1
+    pub line: u32,
+    pub command: String,
+}
+
+/// Maps ROM addresses of translated code back to the VM commands they came from. Code which the
+/// translator adds itself, like the bootstrap, is not mapped.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceMap {
+    /// Entries in order of address, none of which are empty
+    pub entries: Vec<SourceMapEntry>,
+}
+
+impl SourceMap {
+    /// Builds the map for a program which was translated from `file`, assuming its code is placed
+    /// in ROM starting at address 0
+    pub fn new<'a>(file: &str, blocks: impl IntoIterator<Item = &'a Block<'a>>) -> Self {
+        let mut entries = Vec::new();
+        let mut address = 0;
+        for block in blocks {
+            let start = address;
+            address += block
+                .items
+                .iter()
+                .filter(|item| matches!(item, Item::Instruction(_)))
+                .count() as u16;
+
+            if let (Some(command), true) = (&block.command, address > start) {
+                entries.push(SourceMapEntry {
+                    start,
+                    end: address,
+                    file: file.to_string(),
+                    line: command.span.line,
+                    command: command.item.to_string(),
+                });
+            }
+        }
+        Self { entries }
+    }
+
+    /// Finds the command which produced the instruction at `address`
+    pub fn lookup(&self, address: u16) -> Option<&SourceMapEntry> {
+        let index = self.entries.partition_point(|entry| entry.end <= address);
+        self.entries
+            .get(index)
+            .filter(|entry| entry.start <= address)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::translate::{translate_blocks, TranslateOptions};
+
+    #[test]
+    fn lookup() {
+        let program = "push constant 1\nlabel LOOP\n\nneg\ngoto LOOP";
+        let blocks = translate_blocks(program, TranslateOptions::default())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let map = SourceMap::new("Main.vm", &blocks);
+        let prelude = blocks[0].items.len() as u16;
+
+        // the label takes no space, so it does not appear
+        let commands = map
+            .entries
+            .iter()
+            .map(|entry| (entry.line, entry.command.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            commands,
+            [(1, "push constant 1"), (4, "neg"), (5, "goto LOOP")]
+        );
+
+        assert_eq!(map.lookup(0), None);
+        assert_eq!(map.lookup(prelude).unwrap().line, 1);
+        assert_eq!(map.lookup(map.entries[1].start).unwrap().command, "neg");
+        assert_eq!(map.lookup(map.entries[2].end - 1).unwrap().line, 5);
+        assert_eq!(map.lookup(map.entries[2].end), None);
+    }
+}
@@ -1,9 +1,14 @@
+use crate::vm::VmConfig;
 use n2t_asm::n2tasm;
 use n2t_asm::parse::Item;
-use std::str::FromStr;
-use strum_macros::EnumString;
+use strum_macros::{Display, EnumString};
 
-fn segment_table_addr(table_offset: u16, segment_offset: u16, push_or_pop: &Stack) -> Vec<Item> {
+fn segment_table_addr(
+    table_offset: u16,
+    segment_offset: u16,
+    push_or_pop: &Stack,
+    sp: u16,
+) -> Vec<Item> {
     match push_or_pop {
         Stack::Push => n2tasm!(
             {@n:table_offset}   // get the base ptr to the table
@@ -12,7 +17,7 @@ fn segment_table_addr(table_offset: u16, segment_offset: u16, push_or_pop: &Stac
             {A=(D+A)}           // add the offset to the base ptr
             {D=(M)}             // store the value at the offset to D
 
-            {@0}
+            {@n:sp}
             {M=(M+1)}           // increment the stack ptr
             {A=(M-1)}           // get the empty stack location
 
@@ -25,7 +30,7 @@ fn segment_table_addr(table_offset: u16, segment_offset: u16, push_or_pop: &Stac
             {@n:segment_offset}
             {D=(D+A)}           // calculate destination ptr and store it in D
 
-            {@0}
+            {@n:sp}
             {M=(M-1)}           // decrement the stack ptr
             {A=(M+1)}           // go one above the end of the stack
             {M=(D)}             // store destination ptr above the stack
@@ -41,24 +46,20 @@ fn segment_table_addr(table_offset: u16, segment_offset: u16, push_or_pop: &Stac
     }
 }
 
-fn segment_static_addr(addr: u16, segment_offset: u16, push_or_pop: &Stack) -> Vec<Item> {
-    static_addr(addr + segment_offset, push_or_pop)
-}
-
-fn static_addr(addr: u16, push_or_pop: &Stack) -> Vec<Item> {
+fn static_addr(addr: u16, push_or_pop: &Stack, sp: u16) -> Vec<Item> {
     match push_or_pop {
         Stack::Push => n2tasm!(
             {@n:addr}
             {D=(M)}
 
-            {@0}
+            {@n:sp}
             {M=(M+1)}
             {A=(M-1)}
 
             {M=(D)}
         ),
         Stack::Pop => n2tasm!(
-            {@0}
+            {@n:sp}
             {M=(M-1)}
             {A=(M)}
             {D=(M)}             // perform pop
@@ -70,12 +71,12 @@ fn static_addr(addr: u16, push_or_pop: &Stack) -> Vec<Item> {
     .to_vec()
 }
 
-fn push_const(value: u16) -> Vec<Item> {
+fn push_const(value: u16, sp: u16) -> Vec<Item> {
     n2tasm!(
         {@n:value}
         {D=(A)}
 
-        {@0}
+        {@n:sp}
         {M=(M+1)}
         {A=(M-1)}
         {M=(D)}
@@ -83,40 +84,175 @@ fn push_const(value: u16) -> Vec<Item> {
     .to_vec()
 }
 
-impl Stack {
-    pub fn translate<'a>(&self, mut words: impl Iterator<Item = &'a str>) -> Result<Vec<Item>, ()> {
-        if let Some(Ok(segment)) = words.next().map(|seg_ident| Segment::from_str(seg_ident)) {
-            let offset = words
-                .next()
-                .map(|s| u16::from_str_radix(s, 10).map_err(|_| ()))
-                .unwrap_or(Err(()))?;
-            segment.translate(offset, self)
-        } else {
-            Err(())
+/// Pushes 0 or 1 without going through the D register
+fn push_small_const(value: u16, sp: u16) -> Vec<Item> {
+    match value {
+        0 => n2tasm!(
+            {@n:sp}
+            {M=(M+1)}
+            {A=(M-1)}
+            {M=(0)}
+        ),
+        _ => n2tasm!(
+            {@n:sp}
+            {M=(M+1)}
+            {A=(M-1)}
+            {M=(1)}
+        ),
+    }
+    .to_vec()
+}
+
+/// Where the value at some index of a segment lives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    /// At an offset from the base pointer stored in the given register
+    Indirect { table: u16, offset: u16 },
+    /// At a fixed address
+    Direct(u16),
+    /// Not in memory at all
+    Constant(u16),
+}
+
+/// Loads the value at `location` into D
+fn load(location: Location) -> Vec<Item> {
+    match location {
+        Location::Indirect { table, offset: 0 } => n2tasm!(
+            {@n:table}
+            {A=(M)}
+            {D=(M)}
+        )
+        .to_vec(),
+        Location::Indirect { table, offset } => n2tasm!(
+            {@n:table}
+            {D=(M)}
+            {@n:offset}
+            {A=(D+A)}
+            {D=(M)}
+        )
+        .to_vec(),
+        Location::Direct(addr) => n2tasm!(
+            {@n:addr}
+            {D=(M)}
+        )
+        .to_vec(),
+        Location::Constant(value) => n2tasm!(
+            {@n:value}
+            {D=(A)}
+        )
+        .to_vec(),
+    }
+}
+
+/// Copies the value at `from` into `to` without touching the stack
+pub fn move_value(from: Location, to: Location) -> Vec<Item> {
+    match to {
+        Location::Direct(addr) => {
+            let mut items = load(from);
+            items.extend(n2tasm!(
+                {@n:addr}
+                {M=(D)}
+            ));
+            items
         }
+        // walking to a nearby address is cheaper than calculating it ahead of time
+        Location::Indirect { table, offset } if offset <= 3 => {
+            let mut items = load(from);
+            items.extend(n2tasm!(
+                {@n:table}
+                {A=(M)}
+            ));
+            (0..offset).for_each(|_| items.extend(n2tasm!({A=(A+1)})));
+            items.extend(n2tasm!({M=(D)}));
+            items
+        }
+        Location::Indirect { table, offset } => {
+            let mut items = n2tasm!(
+                {@n:table}
+                {D=(M)}
+                {@n:offset}
+                {D=(D+A)}
+                {@13}
+                {M=(D)}         // store the destination in R13
+            )
+            .to_vec();
+            items.extend(load(from));
+            items.extend(n2tasm!(
+                {@13}
+                {A=(M)}
+                {M=(D)}
+            ));
+            items
+        }
+        Location::Constant(_) => unreachable!("constants cannot be written to"),
     }
 }
 
 impl Segment {
-    pub fn translate(&self, offset: u16, push_or_pop: &Stack) -> Result<Vec<Item>, ()> {
+    /// Finds where an index of the segment lives under the memory model in `config`, or `None` if
+    /// the segment has no such index
+    pub fn location(&self, offset: u16, config: &VmConfig) -> Option<Location> {
+        let pointers = &config.pointers;
+        let within = |range: &std::ops::Range<u16>| {
+            let addr = range.start.checked_add(offset)?;
+            range.contains(&addr).then_some(Location::Direct(addr))
+        };
         match self {
-            Segment::Local => Ok(segment_table_addr(1, offset, push_or_pop)),
-            Segment::Argument => Ok(segment_table_addr(2, offset, push_or_pop)),
-            Segment::This => Ok(segment_table_addr(3, offset, push_or_pop)),
-            Segment::That => Ok(segment_table_addr(4, offset, push_or_pop)),
-            Segment::Constant => match push_or_pop {
-                Stack::Push => Ok(push_const(offset)),
-                Stack::Pop => Err(()),
-            },
-            Segment::Static => Ok(segment_static_addr(16, offset, push_or_pop)),
-            Segment::Temp => Ok(segment_static_addr(5, offset, push_or_pop)),
+            Segment::Local => Some(Location::Indirect {
+                table: pointers.local,
+                offset,
+            }),
+            Segment::Argument => Some(Location::Indirect {
+                table: pointers.argument,
+                offset,
+            }),
+            Segment::This => Some(Location::Indirect {
+                table: pointers.this,
+                offset,
+            }),
+            Segment::That => Some(Location::Indirect {
+                table: pointers.that,
+                offset,
+            }),
+            Segment::Constant => Some(Location::Constant(offset)),
+            Segment::Static => within(&config.statics),
+            Segment::Temp => within(&config.temp),
             Segment::Pointer => match offset {
-                0 => Ok(static_addr(3, push_or_pop)),
-                1 => Ok(static_addr(4, push_or_pop)),
-                _ => Err(()),
+                0 => Some(Location::Direct(pointers.this)),
+                1 => Some(Location::Direct(pointers.that)),
+                _ => None,
             },
         }
     }
+
+    pub(crate) fn translate(
+        &self,
+        offset: u16,
+        push_or_pop: &Stack,
+        config: &VmConfig,
+    ) -> Result<Vec<Item>, ()> {
+        let sp = config.pointers.sp;
+        match (self.location(offset, config).ok_or(())?, push_or_pop) {
+            (Location::Indirect { table, offset }, _) => {
+                Ok(segment_table_addr(table, offset, push_or_pop, sp))
+            }
+            (Location::Direct(addr), _) => Ok(static_addr(addr, push_or_pop, sp)),
+            (Location::Constant(value), Stack::Push) => Ok(push_const(value, sp)),
+            (Location::Constant(_), Stack::Pop) => Err(()),
+        }
+    }
+
+    /// Like [`Segment::translate`] for pushes, but uses shorter sequences where they exist
+    pub(crate) fn translate_push_small(
+        &self,
+        offset: u16,
+        config: &VmConfig,
+    ) -> Result<Vec<Item>, ()> {
+        match self {
+            Segment::Constant if offset <= 1 => Ok(push_small_const(offset, config.pointers.sp)),
+            _ => self.translate(offset, &Stack::Push, config),
+        }
+    }
 }
 
 #[derive(EnumString, Debug, PartialEq, Eq)]
@@ -126,7 +262,7 @@ pub enum Stack {
     Pop,
 }
 
-#[derive(EnumString)]
+#[derive(EnumString, Display, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[strum(serialize_all = "lowercase")]
 pub enum Segment {
     Local,
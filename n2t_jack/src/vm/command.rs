@@ -0,0 +1,33 @@
+use crate::translate::arithmetic::Arithmetic;
+use crate::translate::stack::Segment;
+use std::fmt::{Display, Formatter};
+
+/// A single command of the VM language
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmCommand {
+    Arithmetic(Arithmetic),
+    Push(Segment, u16),
+    Pop(Segment, u16),
+    Label(String),
+    Goto(String),
+    IfGoto(String),
+    Function { name: String, locals: u16 },
+    Call { name: String, args: u16 },
+    Return,
+}
+
+impl Display for VmCommand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VmCommand::Arithmetic(op) => write!(f, "{op}"),
+            VmCommand::Push(segment, index) => write!(f, "push {segment} {index}"),
+            VmCommand::Pop(segment, index) => write!(f, "pop {segment} {index}"),
+            VmCommand::Label(label) => write!(f, "label {label}"),
+            VmCommand::Goto(label) => write!(f, "goto {label}"),
+            VmCommand::IfGoto(label) => write!(f, "if-goto {label}"),
+            VmCommand::Function { name, locals } => write!(f, "function {name} {locals}"),
+            VmCommand::Call { name, args } => write!(f, "call {name} {args}"),
+            VmCommand::Return => write!(f, "return"),
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use std::ops::Range;
+
+/// The registers holding the stack pointer and the base address of each segment which can move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pointers {
+    pub sp: u16,
+    pub local: u16,
+    pub argument: u16,
+    pub this: u16,
+    pub that: u16,
+}
+
+impl Default for Pointers {
+    fn default() -> Self {
+        Self {
+            sp: 0,
+            local: 1,
+            argument: 2,
+            this: 3,
+            that: 4,
+        }
+    }
+}
+
+/// Where the VM keeps its stack and segments in RAM. The default is the layout from the course.
+///
+/// R13 to R15 are always used as scratch space by translated code, so nothing here should overlap
+/// them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VmConfig {
+    pub pointers: Pointers,
+    /// Where the stack starts when the program is bootstrapped
+    pub stack_base: u16,
+    pub temp: Range<u16>,
+    pub statics: Range<u16>,
+    /// Whether to set the stack pointer and call `Sys.init` before anything else. Project 7's test
+    /// scripts set up memory themselves and expect this to be off.
+    pub bootstrap: bool,
+}
+
+impl Default for VmConfig {
+    fn default() -> Self {
+        Self {
+            pointers: Pointers::default(),
+            stack_base: 256,
+            temp: 5..13,
+            statics: 16..256,
+            bootstrap: true,
+        }
+    }
+}
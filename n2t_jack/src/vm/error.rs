@@ -0,0 +1,32 @@
+use crate::translate::stack::Segment;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum VmParseError {
+    #[error("`{0}` is not a VM command")]
+    UnknownCommand(String),
+
+    #[error("`{0}` is not a memory segment")]
+    UnknownSegment(String),
+
+    #[error("Expected {0}")]
+    Missing(&'static str),
+
+    #[error("`{0}` is not a number between 0 and 32767")]
+    BadNumber(String),
+
+    #[error("`{0}` is not a valid name (names may contain letters, digits, and `_.$:`, but may not start with a digit)")]
+    BadName(String),
+
+    #[error("Values cannot be popped into the constant segment")]
+    PopConstant,
+
+    #[error("The pointer segment only has the indices 0 and 1")]
+    PointerIndex,
+
+    #[error("The {0} segment has no index {1} in this memory layout")]
+    IndexOutOfRange(Segment, u16),
+
+    #[error("Unexpected `{0}` after the end of the command")]
+    Trailing(String),
+}
@@ -0,0 +1,59 @@
+use super::VmCommand;
+use crate::span::Spanned;
+use crate::translate::stack::Segment;
+
+/// Joins several VM files into one program. Each file has its own static variables, so the static
+/// indices of each file are moved past those used by the files before it, like the course's
+/// translator does by naming statics after their file.
+pub fn link(files: impl IntoIterator<Item = Vec<Spanned<VmCommand>>>) -> Vec<Spanned<VmCommand>> {
+    let mut program = Vec::new();
+    let mut base = 0;
+    for file in files {
+        let mut used = 0;
+        for mut command in file {
+            if let VmCommand::Push(Segment::Static, index)
+            | VmCommand::Pop(Segment::Static, index) = &mut command.item
+            {
+                used = used.max(*index + 1);
+                *index += base;
+            }
+            program.push(command);
+        }
+        base += used;
+    }
+    program
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vm::parse;
+
+    #[test]
+    fn statics() {
+        let files = [
+            "push static 0\npop static 2",
+            "push constant 1",
+            "push static 1\npop static 0",
+        ];
+        let linked = link(
+            files
+                .iter()
+                .map(|file| parse(file).map(Result::unwrap).collect()),
+        );
+        let linked = linked
+            .iter()
+            .map(|command| command.item.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            linked,
+            [
+                "push static 0",
+                "pop static 2",
+                "push constant 1",
+                "push static 4",
+                "pop static 3"
+            ]
+        );
+    }
+}
@@ -0,0 +1,516 @@
+use super::{parse, VmCommand, VmConfig, VmParseError};
+use crate::span::{Span, Spanned};
+use crate::translate::arithmetic::Arithmetic;
+use crate::translate::stack::Segment;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use thiserror::Error;
+
+/// The functions of the Jack OS and how many arguments they take, counting `this` for methods.
+/// VM emulators provide these, so programs may call them without defining them.
+pub const OS_FUNCTIONS: &[(&str, u16)] = &[
+    ("Math.init", 0),
+    ("Math.abs", 1),
+    ("Math.multiply", 2),
+    ("Math.divide", 2),
+    ("Math.min", 2),
+    ("Math.max", 2),
+    ("Math.sqrt", 1),
+    ("String.new", 1),
+    ("String.dispose", 1),
+    ("String.length", 1),
+    ("String.charAt", 2),
+    ("String.setCharAt", 3),
+    ("String.appendChar", 2),
+    ("String.eraseLastChar", 1),
+    ("String.intValue", 1),
+    ("String.setInt", 2),
+    ("String.backSpace", 0),
+    ("String.doubleQuote", 0),
+    ("String.newLine", 0),
+    ("Array.new", 1),
+    ("Array.dispose", 1),
+    ("Output.init", 0),
+    ("Output.moveCursor", 2),
+    ("Output.printChar", 1),
+    ("Output.printString", 1),
+    ("Output.printInt", 1),
+    ("Output.println", 0),
+    ("Output.backSpace", 0),
+    ("Screen.init", 0),
+    ("Screen.clearScreen", 0),
+    ("Screen.setColor", 1),
+    ("Screen.drawPixel", 2),
+    ("Screen.drawLine", 4),
+    ("Screen.drawRectangle", 4),
+    ("Screen.drawCircle", 3),
+    ("Keyboard.init", 0),
+    ("Keyboard.keyPressed", 0),
+    ("Keyboard.readChar", 0),
+    ("Keyboard.readLine", 1),
+    ("Keyboard.readInt", 1),
+    ("Memory.init", 0),
+    ("Memory.peek", 1),
+    ("Memory.poke", 2),
+    ("Memory.alloc", 1),
+    ("Memory.deAlloc", 1),
+    ("Sys.init", 0),
+    ("Sys.halt", 0),
+    ("Sys.error", 1),
+    ("Sys.wait", 1),
+];
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum Problem {
+    #[error(transparent)]
+    Parse(VmParseError),
+
+    #[error("`{0}` is not defined in any file")]
+    UndefinedFunction(String),
+
+    #[error("`{name}` takes {expected} arguments, but is given {given}")]
+    ArgumentCount {
+        name: String,
+        expected: u16,
+        given: u16,
+    },
+
+    #[error("`{name}` reads argument {read}, but is given {given} arguments")]
+    TooFewArguments { name: String, read: u16, given: u16 },
+
+    #[error("Calls to `{name}` disagree on its argument count, and this one gives {given}")]
+    InconsistentCalls { name: String, given: u16 },
+
+    #[error("The label `{0}` is not defined in this function")]
+    UndefinedLabel(String),
+
+    #[error("The label `{0}` is already defined in this function")]
+    DuplicateLabel(String),
+
+    #[error("The function `{0}` is already defined")]
+    DuplicateFunction(String),
+
+    #[error("Commands must be inside a function")]
+    OutsideFunction,
+
+    #[error("The stack holds {0} values at this return instead of 1")]
+    ReturnDepth(i32),
+
+    #[error("Paths reach this label with {0} and {1} values on the stack")]
+    InconsistentDepth(i32, i32),
+
+    #[error("This command pops from an empty stack")]
+    StackUnderflow,
+
+    #[error("The {0} segment has no index {1}")]
+    IndexOutOfRange(Segment, u16),
+}
+
+/// A problem found in a VM file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: String,
+    pub span: Span,
+    pub problem: Problem,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let Span { line, column, .. } = self.span;
+        write!(f, "{}:{line}:{column}: {}", self.file, self.problem)
+    }
+}
+
+/// A command along with where it came from
+struct Located<'a> {
+    file: &'a str,
+    function: Option<&'a str>,
+    command: &'a Spanned<VmCommand>,
+}
+
+#[derive(Default)]
+struct Linter {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter {
+    fn report(&mut self, file: &str, span: Span, problem: Problem) {
+        self.diagnostics.push(Diagnostic {
+            file: file.to_string(),
+            span,
+            problem,
+        })
+    }
+}
+
+/// How a command changes the depth of the stack
+fn stack_effect(command: &VmCommand) -> (i32, i32) {
+    match command {
+        VmCommand::Arithmetic(Arithmetic::Neg | Arithmetic::Not) => (1, 1),
+        VmCommand::Arithmetic(_) => (2, 1),
+        VmCommand::Push(..) => (0, 1),
+        VmCommand::Pop(..) | VmCommand::IfGoto(_) => (1, 0),
+        VmCommand::Call { args, .. } => (*args as i32, 1),
+        VmCommand::Return => (1, 0),
+        VmCommand::Label(_) | VmCommand::Goto(_) | VmCommand::Function { .. } => (0, 0),
+    }
+}
+
+/// Follows every path through the function starting at `start`, checking that the stack never
+/// underflows, that every path to a label leaves the same number of values on the stack, and that
+/// exactly one value is left at each `return`
+fn check_depth(linter: &mut Linter, commands: &[Located], start: usize) {
+    let end = (start + 1..commands.len())
+        .find(|&i| matches!(commands[i].command.item, VmCommand::Function { .. }))
+        .unwrap_or(commands.len());
+    let body = &commands[..end];
+    let labels = (start + 1..end)
+        .filter_map(|i| match &body[i].command.item {
+            VmCommand::Label(label) => Some((label.as_str(), i)),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut depths: HashMap<usize, i32> = HashMap::new();
+    let mut pending = vec![(start + 1, 0)];
+    while let Some((i, depth)) = pending.pop() {
+        let Some(located) = body.get(i) else {
+            continue;
+        };
+        let (file, span) = (located.file, located.command.span);
+        if let Some(&seen) = depths.get(&i) {
+            if seen != depth {
+                linter.report(file, span, Problem::InconsistentDepth(seen, depth));
+            }
+            continue;
+        }
+        depths.insert(i, depth);
+
+        let command = &located.command.item;
+        let (pops, pushes) = stack_effect(command);
+        if depth < pops {
+            linter.report(file, span, Problem::StackUnderflow);
+            continue;
+        }
+        let next = depth - pops + pushes;
+        match command {
+            VmCommand::Return if depth != 1 => {
+                linter.report(file, span, Problem::ReturnDepth(depth));
+            }
+            VmCommand::Return => (),
+            VmCommand::Goto(label) => {
+                pending.extend(labels.get(label.as_str()).map(|&i| (i, next)))
+            }
+            VmCommand::IfGoto(label) => {
+                pending.extend(labels.get(label.as_str()).map(|&i| (i, next)));
+                pending.push((i + 1, next));
+            }
+            _ => pending.push((i + 1, next)),
+        }
+    }
+}
+
+/// Checks a program made of several VM files, given as pairs of file names and their contents.
+/// Functions may be called from any file, and the functions of the Jack OS are assumed to exist.
+pub fn lint<'a>(
+    files: impl IntoIterator<Item = (&'a str, &'a str)>,
+    config: &VmConfig,
+) -> Vec<Diagnostic> {
+    let mut linter = Linter::default();
+
+    let parsed = files
+        .into_iter()
+        .map(|(file, source)| {
+            let commands = parse(source)
+                .filter_map(|command| match command {
+                    Ok(command) => Some(command),
+                    Err(e) => {
+                        linter.report(file, e.span, Problem::Parse(e.item));
+                        None
+                    }
+                })
+                .collect::<Vec<_>>();
+            (file, commands)
+        })
+        .collect::<Vec<_>>();
+
+    let mut commands = Vec::new();
+    for (file, file_commands) in &parsed {
+        let mut function = None;
+        let mut outside_reported = false;
+        for command in file_commands {
+            if let VmCommand::Function { name, .. } = &command.item {
+                function = Some(name.as_str());
+            } else if function.is_none() && !outside_reported {
+                linter.report(file, command.span, Problem::OutsideFunction);
+                outside_reported = true;
+            }
+            commands.push(Located {
+                file,
+                function,
+                command,
+            });
+        }
+    }
+
+    let mut functions = HashMap::new();
+    let mut labels = HashSet::new();
+    // the highest argument each function reads, plus one
+    let mut arguments_read: HashMap<&str, u16> = HashMap::new();
+    for (i, located) in commands.iter().enumerate() {
+        let (file, span) = (located.file, located.command.span);
+        match &located.command.item {
+            VmCommand::Function { name, .. } => {
+                if functions.insert(name.as_str(), i).is_some() {
+                    linter.report(file, span, Problem::DuplicateFunction(name.clone()));
+                }
+            }
+            VmCommand::Label(label) => {
+                if !labels.insert((located.function, label.as_str())) {
+                    linter.report(file, span, Problem::DuplicateLabel(label.clone()));
+                }
+            }
+            VmCommand::Push(segment, index) | VmCommand::Pop(segment, index) => {
+                let range = match segment {
+                    Segment::Static => &config.statics,
+                    Segment::Temp => &config.temp,
+                    _ => &(0..u16::MAX),
+                };
+                if *index >= range.len() as u16 {
+                    linter.report(file, span, Problem::IndexOutOfRange(*segment, *index));
+                }
+                if let (Segment::Argument, Some(function)) = (segment, located.function) {
+                    let read = arguments_read.entry(function).or_default();
+                    *read = (*read).max(index + 1);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let os = OS_FUNCTIONS.iter().copied().collect::<HashMap<_, _>>();
+    // the argument count of the first call to each function which gives enough arguments
+    let mut call_counts = HashMap::new();
+    for located in &commands {
+        let (file, span) = (located.file, located.command.span);
+        match &located.command.item {
+            VmCommand::Goto(label) | VmCommand::IfGoto(label)
+                if !labels.contains(&(located.function, label.as_str())) =>
+            {
+                linter.report(file, span, Problem::UndefinedLabel(label.clone()));
+            }
+            VmCommand::Call { name, args } => {
+                let name_ref = name.as_str();
+                let expected = match (functions.contains_key(name_ref), os.get(name_ref)) {
+                    (true, _) => None,
+                    (false, Some(&expected)) => Some(expected),
+                    (false, None) => {
+                        linter.report(file, span, Problem::UndefinedFunction(name.clone()));
+                        continue;
+                    }
+                };
+                let read = arguments_read.get(name_ref).copied().unwrap_or(0);
+
+                let problem = match expected {
+                    Some(expected) if expected != *args => Some(Problem::ArgumentCount {
+                        name: name.clone(),
+                        expected,
+                        given: *args,
+                    }),
+                    _ if read > *args => Some(Problem::TooFewArguments {
+                        name: name.clone(),
+                        read: read - 1,
+                        given: *args,
+                    }),
+                    Some(_) => None,
+                    None => (*call_counts.entry(name_ref).or_insert(*args) != *args).then(|| {
+                        Problem::InconsistentCalls {
+                            name: name.clone(),
+                            given: *args,
+                        }
+                    }),
+                };
+                if let Some(problem) = problem {
+                    linter.report(file, span, problem);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let mut starts = functions.into_values().collect::<Vec<_>>();
+    starts.sort_unstable();
+    for start in starts {
+        check_depth(&mut linter, &commands, start);
+    }
+
+    let order = parsed.iter().map(|(file, _)| *file).collect::<Vec<_>>();
+    let mut diagnostics = linter.diagnostics;
+    diagnostics.sort_by_key(|d| (order.iter().position(|&file| file == d.file), d.span.offset));
+    diagnostics
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn problems(files: &[(&str, &str)]) -> Vec<(String, u32, Problem)> {
+        lint(files.iter().copied(), &VmConfig::default())
+            .into_iter()
+            .map(|d| (d.file, d.span.line, d.problem))
+            .collect()
+    }
+
+    #[test]
+    fn clean_program() {
+        let main = "
+function Main.main 1
+    push constant 3
+    call Main.double 1
+    pop local 0
+    label LOOP
+    push local 0
+    if-goto LOOP
+    push local 0
+    call Math.sqrt 1
+    return
+";
+        let double = "
+function Main.double 0
+    push argument 0
+    push argument 0
+    add
+    return
+";
+        assert_eq!(problems(&[("Main.vm", main), ("Double.vm", double)]), []);
+    }
+
+    #[test]
+    fn names() {
+        let program = "
+push constant 1
+function Main.main 0
+    call Main.missing 0
+    call Math.multiply 1
+    call Main.two 1
+    call Main.two 2
+    goto NOWHERE
+label HERE
+label HERE
+function Main.two 0
+    push argument 1
+    return
+function Main.two 0
+    return
+";
+        let problems = problems(&[("Main.vm", program)]);
+        let problems = problems
+            .iter()
+            .map(|(_, line, problem)| (*line, problem.clone()));
+        let expected = [
+            (2, Problem::OutsideFunction),
+            (4, Problem::UndefinedFunction("Main.missing".to_string())),
+            (
+                5,
+                Problem::ArgumentCount {
+                    name: "Math.multiply".to_string(),
+                    expected: 2,
+                    given: 1,
+                },
+            ),
+            (
+                6,
+                Problem::TooFewArguments {
+                    name: "Main.two".to_string(),
+                    read: 1,
+                    given: 1,
+                },
+            ),
+            (7, Problem::StackUnderflow),
+            (8, Problem::UndefinedLabel("NOWHERE".to_string())),
+            (10, Problem::DuplicateLabel("HERE".to_string())),
+            (14, Problem::DuplicateFunction("Main.two".to_string())),
+            (15, Problem::StackUnderflow),
+        ];
+        assert_eq!(problems.collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn inconsistent_calls() {
+        let program = "
+function Main.main 0
+    call Main.pair 0
+    call Main.pair 1
+    call Main.pair 0
+    return
+function Main.pair 0
+    push constant 0
+    return
+";
+        let problems = problems(&[("Main.vm", program)]);
+        let problems = problems
+            .iter()
+            .map(|(_, line, problem)| (*line, problem.clone()))
+            .filter(|(_, problem)| !matches!(problem, Problem::ReturnDepth(_)));
+        let expected = [(
+            4,
+            Problem::InconsistentCalls {
+                name: "Main.pair".to_string(),
+                given: 1,
+            },
+        )];
+        assert_eq!(problems.collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn stack_depth() {
+        let program = "
+function Main.main 0
+    push constant 1
+    if-goto SKIP
+    push constant 2
+label SKIP
+    push constant 3
+    return
+function Main.pop 0
+    pop temp 0
+    push constant 0
+    return
+function Main.extra 0
+    push constant 0
+    push constant 0
+    return
+";
+        let problems = problems(&[("Main.vm", program)]);
+        let problems = problems
+            .into_iter()
+            .map(|(_, line, problem)| (line, problem))
+            .collect::<Vec<_>>();
+        assert!(
+            problems.contains(&(6, Problem::InconsistentDepth(0, 1)))
+                || problems.contains(&(6, Problem::InconsistentDepth(1, 0)))
+        );
+        assert!(problems.contains(&(10, Problem::StackUnderflow)));
+        assert!(problems.contains(&(16, Problem::ReturnDepth(2))));
+    }
+
+    #[test]
+    fn indices() {
+        let program = "function Main.main 0\npush temp 8\npop static 239\npush static 240\nreturn";
+        assert_eq!(
+            problems(&[("Main.vm", program)]),
+            [
+                (
+                    "Main.vm".to_string(),
+                    2,
+                    Problem::IndexOutOfRange(Segment::Temp, 8)
+                ),
+                (
+                    "Main.vm".to_string(),
+                    4,
+                    Problem::IndexOutOfRange(Segment::Static, 240)
+                ),
+            ]
+        );
+    }
+}
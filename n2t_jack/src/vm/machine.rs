@@ -0,0 +1,534 @@
+use super::link;
+use super::native::{self, Native, NativeOs};
+use super::{VmCommand, VmConfig, VmParseError};
+use crate::jack::os_vm;
+use crate::span::Spanned;
+use crate::translate::arithmetic::Arithmetic;
+use crate::translate::stack::{Location, Segment};
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+
+/// The size of the Hack platform's data memory, including the screen and keyboard
+pub const RAM_SIZE: usize = 0x6001;
+
+#[derive(Error, Debug, PartialEq, Eq, Clone)]
+pub enum VmRuntimeError {
+    #[error("The program has finished running")]
+    Halted,
+
+    #[error("The function {0} is not defined")]
+    UnknownFunction(String),
+
+    #[error("The label {0} is not defined in this function")]
+    UnknownLabel(String),
+
+    #[error("The address {0} is outside of memory")]
+    BadAddress(i32),
+
+    #[error("The {0} segment has no index {1}")]
+    BadIndex(Segment, u16),
+
+    #[error("Values cannot be popped into the constant segment")]
+    PopConstant,
+}
+
+/// A function call which has not returned yet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// The function being run
+    pub function: String,
+    /// The index of the command which called it, or `None` if it was called by the bootstrap code
+    pub call_site: Option<usize>,
+    /// The value of ARG for this call
+    pub arg: i16,
+    /// The value of LCL for this call
+    pub lcl: i16,
+}
+
+/// Executes VM commands directly, with the same memory layout as translated code. The stack and
+/// all segments live in RAM, so scripts and debuggers can inspect the machine the same way they
+/// would inspect the CPU.
+pub struct VmMachine {
+    commands: Vec<Spanned<VmCommand>>,
+    /// The function containing each command, used to resolve labels
+    enclosing: Vec<Option<String>>,
+    functions: HashMap<String, usize>,
+    labels: HashMap<(Option<String>, String), usize>,
+    ram: Vec<i16>,
+    pc: usize,
+    call_stack: Vec<Frame>,
+    config: VmConfig,
+    /// Runs calls to OS functions which the program does not define, if set
+    native_os: Option<NativeOs>,
+}
+
+impl VmMachine {
+    pub fn new(commands: Vec<Spanned<VmCommand>>) -> Self {
+        Self::with_config(commands, VmConfig::default())
+    }
+
+    /// Creates a machine which lays out memory according to `config`
+    pub fn with_config(commands: Vec<Spanned<VmCommand>>, config: VmConfig) -> Self {
+        let mut machine = Self {
+            commands: Vec::new(),
+            enclosing: Vec::with_capacity(commands.len()),
+            functions: HashMap::new(),
+            labels: HashMap::new(),
+            ram: vec![0; RAM_SIZE],
+            pc: 0,
+            call_stack: Vec::new(),
+            config,
+            native_os: None,
+        };
+        machine.extend(commands);
+        machine
+    }
+
+    /// Adds commands to the end of the program
+    fn extend(&mut self, commands: Vec<Spanned<VmCommand>>) {
+        let mut current = self.enclosing.last().cloned().flatten();
+        for command in commands {
+            let i = self.commands.len();
+            match &command.item {
+                VmCommand::Function { name, .. } => {
+                    self.functions.insert(name.clone(), i);
+                    current = Some(name.clone());
+                }
+                VmCommand::Label(label) => {
+                    self.labels.insert((current.clone(), label.clone()), i);
+                }
+                _ => (),
+            }
+            self.enclosing.push(current.clone());
+            self.commands.push(command);
+        }
+    }
+
+    pub fn from_source(program: &str) -> Result<Self, Spanned<VmParseError>> {
+        super::parse(program)
+            .collect::<Result<Vec<_>, _>>()
+            .map(Self::new)
+    }
+
+    /// Does what the translator's bootstrap code would: set SP to the base of the stack and call
+    /// `Sys.init`
+    pub fn bootstrap(&mut self) -> Result<(), VmRuntimeError> {
+        let sp = self.sp();
+        self.ram[sp] = self.config.stack_base as i16;
+        self.call("Sys.init", 0)
+    }
+
+    /// Makes calls to Jack OS functions which the program does not define run natively, like the
+    /// course's VM emulator runs its built-in OS. This lets a program run without the OS's VM code,
+    /// or with only some of its classes. Classes which depend on a class the program defines, such
+    /// as `String` on `Memory`, run as the bundled Jack OS instead, so that they use the program's.
+    /// This should be done before the program starts running.
+    pub fn set_native_os(&mut self, enabled: bool) {
+        self.native_os = enabled.then(NativeOs::new);
+        if !enabled {
+            return;
+        }
+
+        let defined = self
+            .functions
+            .keys()
+            .filter_map(|name| Some(name.split_once('.')?.0))
+            .collect::<HashSet<_>>();
+        let jack_classes = native::jack_classes(&defined);
+        let jack = os_vm()
+            .into_iter()
+            .filter(|(name, _)| jack_classes.contains(name))
+            .map(|(_, commands)| commands);
+        // the OS's statics go after the program's
+        let len = self.commands.len();
+        let linked = link([self.commands.clone()].into_iter().chain(jack));
+        self.extend(linked.into_iter().skip(len).collect());
+
+        // Jack programs which leave out Sys.init get one which starts the OS and runs Main.main
+        let defined = |name| self.functions.contains_key(name);
+        if !defined("Sys.init") && defined("Main.main") {
+            self.extend(native::sys_init());
+        }
+    }
+
+    pub fn config(&self) -> &VmConfig {
+        &self.config
+    }
+
+    pub fn ram(&self) -> &[i16] {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [i16] {
+        &mut self.ram
+    }
+
+    pub fn commands(&self) -> &[Spanned<VmCommand>] {
+        &self.commands
+    }
+
+    /// The index of the next command to run
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+
+    pub fn current_command(&self) -> Option<&Spanned<VmCommand>> {
+        self.commands.get(self.pc)
+    }
+
+    /// The calls which are currently running, innermost last
+    pub fn call_stack(&self) -> &[Frame] {
+        &self.call_stack
+    }
+
+    /// The contents of the working stack, from the bottom of the current frame to the top
+    pub fn stack(&self) -> &[i16] {
+        let bottom = match self.call_stack.last() {
+            Some(frame) => frame.lcl as usize,
+            None => self.config.stack_base as usize,
+        };
+        let top = self.ram[self.sp()] as usize;
+        self.ram.get(bottom..top).unwrap_or(&[])
+    }
+
+    /// The address of the stack pointer
+    fn sp(&self) -> usize {
+        self.config.pointers.sp as usize
+    }
+
+    fn address(&self, addr: i32) -> Result<usize, VmRuntimeError> {
+        usize::try_from(addr)
+            .ok()
+            .filter(|addr| *addr < RAM_SIZE)
+            .ok_or(VmRuntimeError::BadAddress(addr))
+    }
+
+    fn read(&self, addr: i32) -> Result<i16, VmRuntimeError> {
+        Ok(self.ram[self.address(addr)?])
+    }
+
+    fn write(&mut self, addr: i32, value: i16) -> Result<(), VmRuntimeError> {
+        let addr = self.address(addr)?;
+        self.ram[addr] = value;
+        Ok(())
+    }
+
+    fn push(&mut self, value: i16) -> Result<(), VmRuntimeError> {
+        let pointer = self.sp();
+        let sp = self.ram[pointer];
+        self.write(sp as i32, value)?;
+        self.ram[pointer] = sp.wrapping_add(1);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<i16, VmRuntimeError> {
+        let pointer = self.sp();
+        let sp = self.ram[pointer].wrapping_sub(1);
+        self.ram[pointer] = sp;
+        self.read(sp as i32)
+    }
+
+    /// Finds the address of an index of a segment, or its value if it is a constant
+    fn locate(&self, segment: Segment, index: u16) -> Result<Result<i32, i16>, VmRuntimeError> {
+        match segment.location(index, &self.config) {
+            Some(Location::Indirect { table, offset }) => {
+                Ok(Ok(self.ram[table as usize] as i32 + offset as i32))
+            }
+            Some(Location::Direct(addr)) => Ok(Ok(addr as i32)),
+            Some(Location::Constant(value)) => Ok(Err(value as i16)),
+            None => Err(VmRuntimeError::BadIndex(segment, index)),
+        }
+    }
+
+    fn call(&mut self, function: &str, args: u16) -> Result<(), VmRuntimeError> {
+        let Some(&target) = self.functions.get(function) else {
+            return self.call_native(function, args);
+        };
+
+        let pointers = self.config.pointers;
+        let (lcl, arg) = (pointers.local as usize, pointers.argument as usize);
+        self.push(self.pc as i16)?; // the return address is the command after the call
+        for pointer in [
+            pointers.local,
+            pointers.argument,
+            pointers.this,
+            pointers.that,
+        ] {
+            self.push(self.ram[pointer as usize])?;
+        }
+        let sp = self.ram[self.sp()];
+        self.ram[arg] = sp.wrapping_sub(args as i16 + 5);
+        self.ram[lcl] = sp;
+
+        self.call_stack.push(Frame {
+            function: function.to_string(),
+            call_site: self.pc.checked_sub(1),
+            arg: self.ram[arg],
+            lcl: sp,
+        });
+        self.pc = target;
+        Ok(())
+    }
+
+    /// Runs an OS function natively, as if it had been called and had returned
+    fn call_native(&mut self, function: &str, args: u16) -> Result<(), VmRuntimeError> {
+        let unknown = || VmRuntimeError::UnknownFunction(function.to_string());
+        let sp = self.ram[self.sp()];
+        let base = sp.wrapping_sub(args as i16);
+        let values = (0..args as i16)
+            .map(|i| self.read(base.wrapping_add(i) as i32))
+            .collect::<Result<Vec<_>, _>>()?;
+        let os = self.native_os.as_mut().ok_or_else(unknown)?;
+        match os
+            .call(function, &values, &mut self.ram)
+            .ok_or_else(unknown)??
+        {
+            Native::Return(value) => {
+                let sp = self.sp();
+                self.ram[sp] = base;
+                self.push(value)
+            }
+            // the call runs again until the keyboard gives it what it is waiting for
+            Native::Pending => {
+                self.pc -= 1;
+                Ok(())
+            }
+            Native::Halt => {
+                self.pc = self.commands.len();
+                Ok(())
+            }
+        }
+    }
+
+    fn return_(&mut self) -> Result<(), VmRuntimeError> {
+        let pointers = self.config.pointers;
+        let frame = self.ram[pointers.local as usize] as i32;
+        let return_address = self.read(frame - 5)?;
+        let value = self.pop()?;
+        let arg = self.ram[pointers.argument as usize];
+        self.write(arg as i32, value)?;
+        let sp = self.sp();
+        self.ram[sp] = arg.wrapping_add(1);
+        let saved = [
+            pointers.local,
+            pointers.argument,
+            pointers.this,
+            pointers.that,
+        ];
+        for (i, pointer) in saved.into_iter().enumerate() {
+            self.ram[pointer as usize] = self.read(frame - 4 + i as i32)?;
+        }
+
+        self.call_stack.pop();
+        self.pc = return_address as u16 as usize;
+        Ok(())
+    }
+
+    fn jump(&mut self, label: &str) -> Result<(), VmRuntimeError> {
+        let function = self.enclosing[self.pc - 1].clone();
+        self.pc = *self
+            .labels
+            .get(&(function, label.to_string()))
+            .ok_or_else(|| VmRuntimeError::UnknownLabel(label.to_string()))?;
+        Ok(())
+    }
+
+    fn arithmetic(&mut self, op: Arithmetic) -> Result<(), VmRuntimeError> {
+        let bool = |b: bool| if b { -1 } else { 0 };
+        let result = match op {
+            Arithmetic::Neg => self.pop()?.wrapping_neg(),
+            Arithmetic::Not => !self.pop()?,
+            op => {
+                let y = self.pop()?;
+                let x = self.pop()?;
+                match op {
+                    Arithmetic::Add => x.wrapping_add(y),
+                    Arithmetic::Sub => x.wrapping_sub(y),
+                    Arithmetic::Eq => bool(x == y),
+                    Arithmetic::Gt => bool(x > y),
+                    Arithmetic::Lt => bool(x < y),
+                    Arithmetic::And => x & y,
+                    Arithmetic::Or => x | y,
+                    Arithmetic::Neg | Arithmetic::Not => unreachable!(),
+                }
+            }
+        };
+        self.push(result)
+    }
+
+    /// Runs a single command
+    pub fn step(&mut self) -> Result<(), VmRuntimeError> {
+        let command = self
+            .commands
+            .get(self.pc)
+            .ok_or(VmRuntimeError::Halted)?
+            .item
+            .clone();
+        self.pc += 1;
+
+        match command {
+            VmCommand::Arithmetic(op) => self.arithmetic(op),
+            VmCommand::Push(segment, index) => {
+                let value = match self.locate(segment, index)? {
+                    Ok(addr) => self.read(addr)?,
+                    Err(constant) => constant,
+                };
+                self.push(value)
+            }
+            VmCommand::Pop(segment, index) => match self.locate(segment, index)? {
+                Ok(addr) => {
+                    let value = self.pop()?;
+                    self.write(addr, value)
+                }
+                Err(_) => Err(VmRuntimeError::PopConstant),
+            },
+            VmCommand::Label(_) => Ok(()),
+            VmCommand::Goto(label) => self.jump(&label),
+            VmCommand::IfGoto(label) => match self.pop()? {
+                0 => Ok(()),
+                _ => self.jump(&label),
+            },
+            VmCommand::Function { locals, .. } => (0..locals).try_for_each(|_| self.push(0)),
+            VmCommand::Call { name, args } => self.call(&name, args),
+            VmCommand::Return => self.return_(),
+        }
+    }
+
+    /// Runs until the program halts, an error occurs, or `max_steps` commands have been run.
+    /// Returns the number of commands which were run.
+    pub fn run(&mut self, max_steps: usize) -> Result<usize, VmRuntimeError> {
+        for steps in 0..max_steps {
+            match self.step() {
+                Ok(()) => (),
+                Err(VmRuntimeError::Halted) => return Ok(steps),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(max_steps)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn arithmetic() {
+        let mut vm = VmMachine::from_source(
+            "push constant 7\npush constant 8\nlt\npush constant 32767\npush constant 1\nadd\nneg\nnot",
+        )
+        .unwrap();
+        vm.ram_mut()[0] = 256;
+
+        assert_eq!(vm.run(100), Ok(8));
+        assert_eq!(vm.stack(), [-1, 32767]);
+    }
+
+    #[test]
+    fn segments() {
+        let mut vm = VmMachine::from_source(
+            "
+            push constant 3000
+            pop pointer 0
+            push constant 10
+            pop this 2
+            push constant 11
+            pop temp 6
+            push constant 12
+            pop static 3
+            push this 2
+            push temp 6
+            push static 3
+            ",
+        )
+        .unwrap();
+        vm.ram_mut()[0] = 256;
+        vm.run(100).unwrap();
+
+        assert_eq!(vm.ram()[3], 3000);
+        assert_eq!(vm.ram()[3002], 10);
+        assert_eq!(vm.ram()[11], 11);
+        assert_eq!(vm.ram()[19], 12);
+        assert_eq!(vm.stack(), [10, 11, 12]);
+    }
+
+    #[test]
+    fn functions() {
+        let mut vm = VmMachine::from_source(
+            "
+            function Sys.init 0
+                push constant 5
+                call Main.fact 1
+            label HALT
+                goto HALT
+
+            function Main.fact 0
+                push argument 0
+                push constant 1
+                gt
+                if-goto RECURSE
+                push constant 1
+                return
+            label RECURSE
+                push argument 0
+                push argument 0
+                push constant 1
+                sub
+                call Main.fact 1
+                call Main.mul 2
+                return
+
+            function Main.mul 1
+            label LOOP
+                push argument 1
+                push constant 0
+                eq
+                if-goto END
+                push local 0
+                push argument 0
+                add
+                pop local 0
+                push argument 1
+                push constant 1
+                sub
+                pop argument 1
+                goto LOOP
+            label END
+                push local 0
+                return
+            ",
+        )
+        .unwrap();
+        vm.bootstrap().unwrap();
+
+        // step until the call stack reaches its deepest point
+        let mut deepest = 0;
+        for _ in 0..2000 {
+            vm.step().unwrap();
+            deepest = deepest.max(vm.call_stack().len());
+        }
+
+        assert_eq!(deepest, 6);
+        assert_eq!(vm.call_stack().len(), 1);
+        assert_eq!(vm.call_stack()[0].function, "Sys.init");
+        assert_eq!(vm.stack(), [120]);
+    }
+
+    #[test]
+    fn errors() {
+        let mut vm = VmMachine::from_source("call Nothing 0").unwrap();
+        assert_eq!(
+            vm.step(),
+            Err(VmRuntimeError::UnknownFunction("Nothing".to_string()))
+        );
+
+        let mut vm =
+            VmMachine::from_source("push constant 0\nnot\npop pointer 0\npush this 0").unwrap();
+        vm.ram_mut()[0] = 256;
+        assert_eq!(vm.run(10), Err(VmRuntimeError::BadAddress(-1)));
+    }
+}
@@ -0,0 +1,18 @@
+mod command;
+mod config;
+mod error;
+mod link;
+mod lint;
+mod machine;
+mod native;
+mod optimize;
+mod parse;
+
+pub use command::VmCommand;
+pub use config::{Pointers, VmConfig};
+pub use error::VmParseError;
+pub use link::link;
+pub use lint::{lint, Diagnostic, Problem, OS_FUNCTIONS};
+pub use machine::{Frame, VmMachine, VmRuntimeError, RAM_SIZE};
+pub use optimize::{optimize, Op, Optimizations};
+pub use parse::{parse, parse_line};
@@ -0,0 +1,757 @@
+use super::{VmCommand, VmRuntimeError};
+use crate::jack::ast::{Expression, Statement};
+use crate::jack::os_classes;
+use crate::span::Spanned;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+/// Where the heap starts, which the bundled `Memory.jack` also hardcodes
+const HEAP_BASE: i16 = 2048;
+const SCREEN: usize = 16384;
+const KEYBOARD: usize = 24576;
+const NEW_LINE: i16 = 128;
+const BACKSPACE: i16 = 129;
+/// The longest line `Keyboard.readLine` reads
+const MAX_LINE: usize = 64;
+
+/// The OS classes whose native functions only work with the native versions of some other classes,
+/// because they allocate memory, build strings or print
+const DEPENDENCIES: [(&str, &[&str]); 5] = [
+    ("Array", &["Memory"]),
+    ("String", &["Memory"]),
+    ("Output", &["String"]),
+    ("Keyboard", &["Memory", "String", "Output"]),
+    ("Sys", &["Memory", "String", "Output"]),
+];
+
+/// The OS classes which have to run as the bundled Jack OS's VM code rather than natively, because
+/// the program defines some of the classes they depend on
+pub fn jack_classes(defined: &HashSet<&str>) -> Vec<&'static str> {
+    let mut jack = Vec::new();
+    loop {
+        let next = DEPENDENCIES.iter().find(|(class, dependencies)| {
+            !defined.contains(class)
+                && !jack.contains(class)
+                && dependencies
+                    .iter()
+                    .any(|dependency| defined.contains(dependency) || jack.contains(dependency))
+        });
+        match next {
+            Some((class, _)) => jack.push(*class),
+            None => return jack,
+        }
+    }
+}
+
+/// The `Sys.init` of programs which use the native OS without defining one. Like the Jack OS's, it
+/// sets up each class of the OS, runs `Main.main` and halts.
+const SYS_INIT: &str = "function Sys.init 0
+call Memory.init 0
+pop temp 0
+call Math.init 0
+pop temp 0
+call Screen.init 0
+pop temp 0
+call Output.init 0
+pop temp 0
+call Keyboard.init 0
+pop temp 0
+call Main.main 0
+pop temp 0
+call Sys.halt 0
+pop temp 0
+push constant 0
+return";
+
+pub fn sys_init() -> Vec<Spanned<VmCommand>> {
+    super::parse(SYS_INIT)
+        .collect::<Result<_, _>>()
+        .expect("Sys.init is valid VM code")
+}
+
+/// What the machine should do after a native OS function has run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Native {
+    /// Return a value to the caller
+    Return(i16),
+    /// Run the call again on the next step, because it is waiting for the keyboard
+    Pending,
+    Halt,
+}
+
+/// Why a native function stopped early
+enum Stop {
+    Runtime(VmRuntimeError),
+    /// An error which the Jack OS reports with `Sys.error`
+    Os(i16),
+}
+
+impl From<VmRuntimeError> for Stop {
+    fn from(e: VmRuntimeError) -> Self {
+        Stop::Runtime(e)
+    }
+}
+
+type NativeResult<T> = Result<T, Stop>;
+
+/// The bitmaps of the characters which `Output` can print, taken from the bundled Jack OS
+fn font() -> &'static HashMap<i16, Vec<i16>> {
+    static FONT: OnceLock<HashMap<i16, Vec<i16>>> = OnceLock::new();
+    FONT.get_or_init(|| {
+        let classes = os_classes();
+        let output = classes.iter().find(|class| class.name.item == "Output");
+        let init_map = output
+            .into_iter()
+            .flat_map(|class| &class.subroutines)
+            .find(|subroutine| subroutine.item.name.item == "initMap")
+            .expect("the OS's Output class has an initMap function");
+        let integer = |expression: &Expression| match expression {
+            Expression::Integer(value) => *value as i16,
+            _ => panic!("the font is made of integers"),
+        };
+        init_map
+            .item
+            .statements
+            .iter()
+            .filter_map(|statement| match &statement.item {
+                Statement::Do(call) if call.name.item == "create" => {
+                    let mut values = call.args.iter().map(|arg| integer(&arg.item));
+                    Some((values.next()?, values.collect()))
+                }
+                _ => None,
+            })
+            .collect()
+    })
+}
+
+fn read(ram: &[i16], addr: i16) -> NativeResult<i16> {
+    ram.get(addr as u16 as usize)
+        .copied()
+        .ok_or(Stop::Runtime(VmRuntimeError::BadAddress(addr as i32)))
+}
+
+fn write(ram: &mut [i16], addr: i16, value: i16) -> NativeResult<()> {
+    let cell = ram
+        .get_mut(addr as u16 as usize)
+        .ok_or(Stop::Runtime(VmRuntimeError::BadAddress(addr as i32)))?;
+    *cell = value;
+    Ok(())
+}
+
+/// The Jack OS, implemented in Rust so that programs run without the OS's VM code. The heap and
+/// strings are laid out in RAM as the bundled Jack OS lays them out, and the screen is drawn the
+/// same way, so programs can't tell the difference. Only the OS's own state, such as the cursor,
+/// is kept outside of RAM.
+#[derive(Debug, Clone)]
+pub struct NativeOs {
+    /// The address of the first free block of the heap, once the heap has been set up
+    free_list: Option<i16>,
+    row: i16,
+    column: i16,
+    color: bool,
+    /// The key being read by `Keyboard.readChar`, once it has been pressed
+    key: Option<Option<i16>>,
+    /// The characters read so far by `Keyboard.readLine`
+    line: Option<Vec<i16>>,
+}
+
+impl NativeOs {
+    pub fn new() -> Self {
+        Self {
+            free_list: None,
+            row: 0,
+            column: 0,
+            color: true,
+            key: None,
+            line: None,
+        }
+    }
+
+    /// Runs an OS function, or returns `None` if there is no OS function called `name`
+    pub fn call(
+        &mut self,
+        name: &str,
+        args: &[i16],
+        ram: &mut [i16],
+    ) -> Option<Result<Native, VmRuntimeError>> {
+        let arg = |i: usize| args.get(i).copied().unwrap_or_default();
+        let result = match name {
+            "Math.init" | "Keyboard.init" => Ok(Native::Return(0)),
+            "Math.abs" => Ok(Native::Return(arg(0).wrapping_abs())),
+            "Math.multiply" => Ok(Native::Return(arg(0).wrapping_mul(arg(1)))),
+            "Math.divide" => match arg(1) {
+                0 => Err(Stop::Os(3)),
+                y => Ok(Native::Return(arg(0).wrapping_div(y))),
+            },
+            "Math.min" => Ok(Native::Return(arg(0).min(arg(1)))),
+            "Math.max" => Ok(Native::Return(arg(0).max(arg(1)))),
+            "Math.sqrt" => match arg(0) {
+                x if x < 0 => Err(Stop::Os(4)),
+                x => Ok(Native::Return((x as f64).sqrt() as i16)),
+            },
+
+            "Memory.init" => {
+                self.free_list = None;
+                self.heap(ram).map(|_| Native::Return(0))
+            }
+            "Memory.peek" => read(ram, arg(0)).map(Native::Return),
+            "Memory.poke" => write(ram, arg(0), arg(1)).map(|_| Native::Return(0)),
+            "Memory.alloc" => self.alloc(ram, arg(0)).map(Native::Return),
+            "Memory.deAlloc" => self.de_alloc(ram, arg(0)).map(|_| Native::Return(0)),
+
+            "Array.new" => match arg(0) {
+                size if size < 1 => Err(Stop::Os(2)),
+                size => self.alloc(ram, size).map(Native::Return),
+            },
+            "Array.dispose" => self.de_alloc(ram, arg(0)).map(|_| Native::Return(0)),
+
+            "String.new" => self.new_string(ram, arg(0)).map(Native::Return),
+            "String.dispose" => self.dispose_string(ram, arg(0)).map(|_| Native::Return(0)),
+            "String.length" => read(ram, arg(0).wrapping_add(1)).map(Native::Return),
+            "String.charAt" => string_index(ram, arg(0), arg(1), 15)
+                .and_then(|addr| read(ram, addr))
+                .map(Native::Return),
+            "String.setCharAt" => string_index(ram, arg(0), arg(1), 16)
+                .and_then(|addr| write(ram, addr, arg(2)))
+                .map(|_| Native::Return(0)),
+            "String.appendChar" => {
+                append_char(ram, arg(0), arg(1), 17).map(|_| Native::Return(arg(0)))
+            }
+            "String.eraseLastChar" => erase_last_char(ram, arg(0)).map(|_| Native::Return(0)),
+            "String.intValue" => {
+                string_chars(ram, arg(0)).map(|chars| Native::Return(int_value(&chars)))
+            }
+            "String.setInt" => set_int(ram, arg(0), arg(1)).map(|_| Native::Return(0)),
+            "String.backSpace" => Ok(Native::Return(BACKSPACE)),
+            "String.doubleQuote" => Ok(Native::Return('"' as i16)),
+            "String.newLine" => Ok(Native::Return(NEW_LINE)),
+
+            "Output.init" => {
+                (self.row, self.column) = (0, 0);
+                Ok(Native::Return(0))
+            }
+            "Output.moveCursor" => self
+                .move_cursor(ram, arg(0), arg(1))
+                .map(|_| Native::Return(0)),
+            "Output.printChar" => self.print_char(ram, arg(0)).map(|_| Native::Return(0)),
+            "Output.printString" => string_chars(ram, arg(0))
+                .and_then(|chars| self.print(ram, &chars))
+                .map(|_| Native::Return(0)),
+            "Output.printInt" => self
+                .print(ram, &int_chars(arg(0)))
+                .map(|_| Native::Return(0)),
+            "Output.println" => {
+                self.println();
+                Ok(Native::Return(0))
+            }
+            "Output.backSpace" => self.back_space(ram).map(|_| Native::Return(0)),
+
+            "Screen.init" => {
+                self.color = true;
+                Ok(Native::Return(0))
+            }
+            "Screen.clearScreen" => {
+                ram[SCREEN..KEYBOARD].fill(0);
+                Ok(Native::Return(0))
+            }
+            "Screen.setColor" => {
+                self.color = arg(0) != 0;
+                Ok(Native::Return(0))
+            }
+            "Screen.drawPixel" => match on_screen(arg(0), arg(1)) {
+                true => self
+                    .draw_pixel(ram, arg(0), arg(1))
+                    .map(|_| Native::Return(0)),
+                false => Err(Stop::Os(7)),
+            },
+            "Screen.drawLine" => self
+                .draw_line(ram, [arg(0), arg(1), arg(2), arg(3)], 8)
+                .map(|_| Native::Return(0)),
+            "Screen.drawRectangle" => self
+                .draw_rectangle(ram, [arg(0), arg(1), arg(2), arg(3)])
+                .map(|_| Native::Return(0)),
+            "Screen.drawCircle" => self
+                .draw_circle(ram, arg(0), arg(1), arg(2))
+                .map(|_| Native::Return(0)),
+
+            "Keyboard.keyPressed" => Ok(Native::Return(ram[KEYBOARD])),
+            "Keyboard.readChar" => self.read_char(ram).map(|c| match c {
+                Some(c) => Native::Return(c),
+                None => Native::Pending,
+            }),
+            "Keyboard.readLine" => self.read_line(ram, arg(0)).and_then(|line| match line {
+                Some(line) => self.string_of(ram, &line).map(Native::Return),
+                None => Ok(Native::Pending),
+            }),
+            "Keyboard.readInt" => self.read_line(ram, arg(0)).map(|line| match line {
+                Some(line) => Native::Return(int_value(&line)),
+                None => Native::Pending,
+            }),
+
+            "Sys.halt" => Ok(Native::Halt),
+            "Sys.error" => Err(Stop::Os(arg(0))),
+            "Sys.wait" => match arg(0) {
+                duration if duration < 0 => Err(Stop::Os(1)),
+                _ => Ok(Native::Return(0)),
+            },
+            _ => return None,
+        };
+
+        Some(match result {
+            Ok(native) => Ok(native),
+            Err(Stop::Runtime(e)) => Err(e),
+            // like the Jack OS, print the error and halt
+            Err(Stop::Os(code)) => {
+                let mut message = "ERR".chars().map(|c| c as i16).collect::<Vec<_>>();
+                message.extend(int_chars(code));
+                match self.print(ram, &message) {
+                    Ok(()) | Err(Stop::Os(_)) => Ok(Native::Halt),
+                    Err(Stop::Runtime(e)) => Err(e),
+                }
+            }
+        })
+    }
+
+    /// The address of the first free block of the heap, setting the heap up if it is not already
+    fn heap(&mut self, ram: &mut [i16]) -> NativeResult<i16> {
+        if let Some(free_list) = self.free_list {
+            return Ok(free_list);
+        }
+        let start = HEAP_BASE;
+        write(ram, start, SCREEN as i16 - start)?;
+        write(ram, start.wrapping_add(1), 0)?;
+        self.free_list = Some(start);
+        Ok(start)
+    }
+
+    /// Allocates from the first free block which is big enough, as `Memory.alloc` does
+    fn alloc(&mut self, ram: &mut [i16], size: i16) -> NativeResult<i16> {
+        if size < 1 {
+            return Err(Stop::Os(5));
+        }
+        let needed = size.wrapping_add(1);
+        let mut previous: i16 = 0;
+        let mut block = self.heap(ram)?;
+        while block != 0 {
+            let available = read(ram, block)?;
+            if available >= needed {
+                let rest = available - needed;
+                if rest < 2 {
+                    let next = read(ram, block.wrapping_add(1))?;
+                    match previous {
+                        0 => self.free_list = Some(next),
+                        _ => write(ram, previous.wrapping_add(1), next)?,
+                    }
+                } else {
+                    write(ram, block, rest)?;
+                    block = block.wrapping_add(rest);
+                    write(ram, block, needed)?;
+                }
+                return Ok(block.wrapping_add(1));
+            }
+            previous = block;
+            block = read(ram, block.wrapping_add(1))?;
+        }
+        Err(Stop::Os(6))
+    }
+
+    /// Frees a block, merging it with its neighbours, as `Memory.deAlloc` does
+    fn de_alloc(&mut self, ram: &mut [i16], object: i16) -> NativeResult<()> {
+        let block = object.wrapping_sub(1);
+        let mut previous: i16 = 0;
+        let mut next = self.heap(ram)?;
+        while next != 0 && next < block {
+            previous = next;
+            next = read(ram, next.wrapping_add(1))?;
+        }
+
+        let size = read(ram, block)?;
+        if next != 0 && block.wrapping_add(size) == next {
+            write(ram, block, size.wrapping_add(read(ram, next)?))?;
+            write(ram, block.wrapping_add(1), read(ram, next.wrapping_add(1))?)?;
+        } else {
+            write(ram, block.wrapping_add(1), next)?;
+        }
+
+        if previous == 0 {
+            self.free_list = Some(block);
+            return Ok(());
+        }
+        let previous_size = read(ram, previous)?;
+        if previous.wrapping_add(previous_size) == block {
+            write(ram, previous, previous_size.wrapping_add(read(ram, block)?))?;
+            write(
+                ram,
+                previous.wrapping_add(1),
+                read(ram, block.wrapping_add(1))?,
+            )?;
+        } else {
+            write(ram, previous.wrapping_add(1), block)?;
+        }
+        Ok(())
+    }
+
+    /// Creates a string object, whose fields are its characters, its length and its capacity
+    fn new_string(&mut self, ram: &mut [i16], capacity: i16) -> NativeResult<i16> {
+        if capacity < 0 {
+            return Err(Stop::Os(14));
+        }
+        let string = self.alloc(ram, 3)?;
+        // empty strings have nowhere to keep characters
+        if capacity > 0 {
+            let chars = self.alloc(ram, capacity)?;
+            write(ram, string, chars)?;
+        }
+        write(ram, string.wrapping_add(1), 0)?;
+        write(ram, string.wrapping_add(2), capacity)?;
+        Ok(string)
+    }
+
+    fn dispose_string(&mut self, ram: &mut [i16], string: i16) -> NativeResult<()> {
+        if read(ram, string.wrapping_add(2))? > 0 {
+            self.de_alloc(ram, read(ram, string)?)?;
+        }
+        self.de_alloc(ram, string)
+    }
+
+    /// Creates a string holding some characters
+    fn string_of(&mut self, ram: &mut [i16], chars: &[i16]) -> NativeResult<i16> {
+        let string = self.new_string(ram, MAX_LINE as i16)?;
+        for c in chars {
+            append_char(ram, string, *c, 17)?;
+        }
+        Ok(string)
+    }
+
+    fn draw_char(&self, ram: &mut [i16], c: i16) -> NativeResult<()> {
+        let font = font();
+        let map = font
+            .get(&c)
+            .filter(|_| (32..=126).contains(&c))
+            .unwrap_or(&font[&0]);
+        let mut address = SCREEN as i16 + self.row * 352 + self.column / 2;
+        for row in map {
+            let word = read(ram, address)?;
+            let word = match self.column & 1 {
+                1 => (word & 255) | (row << 8),
+                _ => (word & !255) | row,
+            };
+            write(ram, address, word)?;
+            address += 32;
+        }
+        Ok(())
+    }
+
+    fn move_cursor(&mut self, ram: &mut [i16], row: i16, column: i16) -> NativeResult<()> {
+        if !(0..23).contains(&row) || !(0..64).contains(&column) {
+            return Err(Stop::Os(20));
+        }
+        (self.row, self.column) = (row, column);
+        self.draw_char(ram, ' ' as i16)
+    }
+
+    fn print_char(&mut self, ram: &mut [i16], c: i16) -> NativeResult<()> {
+        match c {
+            NEW_LINE => self.println(),
+            BACKSPACE => self.back_space(ram)?,
+            _ => {
+                self.draw_char(ram, c)?;
+                self.column += 1;
+                if self.column == 64 {
+                    self.println();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn print(&mut self, ram: &mut [i16], chars: &[i16]) -> NativeResult<()> {
+        chars.iter().try_for_each(|c| self.print_char(ram, *c))
+    }
+
+    fn println(&mut self) {
+        self.column = 0;
+        self.row = (self.row + 1) % 23;
+    }
+
+    fn back_space(&mut self, ram: &mut [i16]) -> NativeResult<()> {
+        if self.column > 0 {
+            self.column -= 1;
+        } else if self.row > 0 {
+            (self.row, self.column) = (self.row - 1, 63);
+        }
+        self.draw_char(ram, ' ' as i16)
+    }
+
+    fn draw_pixel(&self, ram: &mut [i16], x: i16, y: i16) -> NativeResult<()> {
+        let address = SCREEN as i16 + y * 32 + x / 16;
+        let mask = 1 << (x & 15);
+        let word = read(ram, address)?;
+        let word = match self.color {
+            true => word | mask,
+            false => word & !mask,
+        };
+        write(ram, address, word)
+    }
+
+    /// Draws a line with Bresenham's algorithm, failing with `error` if it leaves the screen
+    fn draw_line(
+        &self,
+        ram: &mut [i16],
+        [x1, y1, x2, y2]: [i16; 4],
+        error: i16,
+    ) -> NativeResult<()> {
+        if !(on_screen(x1, y1) && on_screen(x2, y2)) {
+            return Err(Stop::Os(error));
+        }
+        let (dx, dy) = ((x2 - x1).abs(), (y2 - y1).abs());
+        let sx = if x2 < x1 { -1 } else { 1 };
+        let sy = if y2 < y1 { -1 } else { 1 };
+        let (mut x, mut y, mut diff) = (x1, y1, dx - dy);
+        loop {
+            self.draw_pixel(ram, x, y)?;
+            if x == x2 && y == y2 {
+                return Ok(());
+            }
+            let doubled = diff * 2;
+            if doubled > -dy {
+                diff -= dy;
+                x += sx;
+            }
+            if doubled < dx {
+                diff += dx;
+                y += sy;
+            }
+        }
+    }
+
+    fn draw_rectangle(&self, ram: &mut [i16], [x1, y1, x2, y2]: [i16; 4]) -> NativeResult<()> {
+        if x1 > x2 || y1 > y2 || !(on_screen(x1, y1) && on_screen(x2, y2)) {
+            return Err(Stop::Os(9));
+        }
+        (y1..=y2).try_for_each(|y| self.draw_line(ram, [x1, y, x2, y], 8))
+    }
+
+    fn draw_circle(&self, ram: &mut [i16], x: i16, y: i16, r: i16) -> NativeResult<()> {
+        if !on_screen(x, y) {
+            return Err(Stop::Os(12));
+        }
+        if !(0..=181).contains(&r) {
+            return Err(Stop::Os(13));
+        }
+        (-r..=r).try_for_each(|dy| {
+            let half = ((r * r - dy * dy) as f64).sqrt() as i16;
+            self.draw_line(ram, [x - half, y + dy, x + half, y + dy], 8)
+        })
+    }
+
+    /// Reads a key once it has been pressed and released, showing a cursor until then. Returns
+    /// `None` while still waiting.
+    fn read_char(&mut self, ram: &mut [i16]) -> NativeResult<Option<i16>> {
+        let key = ram[KEYBOARD];
+        match self.key {
+            None => {
+                self.print_char(ram, 0)?;
+                self.key = Some(None);
+            }
+            Some(None) if key != 0 => self.key = Some(Some(key)),
+            Some(Some(c)) if key == 0 => {
+                self.key = None;
+                self.back_space(ram)?;
+                self.print_char(ram, c)?;
+                return Ok(Some(c));
+            }
+            Some(_) => (),
+        }
+        Ok(None)
+    }
+
+    /// Reads keys until a new line, after printing a message. Returns `None` while still waiting.
+    fn read_line(&mut self, ram: &mut [i16], message: i16) -> NativeResult<Option<Vec<i16>>> {
+        if self.line.is_none() {
+            let message = string_chars(ram, message)?;
+            self.print(ram, &message)?;
+            self.line = Some(Vec::new());
+        }
+        let Some(c) = self.read_char(ram)? else {
+            return Ok(None);
+        };
+        let line = self.line.as_mut().unwrap();
+        match c {
+            NEW_LINE => return Ok(self.line.take()),
+            BACKSPACE => {
+                line.pop();
+            }
+            _ if line.len() < MAX_LINE => line.push(c),
+            _ => (),
+        }
+        Ok(None)
+    }
+}
+
+fn on_screen(x: i16, y: i16) -> bool {
+    (0..512).contains(&x) && (0..256).contains(&y)
+}
+
+/// The address of a character of a string, failing with `error` if it is past the end
+fn string_index(ram: &[i16], string: i16, j: i16, error: i16) -> NativeResult<i16> {
+    if j < 0 || j >= read(ram, string.wrapping_add(1))? {
+        return Err(Stop::Os(error));
+    }
+    Ok(read(ram, string)?.wrapping_add(j))
+}
+
+fn string_chars(ram: &[i16], string: i16) -> NativeResult<Vec<i16>> {
+    let (chars, length) = (read(ram, string)?, read(ram, string.wrapping_add(1))?);
+    (0..length)
+        .map(|j| read(ram, chars.wrapping_add(j)))
+        .collect()
+}
+
+fn append_char(ram: &mut [i16], string: i16, c: i16, error: i16) -> NativeResult<()> {
+    let length = read(ram, string.wrapping_add(1))?;
+    if length == read(ram, string.wrapping_add(2))? {
+        return Err(Stop::Os(error));
+    }
+    write(ram, read(ram, string)?.wrapping_add(length), c)?;
+    write(ram, string.wrapping_add(1), length + 1)
+}
+
+fn erase_last_char(ram: &mut [i16], string: i16) -> NativeResult<()> {
+    match read(ram, string.wrapping_add(1))? {
+        0 => Err(Stop::Os(18)),
+        length => write(ram, string.wrapping_add(1), length - 1),
+    }
+}
+
+fn set_int(ram: &mut [i16], string: i16, value: i16) -> NativeResult<()> {
+    write(ram, string.wrapping_add(1), 0)?;
+    int_chars(value)
+        .into_iter()
+        .try_for_each(|c| append_char(ram, string, c, 19))
+}
+
+/// The characters of a number, as `String.setInt` writes them
+fn int_chars(value: i16) -> Vec<i16> {
+    value.to_string().chars().map(|c| c as i16).collect()
+}
+
+/// The number at the start of some characters, as `String.intValue` reads it
+fn int_value(chars: &[i16]) -> i16 {
+    let (negative, digits) = match chars.first() {
+        Some(&c) if c == '-' as i16 => (true, &chars[1..]),
+        _ => (false, chars),
+    };
+    let value = digits
+        .iter()
+        .take_while(|c| ('0' as i16..='9' as i16).contains(c))
+        .fold(0i16, |value, c| {
+            value.wrapping_mul(10).wrapping_add(c - '0' as i16)
+        });
+    match negative {
+        true => value.wrapping_neg(),
+        false => value,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::jack::{compile, os_vm, parse};
+    use crate::vm::{link, VmCommand, VmMachine, RAM_SIZE};
+
+    const MAIN: &str = "class Main {
+    function void main() {
+        var String s;
+        let s = String.new(10);
+        do s.appendChar(65);
+        do s.setInt(s.intValue() - 1234);
+        do Output.printString(s);
+        do Output.println();
+        do Output.printInt(Math.divide(-1000, 3) + Math.sqrt(1000));
+        do Output.moveCursor(22, 63);
+        do Output.printString(\"wraps\");
+        do Screen.drawLine(0, 255, 511, 100);
+        do Screen.drawRectangle(100, 100, 120, 140);
+        do Screen.setColor(false);
+        do Screen.drawCircle(110, 120, 8);
+        do Memory.poke(8000, Keyboard.readInt(\"? \"));
+        return;
+    }
+}";
+
+    /// Runs `Main` with the named classes of the Jack OS, and the native OS for the others,
+    /// pressing 4, 2 and enter when the program waits for the keyboard
+    fn run(os_classes: &[&str]) -> VmMachine {
+        let main = compile(&parse(MAIN).0.unwrap()).unwrap();
+        let os = os_vm()
+            .into_iter()
+            .filter(|(name, _)| os_classes.contains(name))
+            .map(|(_, commands)| commands);
+        let mut vm = VmMachine::new(link(os.chain([main])));
+        vm.set_native_os(true);
+        vm.bootstrap().unwrap();
+
+        let mut keys = [52, 0, 50, 0, 128, 0].into_iter();
+        let mut held = 0;
+        while vm.run(1000).unwrap() == 1000 {
+            if vm.call_stack().last().unwrap().function == "Sys.halt" {
+                break;
+            }
+            let reading = vm
+                .call_stack()
+                .iter()
+                .any(|frame| frame.function == "Keyboard.readInt")
+                || matches!(
+                    &vm.current_command().unwrap().item,
+                    VmCommand::Call { name, .. } if name == "Keyboard.readInt"
+                );
+            // each key is held and then released for long enough for the Jack OS to notice
+            if reading {
+                held += 1;
+                if held % 50 == 0 {
+                    vm.ram_mut()[RAM_SIZE - 1] = keys.next().unwrap_or(0);
+                }
+            }
+        }
+        vm
+    }
+
+    #[test]
+    fn dependencies() {
+        let defined = HashSet::from(["Main", "Memory"]);
+        assert_eq!(
+            jack_classes(&defined),
+            ["Array", "String", "Output", "Keyboard", "Sys"]
+        );
+        let defined = HashSet::from(["Main", "Screen"]);
+        assert_eq!(jack_classes(&defined), [] as [&str; 0]);
+    }
+
+    #[test]
+    fn native_os() {
+        let native = run(&[]);
+        assert!(native.current_command().is_none());
+        assert_eq!(native.ram()[8000], 42);
+
+        let jack = run(&[
+            "Array", "Keyboard", "Math", "Memory", "Output", "Screen", "String",
+        ]);
+        assert_eq!(jack.ram()[8000], 42);
+        assert_eq!(native.ram()[16384..24576], jack.ram()[16384..24576]);
+    }
+
+    #[test]
+    fn some_classes_native() {
+        let native = run(&[]);
+        for class in ["Math", "Memory", "Output", "Screen", "String"] {
+            let vm = run(&[class]);
+            assert_eq!(vm.ram()[8000], 42, "{class}");
+            assert_eq!(
+                native.ram()[16384..24576],
+                vm.ram()[16384..24576],
+                "{class}"
+            );
+        }
+    }
+}
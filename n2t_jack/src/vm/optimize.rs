@@ -0,0 +1,258 @@
+use super::VmCommand;
+use crate::span::{Span, Spanned};
+use crate::translate::arithmetic::Arithmetic;
+use crate::translate::stack::Segment;
+use std::fmt::{Display, Formatter};
+
+/// Which optimizations to run on VM code before it is translated. Everything is off by default, so
+/// that the output can be compared against the course's reference translator.
+#[derive(Debug, Clone, Default)]
+pub struct Optimizations {
+    /// Replace arithmetic on constants with its result
+    pub fold_constants: bool,
+    /// Turn `push x; pop y` into a copy from `x` to `y` which does not touch the stack
+    pub direct_moves: bool,
+    /// Remove commands which can never be reached because they follow a `goto` or `return`
+    pub remove_dead_code: bool,
+    /// Use shorter sequences for `push constant 0` and `push constant 1`
+    pub small_constants: bool,
+}
+
+impl Optimizations {
+    pub fn all() -> Self {
+        Self {
+            fold_constants: true,
+            direct_moves: true,
+            remove_dead_code: true,
+            small_constants: true,
+        }
+    }
+}
+
+/// A unit of work for the translator, which is either a plain VM command or something that the
+/// optimizer made out of several of them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    Command(VmCommand),
+    Move {
+        from: (Segment, u16),
+        to: (Segment, u16),
+    },
+}
+
+impl Display for Op {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Op::Command(command) => write!(f, "{command}"),
+            Op::Move { from, to } => write!(f, "push {} {}; pop {} {}", from.0, from.1, to.0, to.1),
+        }
+    }
+}
+
+fn join(first: Span, last: Span) -> Span {
+    Span {
+        len: last.offset + last.len - first.offset,
+        ..first
+    }
+}
+
+pub fn optimize(
+    commands: Vec<Spanned<VmCommand>>,
+    optimizations: &Optimizations,
+) -> Vec<Spanned<Op>> {
+    let mut commands = commands;
+    if optimizations.remove_dead_code {
+        commands = remove_dead_code(commands);
+    }
+    if optimizations.fold_constants {
+        commands = fold_constants(commands);
+    }
+
+    let ops = commands.into_iter().map(|Spanned { item, span }| Spanned {
+        item: Op::Command(item),
+        span,
+    });
+    if optimizations.direct_moves {
+        direct_moves(ops)
+    } else {
+        ops.collect()
+    }
+}
+
+/// Drops everything between an unconditional jump and the next place that could be jumped to
+fn remove_dead_code(commands: Vec<Spanned<VmCommand>>) -> Vec<Spanned<VmCommand>> {
+    let mut reachable = true;
+    commands
+        .into_iter()
+        .filter(|command| {
+            match command.item {
+                VmCommand::Label(_) | VmCommand::Function { .. } => reachable = true,
+                _ if !reachable => return false,
+                VmCommand::Goto(_) | VmCommand::Return => reachable = false,
+                _ => (),
+            }
+            true
+        })
+        .collect()
+}
+
+/// Evaluates an operation on constants, if the result can itself be pushed as a constant
+fn evaluate(op: Arithmetic, args: &[u16]) -> Option<u16> {
+    let result = match (op, args) {
+        (Arithmetic::Add, [a, b]) => a.checked_add(*b)?,
+        (Arithmetic::Sub, [a, b]) => a.checked_sub(*b)?,
+        (Arithmetic::And, [a, b]) => a & b,
+        (Arithmetic::Or, [a, b]) => a | b,
+        (Arithmetic::Neg, [0]) => 0,
+        _ => return None,
+    };
+    (result <= i16::MAX as u16).then_some(result)
+}
+
+fn arity(op: Arithmetic) -> usize {
+    match op {
+        Arithmetic::Neg | Arithmetic::Not => 1,
+        _ => 2,
+    }
+}
+
+fn fold_constants(commands: Vec<Spanned<VmCommand>>) -> Vec<Spanned<VmCommand>> {
+    let mut folded: Vec<Spanned<VmCommand>> = Vec::with_capacity(commands.len());
+    for command in commands {
+        if let VmCommand::Arithmetic(op) = command.item {
+            let n = arity(op);
+            let args = folded
+                .iter()
+                .rev()
+                .take(n)
+                .rev()
+                .map(|c| match c.item {
+                    VmCommand::Push(Segment::Constant, value) => Some(value),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()
+                .filter(|args| args.len() == n);
+
+            if let Some(result) = args.and_then(|args| evaluate(op, &args)) {
+                let first = folded.len() - n;
+                let span = join(folded[first].span, command.span);
+                folded.truncate(first);
+                folded.push(Spanned {
+                    item: VmCommand::Push(Segment::Constant, result),
+                    span,
+                });
+                continue;
+            }
+        }
+        folded.push(command);
+    }
+    folded
+}
+
+fn direct_moves(ops: impl Iterator<Item = Spanned<Op>>) -> Vec<Spanned<Op>> {
+    let mut moved: Vec<Spanned<Op>> = Vec::new();
+    for op in ops {
+        if let (
+            Op::Command(VmCommand::Pop(to_segment, to_index)),
+            Some(Spanned {
+                item: Op::Command(VmCommand::Push(from_segment, from_index)),
+                span,
+            }),
+        ) = (&op.item, moved.last())
+        {
+            let span = join(*span, op.span);
+            let item = Op::Move {
+                from: (*from_segment, *from_index),
+                to: (*to_segment, *to_index),
+            };
+            *moved.last_mut().unwrap() = Spanned { item, span };
+        } else {
+            moved.push(op);
+        }
+    }
+    moved
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vm::parse;
+
+    fn optimized(program: &str, optimizations: Optimizations) -> Vec<String> {
+        let commands = parse(program).collect::<Result<Vec<_>, _>>().unwrap();
+        optimize(commands, &optimizations)
+            .into_iter()
+            .map(|op| op.item.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn folding() {
+        let program = "push constant 2\npush constant 3\nadd\npush constant 4\nsub\nneg\npush constant 1\nsub";
+        let optimizations = Optimizations {
+            fold_constants: true,
+            ..Default::default()
+        };
+
+        // 2 + 3 - 4 folds, but -1 cannot be pushed as a constant
+        assert_eq!(
+            optimized(program, optimizations),
+            ["push constant 1", "neg", "push constant 1", "sub"]
+        );
+    }
+
+    #[test]
+    fn moves() {
+        let program = "push local 0\npop that 1\npush constant 3\nadd";
+        let optimizations = Optimizations {
+            direct_moves: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            optimized(program, optimizations),
+            ["push local 0; pop that 1", "push constant 3", "add"]
+        );
+    }
+
+    #[test]
+    fn dead_code() {
+        let program = "
+function f 0
+    goto END
+    push constant 1
+    pop local 0
+label END
+    return
+    push constant 2
+function g 0
+    return
+";
+        let optimizations = Optimizations {
+            remove_dead_code: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            optimized(program, optimizations),
+            [
+                "function f 0",
+                "goto END",
+                "label END",
+                "return",
+                "function g 0",
+                "return"
+            ]
+        );
+    }
+
+    #[test]
+    fn spans_cover_merged_commands() {
+        let program = "push constant 2\npush constant 3\nadd\npop temp 0\n";
+        let commands = parse(program).collect::<Result<Vec<_>, _>>().unwrap();
+        let ops = optimize(commands, &Optimizations::all());
+
+        assert_eq!(ops.len(), 1);
+        assert_eq!(ops[0].span.of(program), program.trim_end());
+    }
+}
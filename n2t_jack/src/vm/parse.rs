@@ -0,0 +1,241 @@
+use super::{VmCommand, VmParseError};
+use crate::span::{Span, Spanned};
+use crate::translate::arithmetic::Arithmetic;
+use crate::translate::stack::Segment;
+use std::str::FromStr;
+
+type ParseResult = Result<Spanned<VmCommand>, Spanned<VmParseError>>;
+
+/// Parses a VM program, producing one result for every line that contains a command. Blank lines
+/// and comments are skipped.
+pub fn parse(program: &str) -> impl Iterator<Item = ParseResult> + '_ {
+    program
+        .split_inclusive('\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((start, line))
+        })
+        .zip(1..)
+        .filter_map(|((offset, line), line_number)| parse_line(line, offset, line_number))
+}
+
+/// A word of a line, along with the position at which it starts
+struct Word<'a> {
+    text: &'a str,
+    span: Span,
+}
+
+fn words<'a>(line: &'a str, offset: usize, line_number: u32) -> Vec<Word<'a>> {
+    let code = line.split_once("//").map_or(line, |(code, _)| code);
+    let mut words = Vec::new();
+    let mut start = None;
+
+    for (i, c) in code
+        .char_indices()
+        .chain(std::iter::once((code.len(), ' ')))
+    {
+        match (start, c.is_whitespace()) {
+            (None, false) => start = Some(i),
+            (Some(s), true) => {
+                words.push(Word {
+                    text: &code[s..i],
+                    span: Span {
+                        offset: offset + s,
+                        len: i - s,
+                        line: line_number,
+                        column: code[..s].chars().count() + 1,
+                    },
+                });
+                start = None;
+            }
+            _ => (),
+        }
+    }
+
+    words
+}
+
+/// Parses a single line which begins at byte `offset` of its program. Returns `None` if the line
+/// does not contain a command.
+pub fn parse_line(line: &str, offset: usize, line_number: u32) -> Option<ParseResult> {
+    let words = words(line, offset, line_number);
+    let (first, last) = (words.first()?, words.last()?);
+    let span = Span {
+        len: last.span.offset + last.span.len - first.span.offset,
+        ..first.span
+    };
+
+    let error = |item, span| Spanned { item, span };
+    let mut args = words.iter().skip(1);
+    let mut next = |expected| {
+        args.next()
+            .ok_or_else(|| error(VmParseError::Missing(expected), span))
+    };
+
+    let number = |word: &Word| {
+        u16::from_str(word.text)
+            .ok()
+            .filter(|n| *n <= i16::MAX as u16)
+            .ok_or_else(|| error(VmParseError::BadNumber(word.text.to_string()), word.span))
+    };
+    let name = |word: &Word| {
+        let valid = !word.text.starts_with(|c: char| c.is_ascii_digit())
+            && word
+                .text
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_.$:".contains(c));
+        if valid {
+            Ok(word.text.to_string())
+        } else {
+            Err(error(
+                VmParseError::BadName(word.text.to_string()),
+                word.span,
+            ))
+        }
+    };
+    let segment = |word: &Word| {
+        Segment::from_str(word.text).map_err(|_| {
+            error(
+                VmParseError::UnknownSegment(word.text.to_string()),
+                word.span,
+            )
+        })
+    };
+
+    let command = (|| {
+        Ok(match first.text {
+            "push" | "pop" => {
+                let seg_word = next("a segment")?;
+                let seg = segment(seg_word)?;
+                let index = number(next("an index")?)?;
+                if seg == Segment::Pointer && index > 1 {
+                    return Err(error(VmParseError::PointerIndex, span));
+                }
+                if first.text == "push" {
+                    VmCommand::Push(seg, index)
+                } else if seg == Segment::Constant {
+                    return Err(error(VmParseError::PopConstant, seg_word.span));
+                } else {
+                    VmCommand::Pop(seg, index)
+                }
+            }
+            "label" => VmCommand::Label(name(next("a label")?)?),
+            "goto" => VmCommand::Goto(name(next("a label")?)?),
+            "if-goto" => VmCommand::IfGoto(name(next("a label")?)?),
+            "function" => VmCommand::Function {
+                name: name(next("a function name")?)?,
+                locals: number(next("the number of locals")?)?,
+            },
+            "call" => VmCommand::Call {
+                name: name(next("a function name")?)?,
+                args: number(next("the number of arguments")?)?,
+            },
+            "return" => VmCommand::Return,
+            op => VmCommand::Arithmetic(
+                Arithmetic::from_str(op)
+                    .map_err(|_| error(VmParseError::UnknownCommand(op.to_string()), first.span))?,
+            ),
+        })
+    })();
+
+    Some(command.and_then(|command| match args.next() {
+        Some(extra) => Err(error(
+            VmParseError::Trailing(extra.text.to_string()),
+            extra.span,
+        )),
+        None => Ok(Spanned {
+            item: command,
+            span,
+        }),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn commands() {
+        let program = "
+// a comment
+function Main.main 2
+    push constant 7 // a trailing comment
+    pop local 1
+label LOOP$1
+    lt
+    if-goto LOOP$1
+    call Math.multiply 2
+    return
+";
+
+        let commands = parse(program)
+            .map(|res| res.unwrap().item)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            commands,
+            [
+                VmCommand::Function {
+                    name: "Main.main".to_string(),
+                    locals: 2
+                },
+                VmCommand::Push(Segment::Constant, 7),
+                VmCommand::Pop(Segment::Local, 1),
+                VmCommand::Label("LOOP$1".to_string()),
+                VmCommand::Arithmetic(Arithmetic::Lt),
+                VmCommand::IfGoto("LOOP$1".to_string()),
+                VmCommand::Call {
+                    name: "Math.multiply".to_string(),
+                    args: 2
+                },
+                VmCommand::Return,
+            ]
+        );
+
+        // every command prints back out the way it was written
+        let printed = commands.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(printed[1], "push constant 7");
+        assert_eq!(printed[5], "if-goto LOOP$1");
+        parse(program)
+            .map(Result::unwrap)
+            .for_each(|cmd| assert_eq!(cmd.span.of(program), cmd.item.to_string()));
+    }
+
+    #[test]
+    fn spans() {
+        let program = "push constant 1\n\n  \tpop  that 5\n";
+        let spans = parse(program)
+            .map(|res| res.unwrap().span)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            spans[1],
+            Span {
+                offset: 20,
+                len: 11,
+                line: 3,
+                column: 4
+            }
+        );
+        assert_eq!(spans[1].of(program), "pop  that 5");
+    }
+
+    #[test]
+    fn errors() {
+        let err = |line: &str| parse(line).next().unwrap().unwrap_err();
+
+        assert_eq!(
+            err("jump").item,
+            VmParseError::UnknownCommand("jump".to_string())
+        );
+        assert_eq!(err("pop constant 3").item, VmParseError::PopConstant);
+        assert_eq!(err("push pointer 2").item, VmParseError::PointerIndex);
+        assert_eq!(err("push local").item, VmParseError::Missing("an index"));
+        assert_eq!(
+            err("push local 40000").item,
+            VmParseError::BadNumber("40000".to_string())
+        );
+        assert_eq!(err("goto 1A").item, VmParseError::BadName("1A".to_string()));
+        assert_eq!(err("return 0").span.column, 8);
+    }
+}
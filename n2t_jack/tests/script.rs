@@ -0,0 +1,85 @@
+use n2t_jack::script::{self, VmTarget};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MAIN: &str = "function Main.fibonacci 0
+push argument 0
+push constant 2
+lt
+if-goto IF_TRUE
+goto IF_FALSE
+label IF_TRUE
+push argument 0
+return
+label IF_FALSE
+push argument 0
+push constant 2
+sub
+call Main.fibonacci 1
+push argument 0
+push constant 1
+sub
+call Main.fibonacci 1
+add
+return
+";
+
+const SYS: &str = "function Sys.init 0
+push constant 4
+call Main.fibonacci 1
+label WHILE
+goto WHILE
+";
+
+/// The course's FibonacciElementVME.tst, which gives the program 110 steps
+const SCRIPT: &str = "load,
+output-file FibonacciElement.out,
+compare-to FibonacciElement.cmp,
+output-list RAM[0]%D1.6.1 RAM[261]%D1.6.1;
+
+set sp 261,
+set local 261,
+set argument 256,
+set this 3000,
+set that 4000;
+
+repeat 110 {
+  vmstep;
+}
+
+output;
+";
+
+const CMP: &str = "| RAM[0] |RAM[261]|
+|    262 |      3 |
+";
+
+/// Writes the FibonacciElement program and its script to a scratch directory
+fn fibonacci(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("n2t_jack_script_{name}"));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("Main.vm"), MAIN).unwrap();
+    fs::write(dir.join("Sys.vm"), SYS).unwrap();
+    fs::write(dir.join("FibonacciElementVME.tst"), SCRIPT).unwrap();
+    fs::write(dir.join("FibonacciElement.cmp"), CMP).unwrap();
+    dir
+}
+
+#[test]
+fn step_budget() {
+    let dir = fibonacci("step_budget");
+    let runner =
+        script::run_file(&dir.join("FibonacciElementVME.tst"), VmTarget::default()).unwrap();
+    assert!(runner.compared());
+    assert_eq!(runner.output().join("\n") + "\n", CMP);
+}
+
+#[test]
+fn bare_name() {
+    let dir = fibonacci("bare_name");
+    std::env::set_current_dir(&dir).unwrap();
+    let path = Path::new("FibonacciElementVME.tst");
+    assert!(script::run_file(path, VmTarget::default())
+        .unwrap()
+        .compared());
+}
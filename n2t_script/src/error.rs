@@ -0,0 +1,13 @@
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("line {line}: {message}")]
+    Syntax { line: usize, message: String },
+
+    #[error("`{0}` is not a variable")]
+    BadVariable(String),
+
+    #[error("`{0}` is not a value")]
+    BadValue(String),
+}
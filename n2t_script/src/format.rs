@@ -0,0 +1,203 @@
+use crate::ParseError;
+use std::fmt::{Display, Formatter};
+
+/// A name which can be read or set by a script, such as `in`, `sel[1]`, `sp`, or `RAM[256]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    pub name: String,
+    pub index: Option<u16>,
+}
+
+impl Variable {
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let bad = || ParseError::BadVariable(text.to_string());
+        match text.split_once('[') {
+            Some((name, rest)) => {
+                let index = rest.strip_suffix(']').ok_or_else(bad)?;
+                Ok(Self {
+                    name: name.to_string(),
+                    index: Some(index.parse().map_err(|_| bad())?),
+                })
+            }
+            None if !text.is_empty() => Ok(Self {
+                name: text.to_string(),
+                index: None,
+            }),
+            None => Err(bad()),
+        }
+    }
+}
+
+impl Display for Variable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.index {
+            Some(index) => write!(f, "{}[{index}]", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Radix {
+    Binary,
+    Decimal,
+    Hex,
+    String,
+}
+
+/// One column of an output list, written as `name%<radix><left>.<width>.<right>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    pub variable: Variable,
+    pub radix: Radix,
+    pub pad_left: usize,
+    pub width: usize,
+    pub pad_right: usize,
+}
+
+impl Column {
+    /// Parses a column. Columns without a format are printed as `%D1.6.1`.
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let bad = || ParseError::BadValue(text.to_string());
+        let (variable, format) = text.split_once('%').unwrap_or((text, "D1.6.1"));
+
+        let mut chars = format.chars();
+        let radix = match chars.next() {
+            Some('B') => Radix::Binary,
+            Some('D') => Radix::Decimal,
+            Some('X') => Radix::Hex,
+            Some('S') => Radix::String,
+            _ => return Err(bad()),
+        };
+        let sizes = chars
+            .as_str()
+            .split('.')
+            .map(str::parse)
+            .collect::<Result<Vec<usize>, _>>()
+            .map_err(|_| bad())?;
+        let [pad_left, width, pad_right] = sizes[..] else {
+            return Err(bad());
+        };
+
+        Ok(Self {
+            variable: Variable::parse(variable)?,
+            radix,
+            pad_left,
+            width,
+            pad_right,
+        })
+    }
+
+    fn total_width(&self) -> usize {
+        self.pad_left + self.width + self.pad_right
+    }
+
+    /// The name of the column, centered in the space its values take up
+    pub fn header(&self) -> String {
+        let name = self.variable.to_string();
+        let total = self.total_width();
+        let name = &name[..name.len().min(total)];
+        let left = (total - name.len()) / 2;
+        format!(
+            "{:left$}{name}{:right$}",
+            "",
+            "",
+            right = total - name.len() - left
+        )
+    }
+
+    pub fn format(&self, value: i16) -> String {
+        let width = self.width;
+        let bits = value as u16;
+        match self.radix {
+            Radix::Binary => self.pad(&format!("{:0width$b}", bits & mask(width, 1))),
+            Radix::Hex => self.pad(&format!("{:0width$X}", bits & mask(width, 4))),
+            Radix::Decimal => self.pad(&format!("{value:>width$}")),
+            Radix::String => self.text(&value.to_string()),
+        }
+    }
+
+    /// Formats text, such as the clock's `time`, aligned to the left
+    pub fn text(&self, text: &str) -> String {
+        self.pad(&format!("{text:<width$}", width = self.width))
+    }
+
+    fn pad(&self, text: &str) -> String {
+        format!(
+            "{:left$}{text}{:right$}",
+            "",
+            "",
+            left = self.pad_left,
+            right = self.pad_right
+        )
+    }
+}
+
+/// Keeps the lowest `digits` digits of a number with `bits` bits per digit
+fn mask(digits: usize, bits: usize) -> u16 {
+    match digits * bits {
+        n if n >= 16 => u16::MAX,
+        n => (1 << n) - 1,
+    }
+}
+
+/// Parses a value given to `set`, which is decimal unless prefixed by `%B`, `%X`, or `%D`
+pub fn parse_value(text: &str) -> Result<i16, ParseError> {
+    let bad = || ParseError::BadValue(text.to_string());
+    let (digits, radix) = match text.get(..2) {
+        Some("%B") => (&text[2..], 2),
+        Some("%X") => (&text[2..], 16),
+        Some("%D") => (&text[2..], 10),
+        _ => (text, 10),
+    };
+    match radix {
+        10 => digits.parse().map_err(|_| bad()),
+        _ => u16::from_str_radix(digits, radix)
+            .map(|n| n as i16)
+            .map_err(|_| bad()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn columns() {
+        let column = Column::parse("time%S1.4.1").unwrap();
+        assert_eq!(column.header(), " time ");
+        assert_eq!(column.text("0+"), " 0+   ");
+        assert_eq!(column.format(12), " 12   ");
+
+        let column = Column::parse("RAM[256]%D1.6.1").unwrap();
+        assert_eq!(column.header(), "RAM[256]");
+        assert_eq!(column.format(-7), "     -7 ");
+
+        let column = Column::parse("in%B2.1.2").unwrap();
+        assert_eq!(column.header(), " in  ");
+        assert_eq!(column.format(1), "  1  ");
+
+        let column = Column::parse("address%D3.1.3").unwrap();
+        assert_eq!(column.header(), "address");
+        assert_eq!(column.format(7), "   7   ");
+
+        let column = Column::parse("out%D1.6.1").unwrap();
+        assert_eq!(column.format(-32123), " -32123 ");
+
+        let column = Column::parse("out%X1.4.1").unwrap();
+        assert_eq!(column.header(), " out  ");
+        assert_eq!(column.format(-1), " FFFF ");
+
+        let column = Column::parse("sel[1]%B1.1.1").unwrap();
+        assert_eq!(column.variable.index, Some(1));
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(parse_value("-32123").unwrap(), -32123);
+        assert_eq!(parse_value("%B0011000000111001").unwrap(), 12345);
+        assert_eq!(parse_value("%B101").unwrap(), 5);
+        assert_eq!(parse_value("%XFFFF").unwrap(), -1);
+        assert!(parse_value("seven").is_err());
+    }
+}
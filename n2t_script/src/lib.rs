@@ -0,0 +1,21 @@
+//! The course's `.tst` test scripts, which the hardware simulator, the CPU emulator and the VM
+//! emulator all run. Each simulator has its own ways of stepping, but reads scripts and formats
+//! their output the same way.
+
+mod error;
+mod format;
+mod parse;
+
+pub use error::ParseError;
+pub use format::{parse_value, Column, Radix, Variable};
+pub use parse::{parse, Command, Comparison, Condition, Statement, Step};
+
+use std::path::Path;
+
+/// The directory which files named by the script at `path` are relative to
+pub fn script_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
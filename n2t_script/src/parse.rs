@@ -0,0 +1,386 @@
+use crate::format::{parse_value, Column, Variable};
+use crate::ParseError;
+use std::iter::Peekable;
+use std::vec::IntoIter;
+
+/// The commands which advance a simulator, which differ between simulators
+pub trait Step: Sized {
+    /// The step named by a command, if this simulator has it
+    fn parse(name: &str) -> Option<Self>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    pub fn test(self, a: i16, b: i16) -> bool {
+        match self {
+            Comparison::Eq => a == b,
+            Comparison::Ne => a != b,
+            Comparison::Lt => a < b,
+            Comparison::Le => a <= b,
+            Comparison::Gt => a > b,
+            Comparison::Ge => a >= b,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    pub variable: Variable,
+    pub comparison: Comparison,
+    pub value: i16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command<S> {
+    /// Loads a chip or program. With no file, the VM emulator loads every VM file in the script's
+    /// directory.
+    Load(Option<String>),
+    OutputFile(String),
+    CompareTo(String),
+    OutputList(Vec<Column>),
+    Set(Variable, i16),
+    Output,
+    Step(S),
+    Echo(String),
+    /// Commands which only affect a simulator's user interface, like `breakpoint`
+    Ignored,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Statement<S> {
+    Command {
+        line: usize,
+        command: Command<S>,
+    },
+    /// Repeats its body `count` times, or for as long as the simulator runs if there is no count
+    Repeat {
+        count: Option<u32>,
+        body: Vec<Statement<S>>,
+    },
+    While {
+        condition: Condition,
+        body: Vec<Statement<S>>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Text(String),
+    /// Any of `,`, `;`, and `!`, which all end a command
+    End,
+    Open,
+    Close,
+}
+
+fn syntax(line: usize, message: impl Into<String>) -> ParseError {
+    ParseError::Syntax {
+        line,
+        message: message.into(),
+    }
+}
+
+fn tokenize(script: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut chars = script.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            c if c.is_whitespace() => (),
+            '/' if chars.peek() == Some(&'/') => while chars.next_if(|&c| c != '\n').is_some() {},
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if last == '*' => break,
+                        Some(c) => {
+                            line += (c == '\n') as usize;
+                            last = c;
+                        }
+                        None => return Err(syntax(line, "Unterminated comment")),
+                    }
+                }
+            }
+            ',' | ';' | '!' => tokens.push((line, Token::End)),
+            '{' => tokens.push((line, Token::Open)),
+            '}' => tokens.push((line, Token::Close)),
+            '"' => {
+                let text = chars.by_ref().take_while(|&c| c != '"').collect::<String>();
+                line += text.matches('\n').count();
+                tokens.push((line, Token::Text(text)));
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|&c| !c.is_whitespace() && !",;!{}\"".contains(c))
+                {
+                    word.push(c);
+                }
+                tokens.push((line, Token::Word(word)));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+type Tokens = Peekable<IntoIter<(usize, Token)>>;
+
+/// Takes the words of a command up to the token which ends it
+fn arguments(tokens: &mut Tokens) -> Vec<Token> {
+    let mut args = Vec::new();
+    while let Some((_, token)) =
+        tokens.next_if(|(_, t)| matches!(t, Token::Word(_) | Token::Text(_)))
+    {
+        args.push(token);
+    }
+    tokens.next_if(|(_, t)| *t == Token::End);
+    args
+}
+
+fn words(line: usize, args: Vec<Token>) -> Result<Vec<String>, ParseError> {
+    args.into_iter()
+        .map(|arg| match arg {
+            Token::Word(word) => Ok(word),
+            _ => Err(syntax(line, "Unexpected string")),
+        })
+        .collect()
+}
+
+fn one(line: usize, name: &str, args: Vec<Token>) -> Result<String, ParseError> {
+    match &mut words(line, args)?[..] {
+        [word] => Ok(std::mem::take(word)),
+        _ => Err(syntax(line, format!("`{name}` takes one argument"))),
+    }
+}
+
+fn condition(line: usize, words: &[String]) -> Result<Condition, ParseError> {
+    let text = words.concat();
+    let operators = [
+        ("<>", Comparison::Ne),
+        ("<=", Comparison::Le),
+        (">=", Comparison::Ge),
+        ("=", Comparison::Eq),
+        ("<", Comparison::Lt),
+        (">", Comparison::Gt),
+    ];
+    let (operator, comparison) = operators
+        .into_iter()
+        .find(|(operator, _)| text.contains(operator))
+        .ok_or_else(|| syntax(line, format!("`{text}` is not a condition")))?;
+    let (variable, value) = text.split_once(operator).unwrap();
+    Ok(Condition {
+        variable: Variable::parse(variable)?,
+        comparison,
+        value: parse_value(value)?,
+    })
+}
+
+fn command<S: Step>(
+    line: usize,
+    name: &str,
+    mut args: Vec<Token>,
+) -> Result<Command<S>, ParseError> {
+    let no_args = |args: Vec<Token>, command| match args.is_empty() {
+        true => Ok(command),
+        false => Err(syntax(line, format!("`{name}` takes no arguments"))),
+    };
+    match name {
+        "load" => match &mut words(line, args)?[..] {
+            [] => Ok(Command::Load(None)),
+            [file] => Ok(Command::Load(Some(std::mem::take(file)))),
+            _ => Err(syntax(line, "`load` takes at most one file")),
+        },
+        "output-file" => Ok(Command::OutputFile(one(line, name, args)?)),
+        "compare-to" => Ok(Command::CompareTo(one(line, name, args)?)),
+        "output-list" => words(line, args)?
+            .iter()
+            .map(|column| Column::parse(column))
+            .collect::<Result<_, _>>()
+            .map(Command::OutputList),
+        "set" => match &words(line, args)?[..] {
+            [variable, value] => Ok(Command::Set(
+                Variable::parse(variable)?,
+                parse_value(value)?,
+            )),
+            _ => Err(syntax(line, "`set` takes a variable and a value")),
+        },
+        "output" => no_args(args, Command::Output),
+        "echo" => match &mut args[..] {
+            [Token::Text(text)] | [Token::Word(text)] => Ok(Command::Echo(std::mem::take(text))),
+            _ => Err(syntax(line, "`echo` takes one string")),
+        },
+        "clear-echo" | "breakpoint" | "clear-breakpoints" => Ok(Command::Ignored),
+        _ => match S::parse(name) {
+            Some(step) => no_args(args, Command::Step(step)),
+            None => Err(syntax(line, format!("`{name}` is not a script command"))),
+        },
+    }
+}
+
+fn block<S: Step>(tokens: &mut Tokens, line: usize) -> Result<Vec<Statement<S>>, ParseError> {
+    let body = statements(tokens)?;
+    match tokens.next() {
+        Some((_, Token::Close)) => Ok(body),
+        _ => Err(syntax(line, "Expected `}` to close the block")),
+    }
+}
+
+fn statements<S: Step>(tokens: &mut Tokens) -> Result<Vec<Statement<S>>, ParseError> {
+    let mut statements = Vec::new();
+    while let Some((line, token)) = tokens.next_if(|(_, t)| *t != Token::Close) {
+        let name = match token {
+            Token::Word(name) => name,
+            Token::End => continue,
+            _ => return Err(syntax(line, "Expected a command")),
+        };
+
+        let args = arguments(tokens);
+        let opens = tokens.next_if(|(_, t)| *t == Token::Open).is_some();
+        statements.push(match name.as_str() {
+            "repeat" if opens => {
+                let count = match &words(line, args)?[..] {
+                    [] => None,
+                    [count] => Some(
+                        count
+                            .parse()
+                            .map_err(|_| syntax(line, format!("`{count}` is not a count")))?,
+                    ),
+                    _ => return Err(syntax(line, "`repeat` takes at most one count")),
+                };
+                Statement::Repeat {
+                    count,
+                    body: block(tokens, line)?,
+                }
+            }
+            "while" if opens => Statement::While {
+                condition: condition(line, &words(line, args)?)?,
+                body: block(tokens, line)?,
+            },
+            _ if opens => return Err(syntax(line, format!("`{name}` cannot start a block"))),
+            _ => Statement::Command {
+                line,
+                command: command(line, &name, args)?,
+            },
+        });
+    }
+    Ok(statements)
+}
+
+/// Parses a script, with steps named as a simulator `S` names them
+pub fn parse<S: Step>(script: &str) -> Result<Vec<Statement<S>>, ParseError> {
+    let mut tokens = tokenize(script)?.into_iter().peekable();
+    let statements = statements(&mut tokens)?;
+    match tokens.next() {
+        Some((line, _)) => Err(syntax(line, "Unmatched `}`")),
+        None => Ok(statements),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Clock {
+        Tick,
+        Tock,
+    }
+
+    impl Step for Clock {
+        fn parse(name: &str) -> Option<Self> {
+            match name {
+                "tick" => Some(Clock::Tick),
+                "tock" => Some(Clock::Tock),
+                _ => None,
+            }
+        }
+    }
+
+    fn parse(script: &str) -> Result<Vec<Statement<Clock>>, ParseError> {
+        super::parse(script)
+    }
+
+    #[test]
+    fn script() {
+        let script = parse(
+            r#"
+// Runs BasicTest.vm
+load BasicTest.vm,
+output-list RAM[256]%D1.6.1 sp;
+
+set sp 256, /* the stack
+starts here */ set local[2] %X10;
+repeat 25 {
+    tick, tock;
+}
+while RAM[0] <> 0 { tick, tock, echo "still running"; }
+output;
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(script.len(), 7);
+        assert_eq!(
+            script[0],
+            Statement::Command {
+                line: 3,
+                command: Command::Load(Some("BasicTest.vm".to_string()))
+            }
+        );
+        assert_eq!(
+            script[3],
+            Statement::Command {
+                line: 7,
+                command: Command::Set(
+                    Variable {
+                        name: "local".to_string(),
+                        index: Some(2)
+                    },
+                    16
+                )
+            }
+        );
+        assert!(
+            matches!(&script[4], Statement::Repeat { count: Some(25), body } if body.len() == 2)
+        );
+        let Statement::While { condition, body } = &script[5] else {
+            panic!("expected a while loop")
+        };
+        assert_eq!(condition.comparison, Comparison::Ne);
+        assert_eq!(
+            body[1],
+            Statement::Command {
+                line: 11,
+                command: Command::Step(Clock::Tock)
+            }
+        );
+        assert_eq!(
+            body[2],
+            Statement::Command {
+                line: 11,
+                command: Command::Echo("still running".to_string())
+            }
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse("repeat 3 { tick;").is_err());
+        assert!(parse("repeat three { tick; }").is_err());
+        assert!(parse("tock; }").is_err());
+        assert!(parse("vmstep;").is_err());
+        assert!(parse("tick 2;").is_err());
+        assert!(parse("set sp").is_err());
+    }
+}
@@ -4,9 +4,15 @@ use std::fs::{File, OpenOptions};
 use std::io;
 use std::path::{Path, PathBuf};
 
+/// Opens a file to write output to. When overwriting, the file is emptied first so that nothing of
+/// a longer old version is left after the new output.
 pub fn open_file(path: impl AsRef<Path>, overwrite: bool) -> Result<File, io::Error> {
     if overwrite {
-        OpenOptions::new().write(true).create(true).open(path)
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
     } else {
         OpenOptions::new().write(true).create_new(true).open(path)
     }
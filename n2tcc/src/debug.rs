@@ -0,0 +1,176 @@
+use std::{
+    io::{self, BufRead, Write},
+    path::PathBuf,
+};
+
+use clap::Args;
+use n2t_jack::debug::{Debugger, Machine, Program, Stop};
+use n2t_jack::jack::Dialect;
+use n2t_jack::pipeline::{Pipeline, Stage};
+
+use super::jack::{exit_with, library, read_sources, run_pipeline, sources};
+use super::test::TargetKind;
+
+/// The most VM commands a single debugger command may run before it gives control back
+const MAX_STEPS: usize = 50_000_000;
+
+const HELP: &str = "\
+break <class> <line>   stop at the statement starting on a line (b)
+clear <class> <line>   remove a breakpoint
+step                   run to the next statement, entering calls (s)
+next                   run to the next statement in this subroutine (n)
+finish                 run until this subroutine returns (f)
+continue               run to the next breakpoint (c)
+locals                 show the variables in scope (l)
+where                  show the statement about to run (w)
+quit                   stop debugging (q)";
+
+#[derive(Args)]
+pub struct Debug {
+    /// A .jack file, or a directory of them
+    file_name: PathBuf,
+    /// What to run the program on
+    #[clap(long, arg_enum, default_value = "vm")]
+    target: TargetKind,
+    /// Which version of Jack the program is written in. The extended dialect adds for loops,
+    /// character literals, else if, operator precedence, const class constants, and break and
+    /// continue.
+    #[clap(long, default_value = "standard", possible_values = ["standard", "extended"])]
+    dialect: Dialect,
+}
+
+impl Debug {
+    pub fn run(self) {
+        let sources = sources(&self.file_name);
+        let pipeline = Pipeline {
+            dialect: self.dialect,
+            os: true,
+            library: library(&sources),
+            ..Default::default()
+        };
+        let files = read_sources(&sources);
+        let program = Program::from_artifacts(run_pipeline(&pipeline, &files, Stage::Compile));
+        let files = sources
+            .into_iter()
+            .zip(files.into_iter().map(|file| file.text));
+
+        let session = Session {
+            files: files.collect(),
+        };
+        match self.target {
+            TargetKind::Vm => {
+                let machine = program.vm_machine().unwrap_or_else(|e| exit_with(e));
+                session.run(Debugger::new(program, machine))
+            }
+            TargetKind::Cpu => {
+                let machine = program.hack_machine().unwrap_or_else(|e| exit_with(e));
+                session.run(Debugger::new(program, machine))
+            }
+        }
+    }
+}
+
+/// Reads debugger commands from standard input, showing the source of the program's classes
+struct Session {
+    /// The path and source of each class, in the order of the program's classes
+    files: Vec<(PathBuf, String)>,
+}
+
+impl Session {
+    fn run(&self, mut debugger: Debugger<impl Machine>) {
+        println!("Type help for a list of commands");
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+        loop {
+            print!("(jack) ");
+            io::stdout().flush().unwrap();
+            let Some(Ok(line)) = lines.next() else {
+                return;
+            };
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let stop = match words.as_slice() {
+                [] => continue,
+                ["break" | "b", class, line] => {
+                    match line.parse() {
+                        Ok(line) if debugger.set_breakpoint(class, line) => (),
+                        _ => println!("There is no statement starting on line {line} of {class}"),
+                    }
+                    continue;
+                }
+                ["clear", class, line] => {
+                    if !line
+                        .parse()
+                        .is_ok_and(|line| debugger.clear_breakpoint(class, line))
+                    {
+                        println!("There is no breakpoint on line {line} of {class}");
+                    }
+                    continue;
+                }
+                ["step" | "s"] => debugger.step_into(MAX_STEPS),
+                ["next" | "n"] => debugger.step_over(MAX_STEPS),
+                ["finish" | "f"] => debugger.step_out(MAX_STEPS),
+                ["continue" | "c"] => debugger.resume(MAX_STEPS),
+                ["locals" | "l"] => {
+                    for variable in debugger.variables() {
+                        println!(
+                            "{} {} {} = {}",
+                            variable.kind, variable.ty, variable.name, variable.value
+                        );
+                    }
+                    continue;
+                }
+                ["where" | "w"] => {
+                    self.show(&debugger);
+                    continue;
+                }
+                ["quit" | "q"] => return,
+                ["help" | "h"] => {
+                    println!("{HELP}");
+                    continue;
+                }
+                _ => {
+                    println!("Unknown command {line:?}; type help for a list of commands");
+                    continue;
+                }
+            };
+
+            match stop {
+                Ok(Stop::Statement) => self.show(&debugger),
+                Ok(Stop::Breakpoint) => {
+                    print!("Breakpoint at ");
+                    self.show(&debugger);
+                }
+                Ok(Stop::Halted) => {
+                    println!("The program has finished");
+                    return;
+                }
+                Ok(Stop::StepLimit) => {
+                    println!("Stopped after {MAX_STEPS} VM commands without reaching a statement")
+                }
+                Err(e) => exit_with(e),
+            }
+        }
+    }
+
+    /// Prints the statement the program is stopped at, with the line of source it starts on
+    fn show(&self, debugger: &Debugger<impl Machine>) {
+        let Some(location) = debugger.location() else {
+            println!("The program is not stopped in a statement");
+            return;
+        };
+        let class = debugger
+            .program()
+            .classes
+            .iter()
+            .position(|class| class.name.item == location.class)
+            .unwrap();
+        let source = self.files[class].1.lines().nth(location.line as usize - 1);
+        println!(
+            "{}.{} line {}: {}",
+            location.class,
+            location.subroutine,
+            location.line,
+            source.unwrap_or_default().trim()
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use std::{fs, path::PathBuf};
+
+use clap::{Args, Subcommand};
+use n2t_jack::jack;
+
+use super::jack::{exit_with, print_error, sources};
+
+#[derive(Args)]
+pub struct Fmt {
+    #[clap(subcommand)]
+    language: Language,
+}
+
+#[derive(Subcommand)]
+enum Language {
+    /// Format Jack files in place
+    Jack(Jack),
+}
+
+#[derive(Args)]
+struct Jack {
+    /// A .jack file, or a directory of them
+    file_name: PathBuf,
+    /// Write nothing, but list the files which are not formatted and fail if there are any
+    #[clap(long)]
+    check: bool,
+    /// Which version of Jack the files are written in. The extended dialect adds for loops,
+    /// character literals, else if, operator precedence, const class constants, and break and
+    /// continue.
+    #[clap(long, default_value = "standard", possible_values = ["standard", "extended"])]
+    dialect: jack::Dialect,
+}
+
+impl Fmt {
+    pub fn run(self) {
+        match self.language {
+            Language::Jack(jack) => jack.run(),
+        }
+    }
+}
+
+impl Jack {
+    fn run(self) {
+        let mut failed = false;
+        for source in sources(&self.file_name) {
+            let file = fs::read_to_string(&source)
+                .unwrap_or_else(|_| exit_with(format!("File not found: {source:?}")));
+            let formatted = match jack::format_with(&file, self.dialect) {
+                Ok(formatted) => formatted,
+                Err(errors) => {
+                    errors.iter().for_each(|e| print_error(&source, e));
+                    failed = true;
+                    continue;
+                }
+            };
+            if formatted == file {
+                continue;
+            }
+            if self.check {
+                println!("{}", source.display());
+                failed = true;
+            } else {
+                fs::write(&source, formatted)
+                    .unwrap_or_else(|e| exit_with(format!("Could not write {source:?}: {e}")));
+            }
+        }
+        if failed {
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use std::{
+    fmt::Display,
+    fs::{self, File},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::{ArgEnum, Args};
+use n2t_jack::jack;
+use n2t_jack::pipeline::{Artifacts, Language, Pipeline, Severity, SourceFile, Stage};
+use n2t_jack::span::Spanned;
+use n2t_jack::translate::TranslateOptions;
+use n2t_jack::vm::{self, VmCommand};
+
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    /// VM code, like the course's compiler produces
+    Vm,
+    /// Assembly for the whole program, along with the VM code of each class
+    Asm,
+    /// Machine code for the whole program, along with the VM code of each class
+    Hack,
+    /// The tokens of the program, like project 10's xxxT.xml files
+    TokensXml,
+    /// The parse tree of the program, like project 10's xxx.xml files
+    Xml,
+}
+
+impl Emit {
+    /// The name of the file to produce, without an extension
+    fn file_stem(self, source_name: &str) -> String {
+        match self {
+            Emit::TokensXml => format!("{source_name}T"),
+            _ => source_name.to_string(),
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Emit::Vm => "vm",
+            Emit::Asm => "asm",
+            Emit::Hack => "hack",
+            Emit::TokensXml | Emit::Xml => "xml",
+        }
+    }
+
+    /// Whether a single file is produced for all of the classes together
+    fn whole_program(self) -> bool {
+        matches!(self, Emit::Asm | Emit::Hack)
+    }
+}
+
+pub(super) fn print_error(file_name: &Path, e: &Spanned<impl Display>) {
+    eprintln!(
+        "{file_name:?}:{}:{}: {}",
+        e.span.line, e.span.column, e.item
+    )
+}
+
+pub(super) fn exit_with(message: impl Display) -> ! {
+    eprintln!("{message}");
+    std::process::exit(1)
+}
+
+/// Writes a file, exiting if it already exists and `overwrite` is not set
+fn write(path: &Path, overwrite: bool, contents: &str) {
+    let mut file: File =
+        super::common::open_file(path, overwrite).unwrap_or_else(|e| match e.kind() {
+            ErrorKind::AlreadyExists => exit_with(format!(
+"The destination file {path:?} already exists.\nPass in a different destination file or \
+specify -o to confirm overwrite\n\n--help for more info"
+            )),
+            _ => panic!("{e:?}"),
+        });
+    file.write_all(contents.as_bytes())
+        .expect("Failed to produce output for an unknown reason");
+}
+
+/// The Jack files to compile, which are either the given file or those in the given directory
+pub(super) fn sources(path: &Path) -> Vec<PathBuf> {
+    if !path.is_dir() {
+        return vec![path.to_path_buf()];
+    }
+    let sources =
+        jack_files(path).unwrap_or_else(|e| exit_with(format!("Could not read {path:?}: {e}")));
+    if sources.is_empty() {
+        exit_with(format!("There are no .jack files in {path:?}"));
+    }
+    sources
+}
+
+/// The .jack files in a directory, in order
+pub(super) fn jack_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = fs::read_dir(dir)?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "jack"))
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
+
+/// Reads the Jack files to compile, naming each after its path
+pub(super) fn read_sources(sources: &[PathBuf]) -> Vec<SourceFile> {
+    sources
+        .iter()
+        .map(|source| SourceFile {
+            name: source.to_string_lossy().into_owned(),
+            text: fs::read_to_string(source)
+                .unwrap_or_else(|_| exit_with(format!("File not found: {source:?}"))),
+        })
+        .collect()
+}
+
+/// The classes a program may call without compiling them. A single file is checked along with
+/// the other classes in its directory, so that it can call them.
+pub(super) fn library(sources: &[PathBuf]) -> Vec<SourceFile> {
+    let [source] = sources else {
+        return Vec::new();
+    };
+    let dir = source.parent().filter(|dir| !dir.as_os_str().is_empty());
+    let siblings = jack_files(dir.unwrap_or(Path::new("."))).unwrap_or_default();
+    let siblings = siblings.into_iter().filter(|sibling| {
+        !fs::canonicalize(sibling)
+            .ok()
+            .zip(fs::canonicalize(source).ok())
+            .is_some_and(|(a, b)| a == b)
+    });
+    siblings
+        .filter_map(|sibling| {
+            Some(SourceFile {
+                text: fs::read_to_string(&sibling).ok()?,
+                name: sibling.to_string_lossy().into_owned(),
+            })
+        })
+        .collect()
+}
+
+/// Runs `files` through `pipeline` up to `last`, printing its diagnostics and exiting if any of
+/// them are errors
+pub(super) fn run_pipeline(pipeline: &Pipeline, files: &[SourceFile], last: Stage) -> Artifacts {
+    let (artifacts, diagnostics) = pipeline.run_until(Language::Jack, files, last);
+    diagnostics.iter().for_each(|d| eprintln!("{d}"));
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        std::process::exit(1)
+    }
+    artifacts
+}
+
+fn render_vm(commands: &[Spanned<VmCommand>]) -> String {
+    commands
+        .iter()
+        .map(|command| format!("{}\n", command.item))
+        .collect()
+}
+
+#[derive(Args)]
+pub struct Jack {
+    /// A .jack file, or a directory of them
+    file_name: PathBuf,
+    /// Where to write the output, if only one file is produced
+    dest_name: Option<PathBuf>,
+    #[clap(short, long)]
+    overwrite: bool,
+    /// The kind of file to produce. VM code is written next to each class, as the course expects.
+    #[clap(long, arg_enum, default_value = "vm")]
+    emit: Emit,
+    /// Print the size of the program after each stage
+    #[clap(long)]
+    report_size: bool,
+    /// Include the bundled Jack OS, leaving out any of its classes which the program defines. With
+    /// --emit vm, the OS's .vm files are written next to the program's sources.
+    #[clap(long)]
+    os: bool,
+    /// How much to optimize: 0 compiles like the course's compiler, 1 folds constants, turns
+    /// multiplies by powers of two into additions and removes branches which are never taken, and 2 also removes unused stores and inlines tiny functions. Above 0,
+    /// the VM code of whole-program emits is optimized as well.
+    #[clap(short = 'O', default_value = "0", possible_values = ["0", "1", "2"])]
+    opt_level: u8,
+    /// Which version of Jack the program is written in. The extended dialect adds for loops,
+    /// character literals, else if, operator precedence, const class constants, and break and
+    /// continue.
+    #[clap(long, default_value = "standard", possible_values = ["standard", "extended"])]
+    dialect: jack::Dialect,
+}
+
+impl Jack {
+    pub fn run(self) {
+        let emit = self.emit;
+        let sources = sources(&self.file_name);
+        let is_dir = self.file_name.is_dir();
+        if self.dest_name.is_some() && is_dir && !emit.whole_program() {
+            exit_with(
+                "A destination can only be given for a single .jack file, or with --emit asm or \
+                 --emit hack",
+            );
+        }
+        if self.os && !matches!(emit, Emit::Vm | Emit::Asm | Emit::Hack) {
+            exit_with("--os can only be given with --emit vm, asm or hack");
+        }
+        let per_class_dest = |source: &Path, emit: Emit| {
+            let source_name = source.file_stem().unwrap().to_string_lossy();
+            let dest_name = match self.emit.whole_program() {
+                true => None,
+                false => self.dest_name.clone(),
+            };
+            super::common::calculate_destination(
+                dest_name,
+                || source.with_file_name(emit.file_stem(&source_name)),
+                emit.extension(),
+            )
+        };
+
+        let pipeline = Pipeline {
+            dialect: self.dialect,
+            optimizations: jack::Optimizations::level(self.opt_level),
+            os: self.os,
+            translate: TranslateOptions {
+                optimizations: match self.opt_level {
+                    0 => vm::Optimizations::default(),
+                    _ => vm::Optimizations::all(),
+                },
+                ..Default::default()
+            },
+            library: library(&sources),
+        };
+        let files = read_sources(&sources);
+        // assembly is wanted even when it is too big to assemble
+        let last = match emit {
+            Emit::TokensXml | Emit::Xml => Stage::Parse,
+            Emit::Vm => Stage::Compile,
+            Emit::Asm => Stage::Translate,
+            Emit::Hack => Stage::Assemble,
+        };
+        let artifacts = run_pipeline(&pipeline, &files, last);
+
+        for (i, source) in sources.iter().enumerate() {
+            let output = match emit {
+                Emit::TokensXml => jack::tokens_xml(artifacts.tokens[i].iter().map(|t| &t.item)),
+                Emit::Xml => jack::class_xml(&artifacts.classes[i]),
+                Emit::Vm | Emit::Asm | Emit::Hack => render_vm(&artifacts.vm[i].1),
+            };
+            let emit = match emit.whole_program() {
+                true => Emit::Vm,
+                false => emit,
+            };
+            write(&per_class_dest(source, emit), self.overwrite, &output);
+        }
+        // the OS classes the program needs come after its own
+        if emit == Emit::Vm {
+            for (name, commands) in &artifacts.vm[sources.len()..] {
+                let name = Path::new(name).file_stem().unwrap().to_string_lossy();
+                write(
+                    &sources[0].with_file_name(format!("{name}.vm")),
+                    self.overwrite,
+                    &render_vm(commands),
+                );
+            }
+        }
+
+        if self.report_size {
+            let lines = files
+                .iter()
+                .map(|file| file.text.lines().count())
+                .sum::<usize>();
+            eprintln!("jack: {} files, {lines} lines", sources.len());
+            if !artifacts.vm.is_empty() {
+                let commands = artifacts.vm.iter().map(|(_, vm)| vm.len()).sum::<usize>();
+                eprintln!("vm: {commands} commands");
+            }
+            if emit.whole_program() {
+                eprintln!("asm: {} lines", artifacts.asm_text.lines().count());
+            }
+            if emit == Emit::Hack {
+                eprintln!("hack: {} instructions", artifacts.binary.len());
+            }
+        }
+        if !emit.whole_program() {
+            return;
+        }
+
+        let output = match emit {
+            Emit::Asm => artifacts.asm_text,
+            _ => artifacts.hack(),
+        };
+        let dest_name = super::common::calculate_destination(
+            self.dest_name,
+            || match is_dir {
+                // like the course's projects, a program in a directory is named after it
+                true => {
+                    let dir = fs::canonicalize(&self.file_name).unwrap_or(self.file_name.clone());
+                    dir.join(dir.file_name().unwrap_or_default())
+                }
+                false => self.file_name.clone(),
+            },
+            emit.extension(),
+        );
+        write(&dest_name, self.overwrite, &output);
+    }
+}
@@ -0,0 +1,442 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::Args;
+use n2t_jack::jack::{
+    self,
+    ast::{Class, SubroutineKind},
+    ide::{self, CompletionKind},
+};
+use n2t_jack::span::{Span, Spanned};
+use serde_json::{json, Value};
+
+use super::jack::jack_files;
+
+/// The error code for a request which the server does not handle
+const METHOD_NOT_FOUND: i64 = -32601;
+/// The error code for a request which cannot be done where it was asked for
+const REQUEST_FAILED: i64 = -32803;
+
+#[derive(Args)]
+pub struct Lsp {}
+
+impl Lsp {
+    pub fn run(self) {
+        let mut server = Server::default();
+        let stdin = io::stdin();
+        let mut input = stdin.lock();
+        while let Some(message) = read_message(&mut input) {
+            if !server.handle(message) {
+                break;
+            }
+        }
+    }
+}
+
+/// Reads a message with its `Content-Length` header, or `None` at the end of the input
+fn read_message(input: &mut impl BufRead) -> Option<Value> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let mut body = vec![0; length?];
+    input.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+fn send(message: Value) {
+    let body = message.to_string();
+    let mut stdout = io::stdout().lock();
+    write!(stdout, "Content-Length: {}\r\n\r\n{body}", body.len())
+        .and_then(|_| stdout.flush())
+        .unwrap_or_else(|_| std::process::exit(1));
+}
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = Vec::new();
+    let mut chars = path.bytes();
+    while let Some(byte) = chars.next() {
+        match byte {
+            b'%' => {
+                let hex = [chars.next()?, chars.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            byte => bytes.push(byte),
+        }
+    }
+    Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = "file://".to_string();
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'.' | b'_' | b'-' | b'~' => {
+                uri.push(byte as char)
+            }
+            byte => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    uri
+}
+
+/// The line and UTF-16 column of a byte offset, which is how editors count
+fn position(source: &str, offset: usize) -> Value {
+    let before = &source[..offset.min(source.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].encode_utf16().count(),
+    })
+}
+
+fn range(source: &str, span: Span) -> Value {
+    json!({
+        "start": position(source, span.offset),
+        "end": position(source, span.offset + span.len),
+    })
+}
+
+/// The byte offset of an editor's position
+fn offset(source: &str, position: &Value) -> usize {
+    let line = position["line"].as_u64().unwrap_or_default() as usize;
+    let character = position["character"].as_u64().unwrap_or_default() as usize;
+    let line_start = match line {
+        0 => 0,
+        _ => source
+            .match_indices('\n')
+            .nth(line - 1)
+            .map_or(source.len(), |(i, _)| i + 1),
+    };
+    let mut units = 0;
+    for (i, c) in source[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    source.len()
+}
+
+/// The code and message of an error answering a request
+type Error = (i64, String);
+
+type Response = Result<Value, Error>;
+
+/// The .jack files in the directory of the file being edited
+type Project = Vec<(PathBuf, File)>;
+
+/// A .jack file of the project being edited
+struct File {
+    uri: String,
+    source: String,
+    class: Option<Class>,
+    syntax_errors: Vec<Spanned<jack::SyntaxError>>,
+}
+
+#[derive(Default)]
+struct Server {
+    /// The text of the files open in the editor, which may not have been saved
+    documents: HashMap<PathBuf, (String, String)>,
+    /// The last class each open file parsed to, which completion falls back on while the code
+    /// being typed is incomplete
+    last_class: HashMap<PathBuf, Class>,
+}
+
+impl Server {
+    /// Handles a message, returning `false` once the client has asked the server to exit
+    fn handle(&mut self, message: Value) -> bool {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let id = message.get("id").cloned();
+        let result = match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": { "triggerCharacters": ["."] },
+                    "renameProvider": true,
+                },
+                "serverInfo": { "name": "n2tcc" },
+            })),
+            "shutdown" => Ok(Value::Null),
+            "exit" => return false,
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                self.open(document["uri"].as_str(), document["text"].as_str());
+                return true;
+            }
+            "textDocument/didChange" => {
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+                self.open(params["textDocument"]["uri"].as_str(), text);
+                return true;
+            }
+            "textDocument/didClose" => {
+                if let Some(path) = params["textDocument"]["uri"].as_str().and_then(uri_to_path) {
+                    self.documents.remove(&path);
+                    self.last_class.remove(&path);
+                }
+                return true;
+            }
+            "textDocument/definition" => self.definition(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/completion" => self.completion(params),
+            "textDocument/rename" => self.rename(params),
+            _ if id.is_none() => return true,
+            _ => Err((METHOD_NOT_FOUND, format!("{method} is not supported"))),
+        };
+
+        if let Some(id) = id {
+            send(match result {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err((code, message)) => json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": code, "message": message },
+                }),
+            });
+        }
+        true
+    }
+
+    fn open(&mut self, uri: Option<&str>, text: Option<&str>) {
+        let (Some(uri), Some(text)) = (uri, text) else {
+            return;
+        };
+        let Some(path) = uri_to_path(uri) else {
+            return;
+        };
+        self.documents
+            .insert(path.clone(), (uri.to_string(), text.to_string()));
+        if let Some(class) = jack::parse(text).0 {
+            self.last_class.insert(path.clone(), class);
+        }
+        self.publish_diagnostics(&path);
+    }
+
+    /// Reads every .jack file in the directory of `path`, preferring the editor's text of files
+    /// which are open
+    fn project(&self, path: &Path) -> Project {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let mut paths = jack_files(dir).unwrap_or_default();
+        let open = self
+            .documents
+            .keys()
+            .filter(|open| open.parent() == Some(dir));
+        paths.extend(open.cloned());
+        paths.sort();
+        paths.dedup();
+
+        paths
+            .into_iter()
+            .filter_map(|path| {
+                let (uri, source) = match self.documents.get(&path) {
+                    Some((uri, text)) => (uri.clone(), text.clone()),
+                    None => (path_to_uri(&path), fs::read_to_string(&path).ok()?),
+                };
+                let (class, syntax_errors) = jack::parse(&source);
+                let file = File {
+                    uri,
+                    source,
+                    class,
+                    syntax_errors,
+                };
+                Some((path, file))
+            })
+            .collect()
+    }
+
+    /// Sends the errors and warnings of every open file in the same project as `path`, since
+    /// changing one class can break the classes which use it
+    fn publish_diagnostics(&self, path: &Path) {
+        let project = self.project(path);
+        let valid = project
+            .iter()
+            .filter(|(_, file)| file.syntax_errors.is_empty())
+            .filter_map(|(_, file)| file.class.clone())
+            .collect::<Vec<_>>();
+        let mut semantic_errors = jack::check(&valid).into_iter();
+
+        for (path, file) in &project {
+            let mut diagnostics = Vec::new();
+            let mut add = |span: Span, severity: u8, message: String, code: Option<&str>| {
+                let mut diagnostic = json!({
+                    "range": range(&file.source, span),
+                    "severity": severity,
+                    "source": "jack",
+                    "message": message,
+                });
+                if let Some(code) = code {
+                    diagnostic["code"] = code.into();
+                }
+                diagnostics.push(diagnostic);
+            };
+            match (&file.class, file.syntax_errors.is_empty()) {
+                (Some(class), true) => {
+                    for e in semantic_errors.next().unwrap_or_default() {
+                        add(e.span, 1, e.item.to_string(), None);
+                    }
+                    for w in jack::warnings(&file.source, class) {
+                        add(w.span, 2, w.item.to_string(), Some(w.item.code()));
+                    }
+                }
+                _ => {
+                    for e in &file.syntax_errors {
+                        add(e.span, 1, e.item.to_string(), None);
+                    }
+                }
+            }
+
+            if self.documents.contains_key(path) {
+                send(json!({
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/publishDiagnostics",
+                    "params": { "uri": file.uri, "diagnostics": diagnostics },
+                }));
+            }
+        }
+    }
+
+    /// The project of the document a request is about, the index of the document in it, and the
+    /// byte offset the request asks about
+    fn locate(&self, params: &Value) -> Result<(Project, usize, usize), Error> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let path = uri_to_path(uri).ok_or((REQUEST_FAILED, format!("{uri} is not a file")))?;
+        let project = self.project(&path);
+        let index = project
+            .iter()
+            .position(|(other, _)| *other == path)
+            .ok_or((REQUEST_FAILED, format!("{uri} could not be read")))?;
+        let offset = offset(&project[index].1.source, &params["position"]);
+        Ok((project, index, offset))
+    }
+
+    /// The class of a file, or the last one it parsed to if it is in the middle of being edited
+    fn class<'a>(&'a self, path: &Path, file: &'a File) -> Option<&'a Class> {
+        file.class.as_ref().or_else(|| self.last_class.get(path))
+    }
+
+    /// The classes of a project and the OS
+    fn classes(project: &Project) -> Vec<Class> {
+        let classes = project
+            .iter()
+            .filter_map(|(_, file)| file.class.clone())
+            .collect::<Vec<_>>();
+        ide::with_os(&classes)
+    }
+
+    /// The name a request is about, along with the project and the index of its file
+    fn target(&self, params: &Value) -> Result<Option<(Project, usize, ide::Reference)>, Error> {
+        let (project, index, offset) = self.locate(params)?;
+        let Some(class) = &project[index].1.class else {
+            return Ok(None);
+        };
+        let references = ide::references(class);
+        let reference = ide::reference_at(&references, offset).cloned();
+        Ok(reference.map(|reference| (project, index, reference)))
+    }
+
+    fn definition(&self, params: &Value) -> Response {
+        let Some((project, index, reference)) = self.target(params)? else {
+            return Ok(Value::Null);
+        };
+        let file = &project[index].1;
+        let class = file.class.as_ref().unwrap();
+        let classes = Self::classes(&project);
+        let Some((class_name, span)) = ide::definition(&reference.target, class, &classes) else {
+            return Ok(Value::Null);
+        };
+        // the OS's classes have no file to go to
+        let defining = project.iter().find(|(_, file)| {
+            file.class
+                .as_ref()
+                .is_some_and(|class| class.name.item == class_name)
+        });
+        Ok(match defining {
+            Some((_, file)) => json!({ "uri": file.uri, "range": range(&file.source, span) }),
+            None => Value::Null,
+        })
+    }
+
+    fn hover(&self, params: &Value) -> Response {
+        let Some((project, index, reference)) = self.target(params)? else {
+            return Ok(Value::Null);
+        };
+        let file = &project[index].1;
+        let classes = Self::classes(&project);
+        Ok(match ide::describe(&reference.target, &classes) {
+            Some(description) => json!({
+                "contents": { "kind": "markdown", "value": format!("```jack\n{description}\n```") },
+                "range": range(&file.source, reference.span),
+            }),
+            None => Value::Null,
+        })
+    }
+
+    fn completion(&self, params: &Value) -> Response {
+        let (project, index, offset) = self.locate(params)?;
+        let (path, file) = &project[index];
+        let Some(class) = self.class(path, file) else {
+            return Ok(Value::Null);
+        };
+        let classes = Self::classes(&project);
+        let items = ide::completions(&classes, class, &file.source, offset)
+            .into_iter()
+            .map(|completion| {
+                // the numbers of the LSP's CompletionItemKind
+                let kind = match completion.kind {
+                    CompletionKind::Class => 7,
+                    CompletionKind::Subroutine(SubroutineKind::Method) => 2,
+                    CompletionKind::Subroutine(SubroutineKind::Function) => 3,
+                    CompletionKind::Subroutine(SubroutineKind::Constructor) => 4,
+                    CompletionKind::Variable => 6,
+                };
+                json!({ "label": completion.label, "kind": kind, "detail": completion.detail })
+            });
+        Ok(Value::Array(items.collect()))
+    }
+
+    fn rename(&self, params: &Value) -> Response {
+        let new_name = params["newName"].as_str().unwrap_or_default();
+        let valid = new_name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && new_name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err((REQUEST_FAILED, format!("`{new_name}` is not a valid name")));
+        }
+        let not_renamable = || {
+            (
+                REQUEST_FAILED,
+                "Only local variables and arguments can be renamed".to_string(),
+            )
+        };
+        let (project, index, reference) = self.target(params)?.ok_or_else(not_renamable)?;
+        let file = &project[index].1;
+        let references = ide::references(file.class.as_ref().unwrap());
+        let spans = ide::rename(&references, &reference.target).ok_or_else(not_renamable)?;
+        let edits = spans
+            .into_iter()
+            .map(|span| json!({ "range": range(&file.source, span), "newText": new_name }));
+        Ok(json!({ "changes": { file.uri.clone(): edits.collect::<Vec<_>>() } }))
+    }
+}
@@ -1,9 +1,12 @@
-#![feature(iterator_try_collect)]
-
 extern crate core;
 
 mod asm;
 mod common;
+mod debug;
+mod fmt;
+mod jack;
+mod lsp;
+mod test;
 mod vm;
 
 use clap::{Parser, Subcommand};
@@ -20,6 +23,16 @@ struct Opt {
 enum Language {
     Asm(asm::Asm),
     Vm(vm::Vm),
+    /// Compile Jack programs
+    Jack(jack::Jack),
+    /// Step through a Jack program, with breakpoints on its lines
+    Debug(debug::Debug),
+    /// Rewrite source files in the canonical style
+    Fmt(fmt::Fmt),
+    /// Run a language server for Jack, which editors talk to over standard input and output
+    Lsp(lsp::Lsp),
+    /// Run a test script, writing its output and checking it against its comparison file
+    Test(test::Test),
 }
 
 impl Opt {
@@ -27,6 +40,11 @@ impl Opt {
         match self.subcommand {
             Language::Asm(asm) => asm.run(),
             Language::Vm(vm) => vm.run(),
+            Language::Jack(jack) => jack.run(),
+            Language::Debug(debug) => debug.run(),
+            Language::Fmt(fmt) => fmt.run(),
+            Language::Lsp(lsp) => lsp.run(),
+            Language::Test(test) => test.run(),
         }
     }
 }
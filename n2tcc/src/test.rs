@@ -0,0 +1,117 @@
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::{ArgEnum, Args};
+use n2t_hdl::prelude::ChipBuilder;
+use n2t_jack::script::{self, CpuTarget, VmTarget};
+
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq)]
+pub enum TargetKind {
+    /// Interpret VM code directly
+    Vm,
+    /// Run Hack code on the CPU emulator, translating VM code first
+    Cpu,
+}
+
+#[derive(Args)]
+pub struct Test {
+    /// The script to run. Scripts which load an HDL file simulate the chip they load.
+    script: PathBuf,
+    /// What to run the script on. Defaults to vm for scripts whose names end in VME, like the
+    /// course's VM emulator scripts, and cpu otherwise.
+    #[clap(long, arg_enum)]
+    target: Option<TargetKind>,
+    /// Directories to search for chips which are not next to an HDL script
+    #[clap(long, short = 'L')]
+    library: Vec<PathBuf>,
+}
+
+/// Prints the echoes of a finished script, and whether its output was compared
+fn report(result: Result<(&[String], bool), impl Display>) {
+    match result {
+        Ok((echoes, compared)) => {
+            echoes.iter().for_each(|echo| println!("{echo}"));
+            if compared {
+                println!("End of script - Comparison ended successfully");
+            } else {
+                println!("End of script");
+            }
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1)
+        }
+    }
+}
+
+/// The file loaded by the first `load` command of a script, skipping comments
+fn first_load(script: &str) -> Option<&str> {
+    let mut rest = script;
+    let mut words = std::iter::from_fn(|| loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',' || c == ';');
+        if let Some(comment) = rest.strip_prefix("//") {
+            rest = comment.split_once('\n').map_or("", |(_, after)| after);
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment.split_once("*/").map_or("", |(_, after)| after);
+        } else if rest.is_empty() {
+            return None;
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || c == ',' || c == ';')
+                .unwrap_or(rest.len());
+            let (word, after) = rest.split_at(end);
+            rest = after;
+            return Some(word);
+        }
+    });
+    words.find(|&word| word == "load")?;
+    words.next()
+}
+
+impl Test {
+    pub fn run(self) {
+        let script = fs::read_to_string(&self.script).unwrap_or_default();
+        let hdl = first_load(&script)
+            .and_then(|file| Path::new(file).extension())
+            .is_some_and(|extension| extension == "hdl");
+        if hdl {
+            if self.target.is_some() {
+                eprintln!("--target cannot be given for scripts which load an HDL file");
+                std::process::exit(1)
+            }
+            let mut builder = ChipBuilder::new();
+            builder.with_builtins();
+            return report(
+                n2t_hdl::script::run_file(&self.script, builder, &self.library)
+                    .as_ref()
+                    .map(|runner| (runner.echoes(), runner.compared())),
+            );
+        }
+
+        let target = self.target.unwrap_or_else(|| {
+            let stem = self
+                .script
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy();
+            match stem.ends_with("VME") {
+                true => TargetKind::Vm,
+                false => TargetKind::Cpu,
+            }
+        });
+
+        match target {
+            TargetKind::Vm => report(
+                script::run_file(&self.script, VmTarget::default())
+                    .as_ref()
+                    .map(|runner| (runner.echoes(), runner.compared())),
+            ),
+            TargetKind::Cpu => report(
+                script::run_file(&self.script, CpuTarget::default())
+                    .as_ref()
+                    .map(|runner| (runner.echoes(), runner.compared())),
+            ),
+        }
+    }
+}
@@ -1,14 +1,49 @@
 use std::{
     fs,
     io::{ErrorKind, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
-use clap::Args;
-use n2t_asm::{
-    assemble::SymbolTable,
-    parse::{Item, Program},
-};
+use clap::{ArgEnum, Args};
+use n2t_jack::pipeline::{Language, Pipeline, Severity, SourceFile, Stage};
+use n2t_jack::translate::TranslateOptions;
+use n2t_jack::vm::{Optimizations, VmConfig};
+
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    Hack,
+    Asm,
+}
+
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    All,
+    FoldConstants,
+    DirectMoves,
+    RemoveDeadCode,
+    SmallConstants,
+}
+
+impl Pass {
+    fn enable(self, optimizations: &mut Optimizations) {
+        match self {
+            Pass::All => *optimizations = Optimizations::all(),
+            Pass::FoldConstants => optimizations.fold_constants = true,
+            Pass::DirectMoves => optimizations.direct_moves = true,
+            Pass::RemoveDeadCode => optimizations.remove_dead_code = true,
+            Pass::SmallConstants => optimizations.small_constants = true,
+        }
+    }
+}
+
+impl Emit {
+    fn extension(self) -> &'static str {
+        match self {
+            Emit::Hack => "hack",
+            Emit::Asm => "asm",
+        }
+    }
+}
 
 #[derive(Args)]
 pub struct Vm {
@@ -18,6 +53,72 @@ pub struct Vm {
     overwrite: bool,
     #[clap(short, long)]
     debug: bool,
+    /// Leave the results of eq, gt, and lt in the high bit instead of pushing -1 or 0
+    #[clap(long)]
+    high_bit_comparisons: bool,
+    /// The kind of file to produce
+    #[clap(long, arg_enum, default_value = "hack")]
+    emit: Emit,
+    /// Optimization passes to run before translation, separated by commas
+    #[clap(long, arg_enum, use_value_delimiter = true)]
+    optimize: Vec<Pass>,
+    /// Emit eq, gt, lt, call, and return once as shared routines rather than at every use
+    #[clap(long)]
+    shared_routines: bool,
+    /// Print the size of the translated code with and without shared routines
+    #[clap(long)]
+    report_size: bool,
+    /// Write a map from ROM addresses to VM lines next to the output, as JSON
+    #[clap(long)]
+    source_map: bool,
+    /// Leave out the code which sets up the stack and calls Sys.init
+    #[clap(long)]
+    no_bootstrap: bool,
+    /// The address the stack starts at
+    #[clap(long, default_value = "256")]
+    stack_base: u16,
+    /// Check the program, along with the other .vm files in its directory, for mistakes such as
+    /// undefined functions and unbalanced stacks before translating it
+    #[clap(long)]
+    lint: bool,
+}
+
+/// Prints every problem in the .vm files of the directory holding `file_name`, exiting if there
+/// are any
+fn lint(file_name: &Path, config: &VmConfig) {
+    let dir = match file_name.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut paths = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "vm"))
+        .collect::<Vec<_>>();
+    if !paths.iter().any(|path| path.file_name() == file_name.file_name()) {
+        paths.push(file_name.to_path_buf());
+    }
+    paths.sort();
+
+    let files = paths
+        .iter()
+        .map(|path| {
+            let source = fs::read_to_string(path).unwrap_or_else(|_| {
+                eprintln!("File not found: {path:?}");
+                std::process::exit(1)
+            });
+            (path.to_string_lossy().into_owned(), source)
+        })
+        .collect::<Vec<_>>();
+    let diagnostics = n2t_jack::vm::lint(
+        files.iter().map(|(name, source)| (name.as_str(), source.as_str())),
+        config,
+    );
+    diagnostics.iter().for_each(|d| eprintln!("{d}"));
+    if !diagnostics.is_empty() {
+        std::process::exit(1)
+    }
 }
 
 impl Vm {
@@ -27,12 +128,73 @@ impl Vm {
         let source_name = file_name.file_stem().unwrap().to_string_lossy();
         let source_dir = file_name.parent().unwrap();
 
-        // if not provided, default destination name should be the same as source name, but .hack
+        // if not provided, default destination name should be the same as source name, but with
+        // the extension of the emitted file
         let dest_name = super::common::calculate_destination(
             self.dest_name,
             || source_dir.join(PathBuf::from(source_name.to_string())),
-            "hack",
+            self.emit.extension(),
         );
+        let map_name = dest_name.with_extension("map.json");
+        let config = VmConfig {
+            stack_base: self.stack_base,
+            bootstrap: !self.no_bootstrap,
+            ..Default::default()
+        };
+        if self.lint {
+            lint(&file_name, &config);
+        }
+
+        // read source file
+        let file = fs::read_to_string(&file_name).unwrap_or_else(|_| {
+            eprintln!("File not found: {file_name:?}");
+            std::process::exit(1)
+        });
+        let mut optimizations = Optimizations::default();
+        self.optimize
+            .iter()
+            .for_each(|pass| pass.enable(&mut optimizations));
+        let options = TranslateOptions {
+            high_bit_comparisons: self.high_bit_comparisons,
+            optimizations,
+            shared_routines: self.shared_routines,
+            config,
+        };
+
+        if self.report_size {
+            let size = |shared_routines| {
+                let options = TranslateOptions {
+                    shared_routines,
+                    ..options.clone()
+                };
+                n2t_jack::translate::code_size(&file, options).unwrap_or_else(|e| {
+                    let name = file_name.to_string_lossy();
+                    eprintln!("{name}:{}:{}: {}", e.span.line, e.span.column, e.item);
+                    std::process::exit(1)
+                })
+            };
+            eprintln!("inline routines: {} instructions", size(false));
+            eprintln!("shared routines: {} instructions", size(true));
+        }
+
+        let pipeline = Pipeline {
+            translate: options,
+            ..Default::default()
+        };
+        let source = SourceFile {
+            name: file_name.to_string_lossy().into_owned(),
+            text: file,
+        };
+        // assembly is wanted even when it is too big to assemble
+        let last = match self.emit {
+            Emit::Asm => Stage::Translate,
+            Emit::Hack => Stage::Assemble,
+        };
+        let (artifacts, diagnostics) = pipeline.run_until(Language::Vm, &[source], last);
+        diagnostics.iter().for_each(|d| eprintln!("{d}"));
+        if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+            std::process::exit(1)
+        }
 
         // open destination file or create it if appropriate
         let mut dest_file =
@@ -49,22 +211,20 @@ specify -o to confirm overwrite\n\n--help for more info"
                 }
             });
 
-        // read source file
-        let file = fs::read_to_string(&file_name).unwrap_or_else(|_| {
-            eprintln!("File not found: {file_name:?}");
-            std::process::exit(1)
-        });
-        let program = Program(
-            n2t_jack::translate::translate(&file)
-                .filter_map(|it| match it {
-                    Ok(Item::Instruction(x)) => Some(Ok(x)),
-                    Ok(_) => None,
-                    Err(x) => Some(Err(x)),
-                })
-                .try_collect()
-                .unwrap(),
-        );
-        let code = n2t_asm::assemble::to_string(&mut SymbolTable::new(), &program);
+        if self.source_map {
+            let map_file =
+                super::common::open_file(&map_name, self.overwrite).unwrap_or_else(|e| {
+                    eprintln!("Could not create {map_name:?}: {e}");
+                    std::process::exit(1)
+                });
+            serde_json::to_writer_pretty(map_file, &artifacts.source_map)
+                .expect("Failed to write the source map for an unknown reason");
+        }
+
+        let code = match self.emit {
+            Emit::Asm => artifacts.asm_text,
+            Emit::Hack => artifacts.hack(),
+        };
 
         dest_file
             .write_all(code.as_bytes())